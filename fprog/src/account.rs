@@ -0,0 +1,328 @@
+use arrayref::array_ref;
+use md::data::{
+    deserialize_account_data_up_to, AccountMetadata, ChatDeserializationError, Message,
+    CHAT_ACCOUNT_SIZE,
+};
+use solana_client::client_error::Result as ClientResult;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::error::Error;
+use std::mem;
+
+use crate::rpc::ChatRpc;
+
+// the buffer keeps messages in the order they landed and deletes only take some out, so what's
+// new is whatever is stored after the message with id after_id; content ids say nothing about
+// order. a deleted sequential after_id is placed by the sequential ids still stored, a deleted
+// content id can't be placed and everything comes back
+pub(crate) fn messages_after(messages: &[Message], after_id: Option<u32>) -> Vec<&Message> {
+    let start = match after_id {
+        None => 0,
+        Some(after_id) => match messages.iter().position(|message| message.id == after_id) {
+            Some(index) => index + 1,
+            None if after_id & Message::CONTENT_ID_FLAG == 0 => messages
+                .iter()
+                .rposition(|message| !message.has_content_id() && message.id < after_id)
+                .map_or(0, |index| index + 1),
+            None => 0,
+        },
+    };
+    messages[start..].iter().collect()
+}
+
+// what a reader has shown of an account: the messages stored up to the one with id after, then
+// the ids in seen once something was shown, which hold up while messages are deleted under it
+#[derive(Debug, Default)]
+pub struct Cursor {
+    after: Option<u32>,
+    seen: HashSet<u32>,
+}
+
+impl Cursor {
+    pub fn after(after: Option<u32>) -> Self {
+        Cursor {
+            after,
+            seen: HashSet::new(),
+        }
+    }
+
+    // everything stored already counts as shown
+    pub fn past(messages: &[Message]) -> Self {
+        Cursor {
+            after: None,
+            seen: messages.iter().map(|message| message.id).collect(),
+        }
+    }
+
+    pub fn at_start(&self) -> bool {
+        self.after.is_none() && self.seen.is_empty()
+    }
+
+    pub fn unseen<'a>(&self, messages: &'a [Message]) -> Vec<&'a Message> {
+        messages_after(messages, self.after)
+            .into_iter()
+            .filter(|message| !self.seen.contains(&message.id))
+            .collect()
+    }
+
+    // marks every message stored up to the last one shown, so a newest first page doesn't hand
+    // back the older ones it skipped on the next read
+    pub fn advance(&mut self, messages: &[Message], shown: &[&Message]) {
+        let last = messages
+            .iter()
+            .rposition(|message| shown.iter().any(|shown| shown.id == message.id));
+        if let Some(last) = last {
+            self.seen
+                .extend(messages[..=last].iter().map(|message| message.id));
+            self.after = None;
+        }
+    }
+}
+
+const U8_SIZE: usize = mem::size_of::<u8>();
+const U32_SIZE: usize = mem::size_of::<u32>();
+
+// next_free_index follows the initialized and bump bytes
+const NEXT_FREE_INDEX_OFFSET: usize = 2 * U8_SIZE;
+const LAST_MESSAGE_ID_OFFSET: usize = NEXT_FREE_INDEX_OFFSET + U32_SIZE;
+
+// reads just the header bytes up to last_message_id, enough for a poll to tell whether anything
+// new landed; None when the account is too short or not opened
+#[allow(clippy::result_large_err)]
+pub fn read_last_message_id(
+    rpc_client: &impl ChatRpc,
+    pubkey: &Pubkey,
+) -> ClientResult<Option<u32>> {
+    let end = LAST_MESSAGE_ID_OFFSET + U32_SIZE;
+    let data = rpc_client.get_account_data_slice(pubkey, 0, end)?;
+    if data.len() < end || data[0] == 0 {
+        return Ok(None);
+    }
+    Ok(Some(u32::from_le_bytes(*array_ref![
+        data,
+        LAST_MESSAGE_ID_OFFSET,
+        U32_SIZE
+    ])))
+}
+
+// reads the fixed metadata header first, then only up to next_free_index instead of the whole
+// account, the free space after the last message is never transferred
+#[allow(clippy::result_large_err)]
+pub fn read_used_data(rpc_client: &impl ChatRpc, pubkey: &Pubkey) -> ClientResult<Vec<u8>> {
+    let base_size = AccountMetadata::ACCOUNT_METADATA_BASE_SIZE;
+    let mut data = rpc_client.get_account_data_slice(pubkey, 0, base_size)?;
+    if data.len() < base_size {
+        return Ok(data);
+    }
+    let metadata_size = match AccountMetadata::calculate_size_from_buffer(&data[..]) {
+        Ok(metadata_size) => metadata_size,
+        Err(_) => return Ok(data),
+    };
+    let next_free_index =
+        u32::from_le_bytes(*array_ref![data, NEXT_FREE_INDEX_OFFSET, U32_SIZE]) as usize;
+    // a bogus next_free_index still gets the whole metadata, but never more than an account holds
+    let end = next_free_index.max(metadata_size).min(CHAT_ACCOUNT_SIZE);
+    if end > base_size {
+        let rest = rpc_client.get_account_data_slice(pubkey, base_size, end - base_size)?;
+        data.extend_from_slice(&rest);
+    }
+    Ok(data)
+}
+
+// A chat account read from chain with its metadata and messages already parsed
+#[derive(Debug)]
+pub struct ChatAccount {
+    pub pubkey: Pubkey,
+    pub metadata: AccountMetadata,
+    pub messages: Vec<Message>,
+    // parsing stopped at the message cap with bytes left, more messages may exist
+    pub truncated: bool,
+    data_len: usize,
+}
+
+impl ChatAccount {
+    pub fn load(rpc_client: &impl ChatRpc, pubkey: &Pubkey) -> Result<ChatAccount, Box<dyn Error>> {
+        let data = rpc_client.get_account_data(pubkey)?;
+        Ok(ChatAccount::from_data(*pubkey, &data[..])?)
+    }
+
+    pub fn from_data(pubkey: Pubkey, data: &[u8]) -> Result<ChatAccount, ChatDeserializationError> {
+        ChatAccount::from_data_up_to(pubkey, data, usize::MAX)
+    }
+
+    // parses at most max_messages messages, see truncated
+    pub fn from_data_up_to(
+        pubkey: Pubkey,
+        data: &[u8],
+        max_messages: usize,
+    ) -> Result<ChatAccount, ChatDeserializationError> {
+        let (metadata, messages, truncated) = deserialize_account_data_up_to(data, max_messages)?;
+        Ok(ChatAccount {
+            pubkey,
+            metadata,
+            messages: messages.unwrap_or_default(),
+            truncated,
+            data_len: data.len(),
+        })
+    }
+
+    pub fn messages_since(&self, id: Option<u32>) -> Vec<&Message> {
+        messages_after(&self.messages, id)
+    }
+
+    // bytes read from chain, less than the account holds after a sliced read
+    pub fn data_len(&self) -> usize {
+        self.data_len
+    }
+
+    pub fn name(&self) -> &str {
+        &self.metadata.account_name
+    }
+
+    // full once not even a message with an empty body fits after the last one
+    pub fn is_full(&self) -> bool {
+        let free = self
+            .data_len
+            .saturating_sub(self.metadata.next_free_index as usize);
+        free < Message::MESSAGE_BASE_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::{serialize_messages, AccountMetadata, ChatData, Message};
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::rpc::fake::FakeRpc;
+
+    use super::{messages_after, read_last_message_id, read_used_data, ChatAccount, Cursor};
+
+    fn account_data(messages: &[Message], data_len: usize) -> Vec<u8> {
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let metadata_size = account_metadata.size();
+        let messages_size: usize = messages.iter().map(|message| message.size()).sum();
+        account_metadata.next_free_index = (metadata_size + messages_size) as u32;
+        account_metadata.message_count = messages.len() as u32;
+        let mut data = vec![0; data_len];
+        account_metadata
+            .serialize(&mut data[..metadata_size])
+            .unwrap();
+        serialize_messages(messages, &mut data[metadata_size..]).unwrap();
+        data
+    }
+
+    #[test]
+    fn chat_account_from_buffer() {
+        let pubkey = Pubkey::new_unique();
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = (1..=3)
+            .map(|id| Message::new(id, sender, id.to_string()))
+            .collect();
+
+        let chat_account = ChatAccount::from_data(pubkey, &account_data(&messages, 1024)).unwrap();
+        assert_eq!(chat_account.pubkey, pubkey);
+        assert_eq!(chat_account.name(), "abc");
+        assert_eq!(chat_account.messages, messages);
+        assert_eq!(
+            chat_account
+                .messages_since(Some(1))
+                .iter()
+                .map(|message| message.id)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert!(!chat_account.is_full());
+
+        let used = chat_account.metadata.next_free_index as usize;
+        let full = ChatAccount::from_data(pubkey, &account_data(&messages, used)).unwrap();
+        assert!(full.is_full());
+    }
+
+    #[test]
+    fn cursor_follows_storage_order() {
+        let sender = Pubkey::new_unique();
+        let content_id = Message::CONTENT_ID_FLAG | 7;
+        let mut messages: Vec<Message> = [2, content_id, 3]
+            .iter()
+            .map(|id| Message::new(*id, sender, id.to_string()))
+            .collect();
+        let ids = |messages: Vec<&Message>| -> Vec<u32> {
+            messages.iter().map(|message| message.id).collect()
+        };
+
+        // a content id sorts above everything sequential but was stored in the middle
+        assert_eq!(ids(messages_after(&messages, Some(2))), vec![content_id, 3]);
+        assert_eq!(ids(messages_after(&messages, Some(content_id))), vec![3]);
+        // a deleted sequential cursor still lands between the ids around it
+        assert_eq!(
+            ids(messages_after(&messages, Some(1))),
+            vec![2, content_id, 3]
+        );
+
+        let mut cursor = Cursor::after(Some(2));
+        let shown = cursor.unseen(&messages);
+        assert_eq!(ids(shown.clone()), vec![content_id, 3]);
+        cursor.advance(&messages, &shown);
+        // the cursor message going away doesn't bring back what was already shown
+        messages.remove(0);
+        messages.push(Message::new(4, sender, "4".to_string()));
+        assert_eq!(ids(cursor.unseen(&messages)), vec![4]);
+
+        let cursor = Cursor::past(&messages);
+        assert!(cursor.unseen(&messages).is_empty());
+    }
+
+    #[test]
+    fn sliced_read_stops_at_next_free_index() {
+        let pubkey = Pubkey::new_unique();
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = (1..=3)
+            .map(|id| Message::new(id, sender, id.to_string()))
+            .collect();
+        let data = account_data(&messages, 1024);
+        let full = ChatAccount::from_data(pubkey, &data).unwrap();
+        let used = full.metadata.next_free_index as usize;
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(pubkey, 0, data);
+
+        let sliced = read_used_data(&rpc, &pubkey).unwrap();
+
+        let base_size = AccountMetadata::ACCOUNT_METADATA_BASE_SIZE;
+        assert_eq!(
+            *rpc.slices.borrow(),
+            vec![
+                (pubkey, 0, base_size),
+                (pubkey, base_size, used - base_size)
+            ]
+        );
+        assert_eq!(sliced.len(), used);
+        let chat_account = ChatAccount::from_data(pubkey, &sliced).unwrap();
+        assert_eq!(chat_account.messages, messages);
+    }
+
+    #[test]
+    fn last_message_id_reads_only_the_header() {
+        let pubkey = Pubkey::new_unique();
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = (1..=3)
+            .map(|id| Message::new(id, sender, id.to_string()))
+            .collect();
+        let mut data = account_data(&messages, 1024);
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        account_metadata.deserialize(&data).unwrap();
+        account_metadata.last_message_id = 3;
+        let metadata_size = account_metadata.size();
+        account_metadata
+            .serialize(&mut data[..metadata_size])
+            .unwrap();
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(pubkey, 0, data);
+
+        assert_eq!(read_last_message_id(&rpc, &pubkey).unwrap(), Some(3));
+        assert_eq!(*rpc.slices.borrow(), vec![(pubkey, 0, 10)]);
+
+        let unopened = Pubkey::new_unique();
+        rpc.add_account(unopened, 0, vec![0; 1024]);
+        assert_eq!(read_last_message_id(&rpc, &unopened).unwrap(), None);
+    }
+}
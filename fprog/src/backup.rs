@@ -0,0 +1,263 @@
+use log::warn;
+use md::data::{deserialize_account_data_sorted, ChatDeserializationError};
+use md::error::ChatError;
+use solana_sdk::pubkey::{Pubkey, PUBKEY_BYTES};
+use solana_sdk::signer::keypair::Keypair;
+use solana_sdk::signer::Signer;
+use std::error::Error;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::Path;
+
+use fprog::instructions::AddressStrategy;
+
+use crate::chat::{
+    account_exists, chat_account_to_read, infer_chat_account_pubkey, open_account,
+    send_messages_batched, ComputeBudget,
+};
+use crate::rpc::ChatRpc;
+
+const BACKUP_MAGIC: &[u8; 8] = b"fprogbak";
+const BACKUP_HEADER_SIZE: usize = BACKUP_MAGIC.len() + 2 * PUBKEY_BYTES;
+
+// the raw account bytes behind a header naming the account and the program that owned it
+#[derive(Debug, PartialEq)]
+pub struct Backup {
+    pub account: Pubkey,
+    pub program_id: Pubkey,
+    pub data: Vec<u8>,
+}
+
+impl Backup {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(BACKUP_HEADER_SIZE + self.data.len());
+        bytes.extend_from_slice(BACKUP_MAGIC);
+        bytes.extend_from_slice(self.account.as_ref());
+        bytes.extend_from_slice(self.program_id.as_ref());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Backup, ChatDeserializationError> {
+        if bytes.len() < BACKUP_HEADER_SIZE || &bytes[..BACKUP_MAGIC.len()] != BACKUP_MAGIC {
+            return Err(ChatDeserializationError);
+        }
+        let account_start = BACKUP_MAGIC.len();
+        let program_start = account_start + PUBKEY_BYTES;
+        Ok(Backup {
+            account: Pubkey::try_from(&bytes[account_start..program_start])
+                .map_err(|_| ChatDeserializationError)?,
+            program_id: Pubkey::try_from(&bytes[program_start..BACKUP_HEADER_SIZE])
+                .map_err(|_| ChatDeserializationError)?,
+            data: bytes[BACKUP_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+pub fn backup(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    owner_pubkey: Option<&Pubkey>,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let account = chat_account_to_read(&from_user.pubkey(), owner_pubkey, program_id, strategy)?;
+    let data = rpc_client.get_account_data(&account)?;
+    // refuse to save something restore couldn't read back
+    deserialize_account_data_sorted(&data[..])?;
+    let backup = Backup {
+        account,
+        program_id: *program_id,
+        data,
+    };
+    fs::write(path, backup.encode())?;
+    println!(
+        "Backed up {} bytes of {} to {}",
+        backup.data.len(),
+        account,
+        path.display()
+    );
+    Ok(())
+}
+
+// a PDA can't be written to directly, so restore opens the caller's account under strategy and
+// sends the backed up messages into it in id order; the program assigns new ids and timestamps,
+// and blocked senders and reactions aren't carried over
+pub fn restore(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    path: &Path,
+    compute_budget: &ComputeBudget,
+    blockhash_refresh_every: Option<NonZeroUsize>,
+) -> Result<(), Box<dyn Error>> {
+    let backup = Backup::decode(&fs::read(path)?)?;
+    if backup.program_id != *program_id {
+        warn!(
+            "Backup of {} was taken under program {}, restoring under {}",
+            backup.account, backup.program_id, program_id
+        );
+    }
+    let (account_metadata, messages) = deserialize_account_data_sorted(&backup.data[..])?;
+    let messages = messages.unwrap_or_default();
+    let count = messages.len();
+
+    let chat_pk = infer_chat_account_pubkey(&from_user.pubkey(), program_id, strategy)?;
+    if account_exists(rpc_client, &chat_pk)? {
+        return Err(Box::new(ChatError::AccountAlreadyInitialized));
+    }
+    open_account(
+        rpc_client,
+        program_id,
+        strategy,
+        from_user,
+        None,
+        &account_metadata.account_name,
+        None,
+        compute_budget,
+    )?;
+    if count > 0 {
+        send_messages_batched(
+            rpc_client,
+            program_id,
+            from_user,
+            &chat_pk,
+            messages,
+            compute_budget,
+            blockhash_refresh_every,
+        )?;
+    }
+    println!(
+        "Restored {} messages of {} into {}",
+        count, backup.account, chat_pk
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::{serialize_messages, AccountMetadata, ChatData, ChatInstruction, Message};
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signer::keypair::Keypair;
+    use solana_sdk::signer::Signer;
+
+    use fprog::instructions::{find_chat_account_pda, AddressStrategy};
+
+    use crate::chat::ComputeBudget;
+    use crate::rpc::fake::FakeRpc;
+
+    use super::{backup, restore, Backup};
+
+    #[test]
+    fn backup_restores_into_a_fresh_account() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let alice = Pubkey::new_unique();
+        let (source_pk, bump) = find_chat_account_pda(&from_user.pubkey(), &program_id, "work");
+        let restored = AddressStrategy::Pda("restored".to_string());
+        let (restored_pk, restored_bump) =
+            find_chat_account_pda(&from_user.pubkey(), &program_id, "restored");
+
+        // stored out of id order, as a delete and resend can leave them
+        let messages = vec![
+            Message::new(2, alice, "there".to_string()),
+            Message::new(1, alice, "hi".to_string()),
+        ];
+        let mut account_metadata = AccountMetadata::with_seed("abc", "work", bump).unwrap();
+        let metadata_size = account_metadata.size();
+        let messages_size: usize = messages.iter().map(Message::size).sum();
+        account_metadata.next_free_index = (metadata_size + messages_size) as u32;
+        account_metadata.message_count = 2;
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..metadata_size])
+            .unwrap();
+        serialize_messages(&messages, &mut data[metadata_size..]).unwrap();
+
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(from_user.pubkey(), 1_000_000, vec![]);
+        rpc.add_account(source_pk, 0, data.clone());
+
+        let path = std::env::temp_dir().join(format!("fprog-backup-{}.bin", source_pk));
+        backup(
+            &rpc,
+            &program_id,
+            &AddressStrategy::Pda("work".to_string()),
+            &from_user,
+            None,
+            &path,
+        )
+        .unwrap();
+        let saved = Backup::decode(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(
+            saved,
+            Backup {
+                account: source_pk,
+                program_id,
+                data,
+            }
+        );
+
+        // missing for restore's and open_account's checks, there once the open lands
+        let opened = AccountMetadata::with_seed("abc", "restored", restored_bump).unwrap();
+        let mut opened_data = vec![0; 1024];
+        opened.serialize(&mut opened_data[..opened.size()]).unwrap();
+        rpc.add_account(restored_pk, 0, opened_data);
+        rpc.hidden_reads.borrow_mut().insert(restored_pk, 2);
+
+        restore(
+            &rpc,
+            &program_id,
+            &restored,
+            &from_user,
+            &path,
+            &ComputeBudget::default(),
+            None,
+        )
+        .unwrap();
+
+        let sent: Vec<ChatInstruction> = rpc
+            .sent
+            .borrow()
+            .iter()
+            .map(|transaction| {
+                ChatInstruction::deserialize(
+                    &transaction.message.instructions.last().unwrap().data[..],
+                )
+                .unwrap()
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                ChatInstruction::OpenAccount {
+                    account_metadata: AccountMetadata {
+                        initialized: 0,
+                        ..opened
+                    },
+                },
+                ChatInstruction::SendMessages {
+                    messages: vec![
+                        Message::new(1, alice, "hi".to_string()),
+                        Message::new(2, alice, "there".to_string()),
+                    ],
+                },
+            ]
+        );
+
+        // an account that's already there is never written over
+        assert!(restore(
+            &rpc,
+            &program_id,
+            &restored,
+            &from_user,
+            &path,
+            &ComputeBudget::default(),
+            None,
+        )
+        .is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,102 +1,333 @@
+use log::{debug, error, info, warn};
 use md::data::{
-    deserialize_account_data, AccountMetadata, ChatCommand, ChatData, ChatInstruction, Message,
+    AccountMetadata, ChatData, ChatInstruction, Message, MessageBuilder, Reaction,
+    CHAT_ACCOUNT_SIZE,
 };
-use solana_client::rpc_client::{self, RpcClient};
-use solana_sdk::instruction::{AccountMeta, Instruction};
+use md::error::ChatError;
+use solana_sdk::account::Account;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::packet::PACKET_DATA_SIZE;
 use solana_sdk::pubkey::{Pubkey, PubkeyError};
+use solana_sdk::signature::Signature;
 use solana_sdk::signer::keypair::Keypair;
 use solana_sdk::signer::Signer;
-use solana_sdk::system_instruction;
 use solana_sdk::transaction::Transaction;
+use std::collections::HashMap;
 use std::error::Error;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-static ACCOUNT_SIZE: u64 = 5 * 1024;
+use fprog::instructions::{
+    build_delete_instruction, build_open_account_instructions, build_send_instruction,
+    create_chat_instruction, AddressStrategy,
+};
+// re-exported for the other modules of the binary
+pub(crate) use fprog::instructions::infer_chat_account_pubkey;
 
-static SEED: &str = "chat";
+use crate::account::{read_last_message_id, read_used_data, ChatAccount, Cursor};
+use crate::compression::{compress_message, decompress_message};
+use crate::error::ChatClientError;
+use crate::export::{account_json, messages_csv, sha256_hex, write_csv, write_json, OutputFormat};
+use crate::rpc::ChatRpc;
+use crate::verify::verify_account_data;
 
-fn create_chat_instruction(
-    program: Pubkey,
-    from_account: Pubkey,
-    to_account: Pubkey,
-    chat_instruction: ChatInstruction,
-) -> Result<Instruction, Box<dyn Error>> {
-    let data_size = chat_instruction.size();
-    let mut instruction_data = vec![0; data_size];
-    chat_instruction.serialize(&mut instruction_data[..])?;
-    let ac_meta = AccountMeta::new(from_account, true);
-    let new_account_meta = AccountMeta::new(to_account, false);
-    Ok(Instruction::new_with_bytes(
-        program,
-        &instruction_data[..],
-        vec![ac_meta, new_account_meta],
-    ))
+static MAINNET_BETA_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+
+static ACCOUNT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// one signature at the default lamports_per_signature
+static ESTIMATED_OPEN_ACCOUNT_FEE: u64 = 5000;
+
+// everything in a single-signer SendMessages transaction except the instruction data:
+// signature (1 + 64), header (3), 3 account keys (1 + 96), blockhash (32),
+// instruction count (1), program index (1), account indexes (1 + 2), data length (2)
+static SEND_TRANSACTION_OVERHEAD: usize = 204;
+
+// logged by the program after SendMessages as "Assigned message ids 7..=9"
+static ASSIGNED_IDS_LOG: &str = "Program log: Assigned message ids ";
+
+static MAX_SEND_INSTRUCTION_DATA_SIZE: usize = PACKET_DATA_SIZE - SEND_TRANSACTION_OVERHEAD;
+
+// rough compute cost of SendMessages with headroom: loading and rewriting the metadata once,
+// then checking, copying and logging each message
+static SEND_BASE_COMPUTE_UNITS: u32 = 20_000;
+static SEND_COMPUTE_UNITS_PER_MESSAGE: u32 = 8_000;
+// the most a transaction may ask for
+static MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+// what the runtime grants each instruction when no limit is set
+static DEFAULT_INSTRUCTION_COMPUTE_UNITS: u32 = 200_000;
+
+// the limit send asks for when --compute-limit isn't given, a tight limit also keeps the
+// priority fee down since it's charged on the requested units
+fn estimated_send_units(message_count: usize) -> u32 {
+    let per_message = SEND_COMPUTE_UNITS_PER_MESSAGE.saturating_mul(message_count as u32);
+    SEND_BASE_COMPUTE_UNITS
+        .saturating_add(per_message)
+        .min(MAX_COMPUTE_UNIT_LIMIT)
+}
+
+// compute budget instructions put in front of every transaction we build
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ComputeBudget {
+    // priority fee in micro-lamports per compute unit
+    pub unit_price: Option<u64>,
+    pub unit_limit: Option<u32>,
+}
+
+impl ComputeBudget {
+    fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = vec![];
+        if let Some(limit) = self.unit_limit {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(limit));
+        }
+        if let Some(price) = self.unit_price {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+        }
+        instructions
+    }
+
+    // bytes the instructions add to a transaction: the program key once, then per
+    // instruction a program index, empty account list, data length and the data
+    fn transaction_overhead(&self) -> usize {
+        let instructions = self.instructions();
+        if instructions.is_empty() {
+            return 0;
+        }
+        let data: usize = instructions.iter().map(|inst| 3 + inst.data.len()).sum();
+        32 + data
+    }
+
+    // lamports the unit price adds on top of the signature fee, charged on the requested limit
+    // or, without one, on the default the runtime grants instruction_count instructions
+    fn priority_fee(&self, instruction_count: usize) -> u64 {
+        let units = self.unit_limit.unwrap_or_else(|| {
+            DEFAULT_INSTRUCTION_COMPUTE_UNITS
+                .saturating_mul(instruction_count as u32)
+                .min(MAX_COMPUTE_UNIT_LIMIT)
+        });
+        let micro_lamports = self.unit_price.unwrap_or(0) as u128 * units as u128;
+        micro_lamports.div_ceil(1_000_000) as u64
+    }
+
+    fn with_instruction(&self, instruction: Instruction) -> Vec<Instruction> {
+        let mut instructions = self.instructions();
+        instructions.push(instruction);
+        instructions
+    }
+
+    // a SendMessages batch of message_count messages, an explicit unit_limit wins over the
+    // estimate
+    fn for_send(&self, message_count: usize) -> ComputeBudget {
+        ComputeBudget {
+            unit_limit: self
+                .unit_limit
+                .or_else(|| Some(estimated_send_units(message_count))),
+            ..*self
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SendOptions {
+    // deflate the message bodies before sending them
+    pub compress: bool,
+    // poll until the recipient account exists for up to this long
+    pub wait_for_account_timeout: Option<Duration>,
+    pub compute_budget: ComputeBudget,
+    // sha256 of a file kept off chain, attached to every message sent
+    pub attachment_hash: Option<[u8; 32]>,
+    // derive ids from the content so the program rejects a repeated send
+    pub content_ids: bool,
+    // open the sender's own chat account first when it doesn't exist yet
+    pub auto_open: Option<AutoOpen>,
+    // id of the message being answered, set on every message sent
+    pub reply_to: Option<u32>,
+    // pays the transaction fees in place of the sender
+    pub payer: Option<Keypair>,
+    // send even when the recipient account is owned by the sender
+    pub allow_self: bool,
+    // messages expire this many seconds after the send
+    pub ttl_secs: Option<u64>,
+    // drop control characters other than newline and tab before storing the bodies
+    pub sanitize: bool,
+    // batches sent on one blockhash before fetching a new one, every batch gets a fresh one
+    // when unset
+    pub blockhash_refresh_every: Option<NonZeroUsize>,
+}
+
+pub struct AutoOpen {
+    pub strategy: AddressStrategy,
+    pub account_name: String,
+}
+
+// reads default to the caller's own account, owner_pk lets us read someone else's public board
+pub(crate) fn chat_account_to_read(
+    user_pk: &Pubkey,
+    owner_pk: Option<&Pubkey>,
+    program_pk: &Pubkey,
+    strategy: &AddressStrategy,
+) -> Result<Pubkey, PubkeyError> {
+    infer_chat_account_pubkey(owner_pk.unwrap_or(user_pk), program_pk, strategy)
 }
 
-fn infer_chat_account_pubkey(user_pk: &Pubkey, program_pk: &Pubkey) -> Result<Pubkey, PubkeyError> {
-    Pubkey::create_with_seed(user_pk, SEED, program_pk)
+// only a missing account is Ok(false), any other failed read is passed on, so a flaky node
+// isn't taken for an account that has to be created
+pub fn account_exists(rpc_client: &impl ChatRpc, pubkey: &Pubkey) -> Result<bool, ChatClientError> {
+    match rpc_client.get_account(pubkey) {
+        Ok(_) => Ok(true),
+        Err(err) => match ChatClientError::from_account_read(pubkey, err) {
+            ChatClientError::AccountNotOpened(_) => Ok(false),
+            err => Err(err),
+        },
+    }
+}
+
+// true once the account exists and the program has written its metadata
+fn is_account_opened(rpc_client: &impl ChatRpc, pubkey: &Pubkey) -> bool {
+    match rpc_client.get_account_data(pubkey) {
+        Ok(data) => ChatAccount::from_data(*pubkey, &data[..])
+            .map(|chat_account| chat_account.metadata.initialized > 0)
+            .unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+// the owner in the metadata catches your accounts under every seed, and ones transferred to you
+fn is_own_account(pubkey: &Pubkey, account: &Account, user_pk: &Pubkey) -> bool {
+    account_owner(pubkey, account) == Some(*user_pk)
+}
+
+// the user managing a chat account, None when the account doesn't hold chat data
+fn account_owner(pubkey: &Pubkey, account: &Account) -> Option<Pubkey> {
+    // anything that isn't a chat account would make the codec panic, so check it first
+    if !verify_account_data(&account.data[..]).is_empty() {
+        return None;
+    }
+    ChatAccount::from_data(*pubkey, &account.data[..])
+        .ok()
+        .map(|chat_account| chat_account.metadata.owner)
+}
+
+// bytes left after the last message, None when the account doesn't hold chat data
+fn free_space(pubkey: &Pubkey, account: &Account) -> Option<usize> {
+    if !verify_account_data(&account.data[..]).is_empty() {
+        return None;
+    }
+    let chat_account = ChatAccount::from_data(*pubkey, &account.data[..]).ok()?;
+    Some(
+        account
+            .data
+            .len()
+            .saturating_sub(chat_account.metadata.next_free_index as usize),
+    )
+}
+
+fn check_balance(available: u64, required: u64) -> Result<(), ChatClientError> {
+    if available < required {
+        return Err(ChatClientError::InsufficientFunds {
+            required,
+            available,
+        });
+    }
+    Ok(())
 }
 
+// wire size: signature count, the signatures, then the serialized message
+fn transaction_size(transaction: &Transaction) -> usize {
+    1 + transaction.signatures.len() * 64 + transaction.message_data().len()
+}
+
+// a separate payer only covers the fee, the program still takes the rent from from_user
+#[allow(clippy::too_many_arguments)]
 pub fn open_account(
-    rpc_client: &RpcClient,
-    program_keypair: &Keypair,
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
     from_user: &Keypair,
+    payer: Option<&Keypair>,
     account_name: &str,
+    welcome_message: Option<&str>,
+    compute_budget: &ComputeBudget,
 ) -> Result<(), Box<dyn Error>> {
-    let account_pub_key =
-        infer_chat_account_pubkey(&from_user.pubkey(), &program_keypair.pubkey())?;
-
-    let rent = rpc_client.get_minimum_balance_for_rent_exemption(ACCOUNT_SIZE as usize)?;
-
-    let existing_account = rpc_client.get_account(&account_pub_key);
-
-    if existing_account.is_err() {
-        println!("Creating new  account {}", &account_pub_key.to_string());
-        let allocation_size = ACCOUNT_SIZE;
+    // reject invalid names before touching the network
+    let (open_instructions, account_pub_key) = build_open_account_instructions(
+        program_id,
+        &from_user.pubkey(),
+        strategy,
+        account_name,
+        welcome_message,
+    )?;
 
-        let account = rpc_client.get_account(&from_user.pubkey())?;
-        let lamports = account.lamports;
-        println!("User: {} has {} lamports", from_user.pubkey(), lamports);
+    let rent = rpc_client.get_minimum_balance_for_rent_exemption(CHAT_ACCOUNT_SIZE)?;
 
-        let open_account_inst = system_instruction::create_account_with_seed(
-            &from_user.pubkey(),
-            &account_pub_key,
-            &from_user.pubkey(),
-            SEED,
-            rent,
-            allocation_size,
-            &program_keypair.pubkey(),
-        );
+    if !account_exists(rpc_client, &account_pub_key)? {
+        info!("Creating new account {}", account_pub_key);
 
-        let chat_instruction = ChatInstruction::OpenAccount {
-            account_metadata: AccountMetadata::new(account_name),
+        // a fresh keypair has no account until something funds it, that's a zero balance
+        let lamports = match rpc_client.get_account(&from_user.pubkey()) {
+            Ok(account) => account.lamports,
+            Err(err) => match ChatClientError::from_account_read(&from_user.pubkey(), err) {
+                ChatClientError::AccountNotOpened(_) => 0,
+                err => return Err(Box::new(err)),
+            },
+        };
+        info!("User: {} has {} lamports", from_user.pubkey(), lamports);
+        debug!("Account rent: {} lamports", rent);
+        let fee = if payer.is_some() {
+            0
+        } else {
+            ESTIMATED_OPEN_ACCOUNT_FEE + compute_budget.priority_fee(open_instructions.len())
         };
+        check_balance(lamports, rent + fee)?;
 
-        let initialize_acc_inst = create_chat_instruction(
-            program_keypair.pubkey(),
-            from_user.pubkey(),
-            account_pub_key,
-            chat_instruction,
-        )?;
+        let mut instructions = compute_budget.instructions();
+        instructions.extend(open_instructions);
 
         let hash = rpc_client.get_latest_blockhash()?;
 
-        let transaction = Transaction::new_signed_with_payer(
-            &[open_account_inst, initialize_acc_inst],
-            Some(&from_user.pubkey()),
-            &[from_user],
-            hash,
-        );
+        let (fee_payer, signers) = fee_payer_and_signers(from_user, payer);
+        let transaction =
+            Transaction::new_signed_with_payer(&instructions, Some(&fee_payer), &signers, hash);
+        let size = transaction_size(&transaction);
+        if size > PACKET_DATA_SIZE {
+            return Err(Box::new(ChatClientError::MessageTooLarge {
+                size,
+                max: PACKET_DATA_SIZE,
+            }));
+        }
+        report_fee(rpc_client, &transaction);
 
         match rpc_client.send_and_confirm_transaction_with_spinner(&transaction) {
             Ok(sig) => {
                 println!("Transaction successed !");
                 println!("Signature: {}", sig);
             }
+            // another run may have opened the account after our check, the program then rejects
+            // ours as already in use, so whether the account ended up opened is what counts
+            Err(err) if is_account_opened(rpc_client, &account_pub_key) => {
+                debug!("Open failed with {:?} but the account exists", err);
+                println!("Account {} already exist", account_pub_key);
+            }
             Err(err) => {
-                println!("Got Error: {:?}", err);
+                error!("Got Error: {:?}", err);
                 return Err(Box::new(err));
             }
         }
+        // without confirmation the account may simply not be there yet
+        if rpc_client.confirms() && !is_account_opened(rpc_client, &account_pub_key) {
+            return Err(Box::new(ChatClientError::AccountNotOpened(account_pub_key)));
+        }
+    } else if is_uninitialized(rpc_client, &account_pub_key) {
+        println!(
+            "Account {} exists but was never initialized, run `-c init-account` to set it up",
+            account_pub_key
+        );
     } else {
         println!("Account {} already exist", account_pub_key);
     }
@@ -104,64 +335,571 @@ pub fn open_account(
     Ok(())
 }
 
-pub fn receive_messages(
-    rpc_client: &RpcClient,
-    program_keypair: &Keypair,
+// created without its metadata, which leaves the data zeroed with initialized = 0; an open split
+// over two transactions or a failed init ends up like this. only the metadata is read, as the
+// program does, since a zeroed next_free_index doesn't pass as account data
+fn is_uninitialized(rpc_client: &impl ChatRpc, pubkey: &Pubkey) -> bool {
+    let data = match rpc_client.get_account_data(pubkey) {
+        Ok(data) => data,
+        Err(_) => return false,
+    };
+    let metadata_size = match AccountMetadata::calculate_size_from_buffer(&data[..]) {
+        Ok(metadata_size) => metadata_size,
+        Err(_) => return false,
+    };
+    let mut account_metadata = AccountMetadata::default();
+    metadata_size <= data.len()
+        && account_metadata.deserialize(&data[..metadata_size]).is_ok()
+        && account_metadata.initialized == 0
+}
+
+// writes the metadata into an account that exists but was never initialized, the program skips
+// creating an account that already holds data and only fills it in
+pub fn init_account(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
     from_user: &Keypair,
-    _last_message_id: Option<u32>,
+    payer: Option<&Keypair>,
+    account_name: &str,
+    compute_budget: &ComputeBudget,
 ) -> Result<(), Box<dyn Error>> {
-    let user_char_account =
-        infer_chat_account_pubkey(&from_user.pubkey(), &program_keypair.pubkey())?;
+    let (open_instructions, account_pub_key) = build_open_account_instructions(
+        program_id,
+        &from_user.pubkey(),
+        strategy,
+        account_name,
+        None,
+    )?;
+
+    if !account_exists(rpc_client, &account_pub_key)? {
+        return Err(Box::new(ChatClientError::AccountNotOpened(account_pub_key)));
+    }
+    if !is_uninitialized(rpc_client, &account_pub_key) {
+        println!("Account {} is already initialized", account_pub_key);
+        return Ok(());
+    }
+    info!("Initializing account {}", account_pub_key);
+
+    let mut instructions = compute_budget.instructions();
+    instructions.extend(open_instructions);
+    let hash = rpc_client.get_latest_blockhash()?;
+    let (fee_payer, signers) = fee_payer_and_signers(from_user, payer);
+    let transaction =
+        Transaction::new_signed_with_payer(&instructions, Some(&fee_payer), &signers, hash);
+    report_fee(rpc_client, &transaction);
+
+    match rpc_client.send_and_confirm_transaction_with_spinner(&transaction) {
+        Ok(sig) => {
+            println!("Account {} initialized", account_pub_key);
+            println!("Signature: {}", sig);
+            Ok(())
+        }
+        Err(err) => {
+            error!("Got Error: {:?}", err);
+            Err(Box::new(err))
+        }
+    }
+}
+
+fn short_pubkey(pubkey: &Pubkey) -> String {
+    let pubkey = pubkey.to_string();
+    format!("{}…{}", &pubkey[..3], &pubkey[pubkey.len() - 3..])
+}
+
+// Resolves message senders to their chat account names, reading each sender's account once
+struct SenderNames<'a, R: ChatRpc> {
+    rpc_client: &'a R,
+    program_pk: Pubkey,
+    strategy: AddressStrategy,
+    names: HashMap<Pubkey, Option<String>>,
+}
+
+impl<'a, R: ChatRpc> SenderNames<'a, R> {
+    // senders' accounts are found the way the account being read was, except that an explicit
+    // address is that account alone, so senders fall back to their default one
+    fn new(rpc_client: &'a R, program_pk: Pubkey, strategy: &AddressStrategy) -> Self {
+        let strategy = match strategy {
+            AddressStrategy::Explicit(_) => AddressStrategy::default(),
+            strategy => strategy.clone(),
+        };
+        SenderNames {
+            rpc_client,
+            program_pk,
+            strategy,
+            names: HashMap::new(),
+        }
+    }
+
+    fn name(&mut self, from: &Pubkey) -> Option<&str> {
+        let (rpc_client, program_pk, strategy) =
+            (self.rpc_client, &self.program_pk, &self.strategy);
+        self.names
+            .entry(*from)
+            .or_insert_with(|| {
+                let chat_account_pk = infer_chat_account_pubkey(from, program_pk, strategy).ok()?;
+                let chat_account = ChatAccount::load(rpc_client, &chat_account_pk).ok()?;
+                Some(chat_account.name().to_string()).filter(|name| !name.is_empty())
+            })
+            .as_deref()
+    }
+
+    fn display_name(&mut self, from: &Pubkey) -> String {
+        match self.name(from) {
+            // the name is whatever the sender stored, shown escaped like a message body
+            Some(name) => format!("{} ({})", escape_controls(name), short_pubkey(from)),
+            None => from.to_string(),
+        }
+    }
+}
+
+// at most limit of the messages, with the id to pass as the next after_id while more are left
+fn page_after(mut messages: Vec<&Message>, limit: Option<usize>) -> (Vec<&Message>, Option<u32>) {
+    match limit {
+        Some(limit) if messages.len() > limit => {
+            messages.truncate(limit);
+            let next_after = messages.last().map(|message| message.id);
+            (messages, next_after)
+        }
+        _ => (messages, None),
+    }
+}
+
+// messages sit in the account in the order they were sent, so reversing them puts the
+// newest first, with a limit that's the last limit messages and nothing left to page to
+fn newest_first(mut messages: Vec<&Message>, limit: Option<usize>) -> Vec<&Message> {
+    messages.reverse();
+    if let Some(limit) = limit {
+        messages.truncate(limit);
+    }
+    messages
+}
+
+// expired messages stay in the account until a purge, readers skip them as if they were gone
+fn unexpired(messages: Vec<&Message>, now: i64) -> Vec<&Message> {
+    messages
+        .into_iter()
+        .filter(|message| !message.is_expired(now))
+        .collect()
+}
+
+// "Account 'friends' (Did…oqM) — 12 messages, 3200/5120 bytes used, opened @ 1650000000"
+fn account_header(pubkey: &Pubkey, account_metadata: &AccountMetadata, allocated: usize) -> String {
+    format!(
+        "Account '{}' ({}) — {} messages, {}/{} bytes used, opened @ {}",
+        escape_controls(&account_metadata.account_name),
+        short_pubkey(pubkey),
+        account_metadata.message_count,
+        account_metadata.next_free_index,
+        allocated,
+        account_metadata.created_at
+    )
+}
+
+// cuts after max_chars chars rather than bytes, so an emoji is never split
+fn truncate_chars(msg: &str, max_chars: usize) -> String {
+    match msg.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &msg[..end]),
+        None => msg.to_string(),
+    }
+}
+
+// C0, DEL and C1 controls, newline and tab are left for multi-line messages
+fn is_unsafe_control(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\t'
+}
+
+// shows control characters as escapes like \u{1b} so a message can't drive the reader's
+// terminal, the stored bytes are left alone
+pub(crate) fn escape_controls(msg: &str) -> String {
+    msg.chars()
+        .map(|c| {
+            if is_unsafe_control(c) {
+                c.escape_unicode().to_string()
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+fn strip_controls(msg: &str) -> String {
+    msg.chars().filter(|c| !is_unsafe_control(*c)).collect()
+}
+
+const ID_COLOR: &str = "\x1b[33m";
+const SENDER_COLOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+fn paint(text: &str, color: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{}{}{}", color, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+// color wraps the id and the sender in ANSI colors
+pub(crate) fn message_line(
+    message: &Message,
+    sender: &str,
+    preview: Option<usize>,
+    color: bool,
+) -> String {
+    let reply = match message.reply_to {
+        Some(reply_to) => format!(" (reply to {})", reply_to),
+        None => String::new(),
+    };
+    let msg = match preview {
+        Some(max_chars) => truncate_chars(&message.msg, max_chars),
+        None => message.msg.clone(),
+    };
+    // after the preview cut so an escape is never split
+    let msg = escape_controls(&msg);
+    format!(
+        "[{}] {} @ {}{}: {}",
+        paint(&message.id.to_string(), ID_COLOR, color),
+        paint(sender, SENDER_COLOR, color),
+        message.timestamp,
+        reply,
+        msg
+    )
+}
+
+// one "👍 x2" entry per distinct emoji, in the order they were first used
+fn reaction_summary(account_metadata: &AccountMetadata, message_id: u32) -> Option<String> {
+    let mut counts: Vec<(&str, usize)> = vec![];
+    for reaction in account_metadata.reactions_to(message_id) {
+        match counts
+            .iter_mut()
+            .find(|(emoji, _)| *emoji == reaction.emoji)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((&reaction.emoji, 1)),
+        }
+    }
+    if counts.is_empty() {
+        return None;
+    }
+    let summary = counts
+        .iter()
+        .map(|(emoji, count)| format!("{} x{}", escape_controls(emoji), count))
+        .collect::<Vec<_>>();
+    Some(summary.join(" "))
+}
+
+#[derive(Default)]
+pub struct ReceiveOptions {
+    // read the chat account of this user instead of your own
+    pub owner_pubkey: Option<Pubkey>,
+    pub resolve_names: bool,
+    pub format: OutputFormat,
+    pub output_file: Option<PathBuf>,
+    pub append: bool,
+    // print at most this many messages per call, with a cursor to the next page
+    pub limit: Option<usize>,
+    // fetch only the used part of the account instead of all of it
+    pub data_slice: bool,
+    // show at most this many chars of each message
+    pub preview: Option<usize>,
+    // print the newest message first
+    pub reverse: bool,
+    // stop parsing the account after this many messages, None reads them all
+    pub max_messages: Option<usize>,
+    // color the ids and senders of the text output
+    pub color: bool,
+}
+
+// the latest state of a chat account with its bodies decompressed, None when it can't be parsed
+pub(crate) fn read_chat_account(
+    rpc_client: &impl ChatRpc,
+    pubkey: &Pubkey,
+    data_slice: bool,
+    max_messages: Option<usize>,
+) -> Result<Option<ChatAccount>, Box<dyn Error>> {
+    let data = if data_slice {
+        read_used_data(rpc_client, pubkey)
+    } else {
+        // follow polls the same account, each poll must see what landed since the last
+        rpc_client.refresh(pubkey)
+    }
+    .map_err(|err| ChatClientError::from_account_read(pubkey, err))?;
+    debug!("size of data: {}", data.len());
+
+    let max_messages = max_messages.unwrap_or(usize::MAX);
+    let mut chat_account = match ChatAccount::from_data_up_to(*pubkey, &data[..], max_messages) {
+        Ok(chat_account) => chat_account,
+        Err(_) => return Ok(None),
+    };
+    if chat_account.truncated {
+        warn!(
+            "Stopped reading {} after {} messages, it may hold more",
+            pubkey, max_messages
+        );
+    }
+    for message in chat_account.messages.iter_mut() {
+        decompress_message(message)?;
+    }
+    Ok(Some(chat_account))
+}
 
-    let data = rpc_client.get_account_data(&user_char_account)?;
+// prints what cursor hasn't shown yet and moves it past that, for the next poll to carry on from
+pub fn receive_messages(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    cursor: &mut Cursor,
+    options: &ReceiveOptions,
+) -> Result<(), Box<dyn Error>> {
+    let user_char_account = chat_account_to_read(
+        &from_user.pubkey(),
+        options.owner_pubkey.as_ref(),
+        program_id,
+        strategy,
+    )?;
 
-    if let Ok((account_metadata, messages)) = deserialize_account_data(&data[..]) {
-        println!("{:?}", account_metadata);
-        println!("{:?}", messages);
+    let chat_account = match read_chat_account(
+        rpc_client,
+        &user_char_account,
+        options.data_slice,
+        options.max_messages,
+    )? {
+        Some(chat_account) => chat_account,
+        None => {
+            println!("account is empty");
+            return Ok(());
+        }
+    };
+    let account_metadata = &chat_account.metadata;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let first_read = cursor.at_start();
+    let messages = unexpired(cursor.unseen(&chat_account.messages), now);
+    let (new_messages, next_after) = if options.reverse {
+        (newest_first(messages, options.limit), None)
     } else {
-        println!("account is empty");
+        page_after(messages, options.limit)
+    };
+
+    if options.format == OutputFormat::Json {
+        // polls with nothing new would only repeat the metadata
+        if first_read || !new_messages.is_empty() {
+            let mut json = account_json(account_metadata, &new_messages);
+            if options.limit.is_some() {
+                json["next_after"] = next_after.into();
+            }
+            write_json(&json, options.output_file.as_deref(), options.append)?;
+        }
+        cursor.advance(&chat_account.messages, &new_messages);
+        return Ok(());
     }
 
-    println!("size of data: {}", data.len());
+    if first_read {
+        // a sliced read ends at next_free_index, the account itself is always full size
+        let allocated = if options.data_slice {
+            CHAT_ACCOUNT_SIZE
+        } else {
+            chat_account.data_len()
+        };
+        println!(
+            "{}",
+            account_header(&user_char_account, account_metadata, allocated)
+        );
+    }
+    if new_messages.is_empty() {
+        return Ok(());
+    }
+    let mut sender_names = SenderNames::new(rpc_client, *program_id, strategy);
+    for message in new_messages.iter() {
+        let sender = if options.resolve_names {
+            sender_names.display_name(&message.from)
+        } else {
+            message.from.to_string()
+        };
+        println!(
+            "{}",
+            message_line(message, &sender, options.preview, options.color)
+        );
+        if let Some(hash) = &message.attachment_hash {
+            println!("  attachment sha256 {}", sha256_hex(hash));
+        }
+        if let Some(summary) = reaction_summary(account_metadata, message.id) {
+            println!("  {}", summary);
+        }
+    }
+    if let Some(next_after) = next_after {
+        println!("More messages, continue with --after {}", next_after);
+    }
 
+    cursor.advance(&chat_account.messages, &new_messages);
     Ok(())
 }
 
-pub fn infer_chat_address(
-    rpc_client: &RpcClient,
-    program_keypair: &Keypair,
+// re-reads the account every interval until ctrl-c, printing only messages not seen yet
+pub fn follow_messages(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
     from_user: &Keypair,
+    after_id: Option<u32>,
+    mut options: ReceiveOptions,
+    interval: Duration,
 ) -> Result<(), Box<dyn Error>> {
-    let from_user_chat_pk =
-        infer_chat_account_pubkey(&from_user.pubkey(), &program_keypair.pubkey())?;
-    println!("Address: {}", from_user_chat_pk);
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_running = running.clone();
+    ctrlc::set_handler(move || handler_running.store(false, Ordering::SeqCst))?;
+
+    let mut cursor = Cursor::after(after_id);
+    while running.load(Ordering::SeqCst) {
+        receive_messages(
+            rpc_client,
+            program_id,
+            strategy,
+            from_user,
+            &mut cursor,
+            &options,
+        )?;
+        info!("cursor: {:?}", cursor);
+        // later polls only carry new messages, they must not replace the first dump
+        options.append = true;
+
+        // sleep in short steps so ctrl-c doesn't have to wait out the whole interval
+        let next_poll = Instant::now() + interval;
+        while running.load(Ordering::SeqCst) && Instant::now() < next_poll {
+            thread::sleep(ACCOUNT_POLL_INTERVAL.min(next_poll - Instant::now()));
+        }
+    }
+
     Ok(())
 }
 
-pub fn send_message(
-    rpc_client: &RpcClient,
-    program_keypair: &Keypair,
+// polls until a message cursor hasn't seen lands and returns the first of them
+fn wait_for_message(
+    rpc_client: &impl ChatRpc,
+    pubkey: &Pubkey,
+    cursor: &Cursor,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> Result<Message, Box<dyn Error>> {
+    let started = Instant::now();
+    loop {
+        if let Some(chat_account) = read_chat_account(rpc_client, pubkey, false, None)? {
+            if let Some(message) = cursor.unseen(&chat_account.messages).first() {
+                return Ok((*message).clone());
+            }
+        }
+        if started.elapsed() >= timeout {
+            return Err(Box::new(ChatClientError::MessageWaitTimeout {
+                timeout_secs: timeout.as_secs(),
+            }));
+        }
+        thread::sleep(poll_interval);
+    }
+}
+
+// anything not stored at the start is new, whatever its id and even when messages are deleted
+// while waiting
+pub fn wait_next_message(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
     from_user: &Keypair,
-    to_user: &Pubkey,
-    msg: String,
+    owner_pubkey: Option<&Pubkey>,
+    timeout: Duration,
+    poll_interval: Duration,
 ) -> Result<(), Box<dyn Error>> {
-    // FIXME, from_user should be generated with seed
-    // this from_user is system account that pays for transaction
-    let from_user_chat_pk =
-        infer_chat_account_pubkey(&from_user.pubkey(), &program_keypair.pubkey())?;
-    let _to_account = rpc_client.get_account(to_user)?;
-    let message = Message::new(0, from_user.pubkey(), msg);
+    let pubkey = chat_account_to_read(&from_user.pubkey(), owner_pubkey, program_id, strategy)?;
+    let cursor = read_chat_account(rpc_client, &pubkey, false, None)?
+        .map_or_else(Cursor::default, |chat_account| {
+            Cursor::past(&chat_account.messages)
+        });
+    let message = wait_for_message(rpc_client, &pubkey, &cursor, timeout, poll_interval)?;
+    println!(
+        "{}",
+        message_line(&message, &message.from.to_string(), None, false)
+    );
+    Ok(())
+}
 
-    let chat_instruction = ChatInstruction::SendMessages {
-        messages: vec![message],
-    };
+// prints the id of the newest message without downloading the messages themselves
+pub fn last_id(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    owner_pubkey: Option<&Pubkey>,
+) -> Result<(), Box<dyn Error>> {
+    let pubkey = chat_account_to_read(&from_user.pubkey(), owner_pubkey, program_id, strategy)?;
+    match read_last_message_id(rpc_client, &pubkey)
+        .map_err(|err| ChatClientError::from_account_read(&pubkey, err))?
+    {
+        Some(last_message_id) => {
+            println!("{}", last_message_id);
+            Ok(())
+        }
+        None => Err(Box::new(ChatClientError::AccountNotOpened(pubkey))),
+    }
+}
 
-    let instruction = create_chat_instruction(
-        program_keypair.pubkey(),
-        from_user.pubkey(),
-        *to_user,
-        chat_instruction,
-    )?;
+fn matching_messages<'a>(
+    messages: &'a [Message],
+    query: &str,
+    ignore_case: bool,
+) -> Vec<&'a Message> {
+    if ignore_case {
+        let query = query.to_lowercase();
+        messages
+            .iter()
+            .filter(|message| message.msg.to_lowercase().contains(&query))
+            .collect()
+    } else {
+        messages
+            .iter()
+            .filter(|message| message.msg.contains(query))
+            .collect()
+    }
+}
+
+pub fn search_messages(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    owner_pk: Option<&Pubkey>,
+    query: &str,
+    ignore_case: bool,
+) -> Result<(), Box<dyn Error>> {
+    let chat_account_pk =
+        chat_account_to_read(&from_user.pubkey(), owner_pk, program_id, strategy)?;
+    let mut chat_account = ChatAccount::load(rpc_client, &chat_account_pk)?;
+    for message in chat_account.messages.iter_mut() {
+        decompress_message(message)?;
+    }
+
+    let matches = matching_messages(&chat_account.messages, query, ignore_case);
+    println!("{} messages match {:?}", matches.len(), query);
+    for message in matches {
+        println!(
+            "{}: {}: {}",
+            message.id,
+            message.from,
+            escape_controls(&message.msg)
+        );
+    }
+    Ok(())
+}
+
+// sends an instruction only the owner may sign, on their own account: the deletes and purge,
+// close, block and unblock, and transfer
+pub fn send_owner_instruction(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    chat_instruction: ChatInstruction,
+) -> Result<(), Box<dyn Error>> {
+    let (instruction, _) =
+        build_delete_instruction(program_id, &from_user.pubkey(), strategy, chat_instruction)?;
 
     let hash = rpc_client.get_latest_blockhash()?;
 
@@ -178,10 +916,1863 @@ pub fn send_message(
             println!("Signature: {}", sig);
         }
         Err(err) => {
-            println!("Got Error: {:?}", err);
+            error!("Got Error: {:?}", err);
             return Err(Box::new(err));
         }
     }
 
     Ok(())
 }
+
+// the program drains the account into the owner, so the rent comes back to from_user
+pub fn close_account(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+) -> Result<(), Box<dyn Error>> {
+    send_owner_instruction(
+        rpc_client,
+        program_id,
+        strategy,
+        from_user,
+        ChatInstruction::CloseAccount,
+    )
+}
+
+pub fn block_sender(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    sender: Pubkey,
+    block: bool,
+) -> Result<(), Box<dyn Error>> {
+    let chat_instruction = if block {
+        ChatInstruction::BlockSender { pubkey: sender }
+    } else {
+        ChatInstruction::UnblockSender { pubkey: sender }
+    };
+    send_owner_instruction(
+        rpc_client,
+        program_id,
+        strategy,
+        from_user,
+        chat_instruction,
+    )
+}
+
+// the account keeps its address, only the signer the program accepts for it changes
+pub fn transfer_ownership(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    new_owner: Pubkey,
+) -> Result<(), Box<dyn Error>> {
+    let chat_instruction = ChatInstruction::TransferOwnership { new_owner };
+    send_owner_instruction(
+        rpc_client,
+        program_id,
+        strategy,
+        from_user,
+        chat_instruction,
+    )
+}
+
+// reacts on the owner's account, or on your own when no owner is given
+pub fn react(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    owner_pk: Option<&Pubkey>,
+    target_id: u32,
+    emoji: &str,
+) -> Result<(), Box<dyn Error>> {
+    // same checks the program does, so a bad emoji doesn't cost a transaction
+    Reaction::new(target_id, from_user.pubkey(), emoji)?;
+    let chat_account = chat_account_to_read(&from_user.pubkey(), owner_pk, program_id, strategy)?;
+    let instruction = create_chat_instruction(
+        *program_id,
+        from_user.pubkey(),
+        chat_account,
+        ChatInstruction::React {
+            target_id,
+            emoji: emoji.to_string(),
+        },
+    )?;
+
+    let hash = rpc_client.get_latest_blockhash()?;
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&from_user.pubkey()),
+        &[from_user],
+        hash,
+    );
+    let sig = rpc_client.send_and_confirm_transaction_with_spinner(&transaction)?;
+    println!("Transaction successed !");
+    println!("Signature: {}", sig);
+
+    Ok(())
+}
+
+// edits a message you sent to the owner's account, or to your own when no owner is given
+pub fn edit_message(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    owner_pk: Option<&Pubkey>,
+    id: u32,
+    new_msg: &str,
+) -> Result<(), Box<dyn Error>> {
+    let chat_account = chat_account_to_read(&from_user.pubkey(), owner_pk, program_id, strategy)?;
+    let instruction = create_chat_instruction(
+        *program_id,
+        from_user.pubkey(),
+        chat_account,
+        ChatInstruction::EditMessage {
+            id,
+            new_msg: new_msg.to_string(),
+        },
+    )?;
+
+    let hash = rpc_client.get_latest_blockhash()?;
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&from_user.pubkey()),
+        &[from_user],
+        hash,
+    );
+    let sig = rpc_client.send_and_confirm_transaction_with_spinner(&transaction)?;
+    println!("Transaction successed !");
+    println!("Signature: {}", sig);
+
+    Ok(())
+}
+
+// dumps the whole history at once, unlike receive which pages and polls
+pub fn export_account(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    owner_pk: Option<&Pubkey>,
+    format: OutputFormat,
+    output_file: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let chat_account_pk =
+        chat_account_to_read(&from_user.pubkey(), owner_pk, program_id, strategy)?;
+    let mut chat_account = ChatAccount::load(rpc_client, &chat_account_pk)?;
+    for message in chat_account.messages.iter_mut() {
+        decompress_message(message)?;
+    }
+
+    let messages = chat_account.messages_since(None);
+    match format {
+        OutputFormat::Csv => write_csv(&messages_csv(&messages), output_file),
+        OutputFormat::Json => write_json(
+            &account_json(&chat_account.metadata, &messages),
+            output_file,
+            false,
+        ),
+        OutputFormat::Text => {
+            for message in messages {
+                println!(
+                    "{}: {}: {}",
+                    message.id,
+                    message.from,
+                    escape_controls(&message.msg)
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn stored_message_count(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+) -> Result<u32, Box<dyn Error>> {
+    let user_chat_account = infer_chat_account_pubkey(&from_user.pubkey(), program_id, strategy)?;
+    Ok(ChatAccount::load(rpc_client, &user_chat_account)?
+        .metadata
+        .message_count)
+}
+
+pub fn count_messages(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+) -> Result<(), Box<dyn Error>> {
+    let user_chat_account = infer_chat_account_pubkey(&from_user.pubkey(), program_id, strategy)?;
+
+    let chat_account = ChatAccount::load(rpc_client, &user_chat_account)?;
+    println!("Messages: {}", chat_account.metadata.message_count);
+    if chat_account.is_full() {
+        warn!(
+            "Account {} is full, delete messages to receive more",
+            chat_account.pubkey
+        );
+    }
+
+    Ok(())
+}
+
+pub fn airdrop(
+    rpc_client: &impl ChatRpc,
+    from_user: &Keypair,
+    lamports: u64,
+) -> Result<(), Box<dyn Error>> {
+    if rpc_client.get_genesis_hash()?.to_string() == MAINNET_BETA_GENESIS_HASH {
+        return Err(Box::new(ChatClientError::AirdropOnMainnet));
+    }
+
+    let sig = rpc_client.request_airdrop(&from_user.pubkey(), lamports)?;
+    rpc_client.poll_for_signature(&sig)?;
+    println!("Airdropped {} lamports to {}", lamports, from_user.pubkey());
+    println!("Signature: {}", sig);
+
+    Ok(())
+}
+
+pub fn infer_chat_address(
+    _rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+) -> Result<(), Box<dyn Error>> {
+    let from_user_chat_pk = infer_chat_account_pubkey(&from_user.pubkey(), program_id, strategy)?;
+    println!("Address: {}", from_user_chat_pk);
+    Ok(())
+}
+
+fn wait_for_account(
+    rpc_client: &impl ChatRpc,
+    pubkey: &Pubkey,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> Result<Account, Box<dyn Error>> {
+    let started = Instant::now();
+    loop {
+        match rpc_client.get_account(pubkey) {
+            Ok(account) => return Ok(account),
+            Err(err) if started.elapsed() >= timeout => {
+                debug!("Last error: {:?}", err);
+                return Err(Box::new(ChatClientError::AccountWaitTimeout {
+                    pubkey: *pubkey,
+                    timeout_secs: timeout.as_secs(),
+                }));
+            }
+            Err(_) => {
+                info!("Waiting for account {}", pubkey);
+                thread::sleep(poll_interval);
+            }
+        }
+    }
+}
+
+// splits messages into SendMessages-sized batches, keeping their order
+fn split_into_batches(
+    messages: Vec<Message>,
+    max_data_size: usize,
+) -> Result<Vec<Vec<Message>>, ChatClientError> {
+    // the instruction tag takes the first byte
+    let max_messages_size = max_data_size - 1;
+    let mut batches: Vec<Vec<Message>> = vec![];
+    let mut batch_size = 0;
+    for message in messages {
+        let size = message.size();
+        if size > max_messages_size {
+            return Err(ChatClientError::MessageTooLarge {
+                size,
+                max: max_messages_size,
+            });
+        }
+        match batches.last_mut() {
+            Some(batch) if batch_size + size <= max_messages_size => {
+                batch.push(message);
+                batch_size += size;
+            }
+            _ => {
+                batches.push(vec![message]);
+                batch_size = size;
+            }
+        }
+    }
+    Ok(batches)
+}
+
+fn parse_assigned_ids(logs: &[String]) -> Option<(u32, u32)> {
+    logs.iter().find_map(|log| {
+        let (first, last) = log.strip_prefix(ASSIGNED_IDS_LOG)?.split_once("..=")?;
+        Some((first.parse().ok()?, last.parse().ok()?))
+    })
+}
+
+pub fn estimate_fee(
+    rpc_client: &impl ChatRpc,
+    transaction: &Transaction,
+) -> Result<u64, Box<dyn Error>> {
+    Ok(rpc_client.get_fee_for_message(&transaction.message)?)
+}
+
+// a failed estimate shouldn't stop the send, the fee is only shown for information
+fn report_fee(rpc_client: &impl ChatRpc, transaction: &Transaction) {
+    match estimate_fee(rpc_client, transaction) {
+        Ok(fee) => println!("Estimated fee: {} lamports", fee),
+        Err(err) => warn!("Couldn't estimate the fee: {}", err),
+    }
+}
+
+fn report_assigned_ids(rpc_client: &impl ChatRpc, signature: &Signature) {
+    let logs = match rpc_client.get_transaction_logs(signature) {
+        Ok(logs) => logs,
+        Err(err) => {
+            warn!("Couldn't read logs of {}: {:?}", signature, err);
+            return;
+        }
+    };
+    match parse_assigned_ids(&logs) {
+        Some((first, last)) if first == last => println!("sent message id {}", first),
+        Some((first, last)) => println!("sent message ids {}..={}", first, last),
+        None => warn!("No message ids in the logs of {}", signature),
+    }
+}
+
+pub fn send_messages_batched(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    from_user: &Keypair,
+    to_user: &Pubkey,
+    messages: Vec<Message>,
+    compute_budget: &ComputeBudget,
+    blockhash_refresh_every: Option<NonZeroUsize>,
+) -> Result<Vec<Signature>, Box<dyn Error>> {
+    send_batches(
+        rpc_client,
+        program_id,
+        from_user,
+        None,
+        to_user,
+        messages,
+        compute_budget,
+        false,
+        blockhash_refresh_every,
+    )
+}
+
+// the payer covers the fees, from_user still signs as the sender
+fn fee_payer_and_signers<'a>(
+    from_user: &'a Keypair,
+    payer: Option<&'a Keypair>,
+) -> (Pubkey, Vec<&'a Keypair>) {
+    match payer {
+        Some(payer) => (payer.pubkey(), vec![payer, from_user]),
+        None => (from_user.pubkey(), vec![from_user]),
+    }
+}
+
+// with content_ids the program derives every id from the message itself and refuses repeats; a
+// blockhash only lasts about a minute, so long sends fetch a new one every few batches
+#[allow(clippy::too_many_arguments)]
+fn send_batches(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    from_user: &Keypair,
+    payer: Option<&Keypair>,
+    to_user: &Pubkey,
+    messages: Vec<Message>,
+    compute_budget: &ComputeBudget,
+    content_ids: bool,
+    blockhash_refresh_every: Option<NonZeroUsize>,
+) -> Result<Vec<Signature>, Box<dyn Error>> {
+    // the overhead doesn't depend on the limit itself, only on there being one
+    let max_data_size =
+        MAX_SEND_INSTRUCTION_DATA_SIZE - compute_budget.for_send(0).transaction_overhead();
+    let batches = split_into_batches(messages, max_data_size)?;
+    let batch_count = batches.len();
+    let refresh_every = blockhash_refresh_every.map_or(1, NonZeroUsize::get);
+    let mut hash = Hash::default();
+    let mut signatures = vec![];
+
+    for (batch_number, messages) in (1..).zip(batches) {
+        let message_count = messages.len();
+        let ids: Vec<u32> = messages.iter().map(Message::content_id).collect();
+        let instruction = build_send_instruction(
+            program_id,
+            &from_user.pubkey(),
+            to_user,
+            messages,
+            content_ids,
+        )?;
+
+        if (batch_number - 1) % refresh_every == 0 {
+            hash = rpc_client.get_latest_blockhash()?;
+        }
+
+        let (fee_payer, signers) = fee_payer_and_signers(from_user, payer);
+        let transaction = Transaction::new_signed_with_payer(
+            &compute_budget
+                .for_send(message_count)
+                .with_instruction(instruction),
+            Some(&fee_payer),
+            &signers,
+            hash,
+        );
+        report_fee(rpc_client, &transaction);
+
+        match rpc_client.send_and_confirm_transaction_with_spinner(&transaction) {
+            Ok(sig) => {
+                println!(
+                    "Batch {}/{} with {} messages sent",
+                    batch_number, batch_count, message_count
+                );
+                println!("Signature: {}", sig);
+                if content_ids {
+                    println!("sent message ids {:?}", ids);
+                } else {
+                    report_assigned_ids(rpc_client, &sig);
+                }
+                signatures.push(sig);
+            }
+            Err(err) => {
+                error!(
+                    "Got Error in batch {}/{}: {:?}",
+                    batch_number, batch_count, err
+                );
+                return Err(Box::new(err));
+            }
+        }
+    }
+
+    Ok(signatures)
+}
+
+pub fn send_message(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    from_user: &Keypair,
+    to_user: &Pubkey,
+    msgs: Vec<String>,
+    options: &SendOptions,
+) -> Result<(), Box<dyn Error>> {
+    // from_user is the system account that pays, its own chat account is only needed for replies
+    if let Some(auto_open) = &options.auto_open {
+        let from_user_chat_pk =
+            infer_chat_account_pubkey(&from_user.pubkey(), program_id, &auto_open.strategy)?;
+        if !is_account_opened(rpc_client, &from_user_chat_pk) {
+            println!("Opening your account {} first", from_user_chat_pk);
+            open_account(
+                rpc_client,
+                program_id,
+                &auto_open.strategy,
+                from_user,
+                options.payer.as_ref(),
+                &auto_open.account_name,
+                None,
+                &options.compute_budget,
+            )?;
+        }
+    }
+    let to_account = match options.wait_for_account_timeout {
+        Some(timeout) => wait_for_account(rpc_client, to_user, timeout, ACCOUNT_POLL_INTERVAL)?,
+        None => rpc_client
+            .get_account(to_user)
+            .map_err(|err| ChatClientError::from_account_read(to_user, err))?,
+    };
+    if !options.allow_self && is_own_account(to_user, &to_account, &from_user.pubkey()) {
+        return Err(Box::new(ChatClientError::SelfSend(*to_user)));
+    }
+    // messages name the user they're for, not the account they're stored in, so an inbox matches
+    // them against its own pubkey; the program refuses whatever isn't a chat account anyway
+    let recipient = account_owner(to_user, &to_account).unwrap_or(*to_user);
+    // part of the content id, a retry of this send keeps it and is caught as a repeat
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let mut messages = vec![];
+    for mut msg in msgs {
+        if options.sanitize {
+            msg = strip_controls(&msg);
+        }
+        let mut builder = MessageBuilder::new()
+            .from(from_user.pubkey())
+            .to(recipient)
+            .timestamp(timestamp)
+            .body(&msg)
+            .attachment_hash(options.attachment_hash);
+        if let Some(reply_to) = options.reply_to {
+            builder = builder.reply_to(reply_to);
+        }
+        if let Some(ttl_secs) = options.ttl_secs {
+            builder = builder.expires_at(timestamp + ttl_secs as i64);
+        }
+        let mut message = builder.build()?;
+        if options.compress {
+            compress_message(&mut message);
+        }
+        messages.push(message);
+    }
+    // the program refuses what doesn't fit, better to know before the first batch lands
+    let messages_size: usize = messages.iter().map(Message::size).sum();
+    if free_space(to_user, &to_account).is_some_and(|free| messages_size > free) {
+        return Err(Box::new(ChatError::AccountFull));
+    }
+
+    send_batches(
+        rpc_client,
+        program_id,
+        from_user,
+        options.payer.as_ref(),
+        to_user,
+        messages,
+        &options.compute_budget,
+        options.content_ids,
+        options.blockhash_refresh_every,
+    )?;
+    println!("Transaction successed !");
+
+    Ok(())
+}
+
+// sends the same messages to the chat account of every user as strategy finds it, a recipient
+// that fails is reported and the rest still get theirs, returns the users it failed for
+pub fn broadcast_message(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    to_users: &[Pubkey],
+    msgs: Vec<String>,
+    options: &SendOptions,
+) -> Vec<Pubkey> {
+    to_users
+        .iter()
+        .filter(|user| {
+            let chat_account = match infer_chat_account_pubkey(user, program_id, strategy) {
+                Ok(chat_account) => chat_account,
+                Err(err) => {
+                    println!("Failed to find the account of {}: {}", user, err);
+                    return true;
+                }
+            };
+            let result = send_message(
+                rpc_client,
+                program_id,
+                from_user,
+                &chat_account,
+                msgs.clone(),
+                options,
+            );
+            match &result {
+                Ok(()) => println!("Sent to {} at {}", user, chat_account),
+                Err(err) => println!("Failed to send to {} at {}: {}", user, chat_account, err),
+            }
+            result.is_err()
+        })
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signer::keypair::Keypair;
+    use solana_sdk::signer::Signer;
+
+    use crate::account::{messages_after, Cursor};
+    use crate::error::ChatClientError;
+    use crate::export::OutputFormat;
+    use crate::rpc::fake::FakeRpc;
+    use crate::rpc::CliRpc;
+
+    use solana_client::rpc_config::RpcSendTransactionConfig;
+
+    use std::str::FromStr;
+
+    use md::error::ChatError;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::transaction::TransactionError;
+
+    use md::data::{
+        serialize_messages, AccountMetadata, ChatCommand, ChatData, ChatInstruction, Message,
+        Reaction, CHAT_ACCOUNT_SIZE, DEFAULT_SEED,
+    };
+
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    use fprog::instructions::{find_chat_account_pda, AddressStrategy};
+
+    use super::{
+        account_exists, account_header, airdrop, broadcast_message, chat_account_to_read,
+        estimate_fee, estimated_send_units, init_account, matching_messages, message_line,
+        newest_first, open_account, page_after, parse_assigned_ids, reaction_summary,
+        receive_messages, send_message, send_messages_batched, split_into_batches, strip_controls,
+        truncate_chars, unexpired, wait_for_account, wait_for_message, AutoOpen, ComputeBudget,
+        ReceiveOptions, SendOptions, SenderNames, ESTIMATED_OPEN_ACCOUNT_FEE,
+        MAINNET_BETA_GENESIS_HASH,
+    };
+
+    #[test]
+    fn owner_pubkey_changes_read_account() {
+        let program_pk = Pubkey::new_unique();
+        let user_pk = Pubkey::new_unique();
+        let owner_pk = Pubkey::new_unique();
+
+        let own_account =
+            chat_account_to_read(&user_pk, None, &program_pk, &AddressStrategy::default()).unwrap();
+        let owner_account = chat_account_to_read(
+            &user_pk,
+            Some(&owner_pk),
+            &program_pk,
+            &AddressStrategy::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            own_account,
+            find_chat_account_pda(&user_pk, &program_pk, DEFAULT_SEED).0
+        );
+        assert_eq!(
+            owner_account,
+            find_chat_account_pda(&owner_pk, &program_pk, DEFAULT_SEED).0
+        );
+        assert_ne!(own_account, owner_account);
+    }
+
+    #[test]
+    fn seeds_derive_different_accounts() {
+        let program_pk = Pubkey::new_unique();
+        let user_pk = Pubkey::new_unique();
+
+        let (default_account, _) = find_chat_account_pda(&user_pk, &program_pk, DEFAULT_SEED);
+        let (work_account, _) = find_chat_account_pda(&user_pk, &program_pk, "work");
+        let (friends_account, _) = find_chat_account_pda(&user_pk, &program_pk, "friends");
+
+        assert_ne!(default_account, work_account);
+        assert_ne!(work_account, friends_account);
+        let work = AddressStrategy::Pda("work".to_string());
+        assert_eq!(
+            work_account,
+            chat_account_to_read(&user_pk, None, &program_pk, &work).unwrap()
+        );
+        // an explicit address is read as given, whoever the owner is
+        let explicit = AddressStrategy::Explicit(friends_account);
+        assert_eq!(
+            chat_account_to_read(&user_pk, Some(&default_account), &program_pk, &explicit).unwrap(),
+            friends_account
+        );
+    }
+
+    #[test]
+    fn open_account_tolerates_concurrent_open() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let (chat_pk, bump) = find_chat_account_pda(&from_user.pubkey(), &program_id, DEFAULT_SEED);
+        let account_metadata = AccountMetadata::new("abc", bump).unwrap();
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..account_metadata.size()])
+            .unwrap();
+
+        // another run opens the account after our check, so our transaction is rejected
+        let mut rpc = FakeRpc {
+            send_error: Some(TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(ChatError::AccountAlreadyInitialized as u32),
+            )),
+            ..FakeRpc::default()
+        };
+        rpc.add_account(from_user.pubkey(), 1_000_000, vec![]);
+        rpc.add_account(chat_pk, 0, data);
+        rpc.hidden_reads.borrow_mut().insert(chat_pk, 1);
+
+        let open = |rpc: &FakeRpc| {
+            open_account(
+                rpc,
+                &program_id,
+                &AddressStrategy::default(),
+                &from_user,
+                None,
+                "abc",
+                None,
+                &ComputeBudget::default(),
+            )
+        };
+        open(&rpc).unwrap();
+        assert_eq!(rpc.sent.borrow().len(), 1);
+
+        // the same failure with nothing opened is still reported
+        rpc.accounts.remove(&chat_pk);
+        assert!(open(&rpc).is_err());
+    }
+
+    #[test]
+    fn account_exists_tells_missing_from_unreadable() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let (chat_pk, _) = find_chat_account_pda(&from_user.pubkey(), &program_id, DEFAULT_SEED);
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(from_user.pubkey(), 10_000_000_000, vec![]);
+
+        assert_eq!(account_exists(&rpc, &chat_pk), Ok(false));
+        rpc.add_account(chat_pk, 0, vec![]);
+        assert_eq!(account_exists(&rpc, &chat_pk), Ok(true));
+
+        rpc.failing_reads.borrow_mut().insert(chat_pk, 1);
+        assert!(matches!(
+            account_exists(&rpc, &chat_pk),
+            Err(ChatClientError::Rpc(_))
+        ));
+
+        // a failed read of the account doesn't make open try to create it again
+        rpc.failing_reads.borrow_mut().insert(chat_pk, 1);
+        let result = open_account(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            None,
+            "abc",
+            None,
+            &ComputeBudget::default(),
+        );
+        assert!(result.is_err());
+        assert!(rpc.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn welcome_message_shares_the_open_transaction() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let (chat_pk, bump) = find_chat_account_pda(&from_user.pubkey(), &program_id, DEFAULT_SEED);
+        let account_metadata = AccountMetadata::new("abc", bump).unwrap();
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..account_metadata.size()])
+            .unwrap();
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(from_user.pubkey(), 1_000_000, vec![]);
+        // missing for the existence check, there for the read-back
+        rpc.add_account(chat_pk, 0, data);
+        let open = |rpc: &FakeRpc, welcome_message: &str| {
+            rpc.hidden_reads.borrow_mut().insert(chat_pk, 1);
+            open_account(
+                rpc,
+                &program_id,
+                &AddressStrategy::default(),
+                &from_user,
+                None,
+                "abc",
+                Some(welcome_message),
+                &ComputeBudget::default(),
+            )
+        };
+
+        open(&rpc, "hello everyone").unwrap();
+
+        let sent = rpc.sent.borrow();
+        let instructions: Vec<ChatInstruction> = sent[0]
+            .message
+            .instructions
+            .iter()
+            .map(|instruction| ChatInstruction::deserialize(&instruction.data[..]).unwrap())
+            .collect();
+        assert_eq!(
+            instructions,
+            vec![
+                ChatInstruction::OpenAccount {
+                    account_metadata: AccountMetadata {
+                        initialized: 0,
+                        ..account_metadata
+                    }
+                },
+                ChatInstruction::SendMessages {
+                    messages: vec![Message::new(
+                        0,
+                        from_user.pubkey(),
+                        "hello everyone".to_string()
+                    )],
+                },
+            ]
+        );
+        drop(sent);
+
+        let err = open(&rpc, &"x".repeat(1000)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ChatClientError>(),
+            Some(ChatClientError::MessageTooLarge { .. })
+        ));
+        assert_eq!(rpc.sent.borrow().len(), 1);
+    }
+
+    #[test]
+    fn init_account_fills_in_a_created_but_uninitialized_account() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let (chat_pk, bump) = find_chat_account_pda(&from_user.pubkey(), &program_id, "work");
+        let mut rpc = FakeRpc::default();
+        let init = |rpc: &FakeRpc| {
+            init_account(
+                rpc,
+                &program_id,
+                &AddressStrategy::Pda("work".to_string()),
+                &from_user,
+                None,
+                "me",
+                &ComputeBudget::default(),
+            )
+        };
+
+        // nothing to initialize yet
+        assert!(init(&rpc).is_err());
+
+        rpc.add_account(chat_pk, 0, vec![0; CHAT_ACCOUNT_SIZE]);
+        init(&rpc).unwrap();
+        let sent = rpc.sent.borrow().clone();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            ChatInstruction::deserialize(&sent[0].message.instructions[0].data).unwrap(),
+            ChatInstruction::OpenAccount {
+                account_metadata: AccountMetadata {
+                    initialized: 0,
+                    ..AccountMetadata::with_seed("me", "work", bump).unwrap()
+                },
+            }
+        );
+
+        // once the metadata is there nothing is sent
+        let account_metadata = AccountMetadata::with_seed("me", "work", bump).unwrap();
+        let mut data = vec![0; CHAT_ACCOUNT_SIZE];
+        account_metadata
+            .serialize(&mut data[..account_metadata.size()])
+            .unwrap();
+        rpc.add_account(chat_pk, 0, data);
+        init(&rpc).unwrap();
+        assert_eq!(rpc.sent.borrow().len(), 1);
+    }
+
+    #[test]
+    fn auto_open_opens_a_missing_sender_account() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let to_user = Pubkey::new_unique();
+        let (chat_pk, bump) = find_chat_account_pda(&from_user.pubkey(), &program_id, DEFAULT_SEED);
+        let account_metadata = AccountMetadata::new("me", bump).unwrap();
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..account_metadata.size()])
+            .unwrap();
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(from_user.pubkey(), 1_000_000, vec![]);
+        rpc.add_account(to_user, 0, vec![]);
+        // missing for the auto-open and open_account checks, there once the open lands
+        rpc.add_account(chat_pk, 0, data);
+        rpc.hidden_reads.borrow_mut().insert(chat_pk, 2);
+        let options = SendOptions {
+            auto_open: Some(AutoOpen {
+                strategy: AddressStrategy::default(),
+                account_name: "me".to_string(),
+            }),
+            ..SendOptions::default()
+        };
+        let send = |rpc: &FakeRpc| {
+            send_message(
+                rpc,
+                &program_id,
+                &from_user,
+                &to_user,
+                vec!["hi".to_string()],
+                &options,
+            )
+        };
+
+        send(&rpc).unwrap();
+
+        let commands = |rpc: &FakeRpc| -> Vec<ChatCommand> {
+            rpc.sent
+                .borrow()
+                .iter()
+                .map(|transaction| {
+                    let data = &transaction.message.instructions.last().unwrap().data[..];
+                    ChatInstruction::deserialize(data).unwrap().command()
+                })
+                .collect()
+        };
+        assert_eq!(
+            commands(&rpc),
+            vec![ChatCommand::OpenAccount, ChatCommand::SendMessages]
+        );
+
+        // once it exists only the message goes out
+        rpc.sent.borrow_mut().clear();
+        send(&rpc).unwrap();
+        assert_eq!(commands(&rpc), vec![ChatCommand::SendMessages]);
+    }
+
+    #[test]
+    fn no_confirm_fires_and_forgets() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let to_user = Pubkey::new_unique();
+        let mut client = FakeRpc::default();
+        client.add_account(from_user.pubkey(), 1_000_000, vec![]);
+        client.add_account(to_user, 0, vec![]);
+        let rpc = CliRpc {
+            client,
+            confirm: false,
+            simulate: false,
+            send_config: RpcSendTransactionConfig::default(),
+            cache: Default::default(),
+        };
+
+        send_message(
+            &rpc,
+            &program_id,
+            &from_user,
+            &to_user,
+            vec!["hi".to_string()],
+            &SendOptions::default(),
+        )
+        .unwrap();
+        // nothing reads the account back, it may not exist yet
+        open_account(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            None,
+            "abc",
+            None,
+            &ComputeBudget::default(),
+        )
+        .unwrap();
+
+        assert!(rpc.client.sent.borrow().is_empty());
+        assert_eq!(rpc.client.unconfirmed.borrow().len(), 2);
+    }
+
+    #[test]
+    fn open_account_with_low_balance() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let mut rpc = FakeRpc {
+            rent: 1_000_000,
+            ..FakeRpc::default()
+        };
+        rpc.add_account(from_user.pubkey(), 1_000, vec![]);
+
+        let err = open_account(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            None,
+            "abc",
+            None,
+            &ComputeBudget::default(),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<ChatClientError>(),
+            Some(&ChatClientError::InsufficientFunds {
+                required: 1_000_000 + ESTIMATED_OPEN_ACCOUNT_FEE,
+                available: 1_000,
+            })
+        );
+        assert!(err.to_string().contains("solana airdrop"));
+        assert!(rpc.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn open_account_from_unfunded_payer() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let rpc = FakeRpc {
+            rent: 1_000_000,
+            ..FakeRpc::default()
+        };
+        let compute_budget = ComputeBudget {
+            unit_price: Some(1_000_000),
+            unit_limit: Some(3_000),
+        };
+
+        let err = open_account(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            None,
+            "abc",
+            None,
+            &compute_budget,
+        )
+        .unwrap_err();
+
+        // no account yet reads as nothing to spend, and the priority fee is part of the bill
+        assert_eq!(
+            err.downcast_ref::<ChatClientError>(),
+            Some(&ChatClientError::InsufficientFunds {
+                required: 1_000_000 + ESTIMATED_OPEN_ACCOUNT_FEE + 3_000,
+                available: 0,
+            })
+        );
+        assert!(rpc.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn airdrop_refused_on_mainnet() {
+        let from_user = Keypair::new();
+        let rpc = FakeRpc {
+            genesis_hash: Hash::from_str(MAINNET_BETA_GENESIS_HASH).unwrap(),
+            ..FakeRpc::default()
+        };
+
+        let err = airdrop(&rpc, &from_user, 1_000).unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<ChatClientError>(),
+            Some(&ChatClientError::AirdropOnMainnet)
+        );
+        assert!(rpc.airdrops.borrow().is_empty());
+    }
+
+    #[test]
+    fn airdrop_on_local_network() {
+        let from_user = Keypair::new();
+        let rpc = FakeRpc::default();
+
+        airdrop(&rpc, &from_user, 1_000).unwrap();
+
+        assert_eq!(*rpc.airdrops.borrow(), vec![(from_user.pubkey(), 1_000)]);
+    }
+
+    #[test]
+    fn sender_names_are_cached() {
+        let program_pk = Pubkey::new_unique();
+        let alice = Pubkey::new_unique();
+        let stranger = Pubkey::new_unique();
+
+        let (alice_chat_pk, bump) = find_chat_account_pda(&alice, &program_pk, DEFAULT_SEED);
+        let account_metadata = AccountMetadata::new("Alice", bump).unwrap();
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..account_metadata.size()])
+            .unwrap();
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(alice_chat_pk, 0, data);
+
+        let mut sender_names = SenderNames::new(&rpc, program_pk, &AddressStrategy::default());
+        assert_eq!(sender_names.name(&alice), Some("Alice"));
+        assert_eq!(sender_names.name(&alice), Some("Alice"));
+        assert_eq!(sender_names.display_name(&stranger), stranger.to_string());
+        assert_eq!(sender_names.display_name(&stranger), stranger.to_string());
+
+        let alice_str = alice.to_string();
+        assert_eq!(
+            sender_names.display_name(&alice),
+            format!(
+                "Alice ({}…{})",
+                &alice_str[..3],
+                &alice_str[alice_str.len() - 3..]
+            )
+        );
+        assert_eq!(rpc.reads.borrow().len(), 2);
+        // an explicit address is only the account being read, senders get their default one
+        let explicit = AddressStrategy::Explicit(Pubkey::new_unique());
+        let mut sender_names = SenderNames::new(&rpc, program_pk, &explicit);
+        assert_eq!(sender_names.name(&alice), Some("Alice"));
+    }
+
+    fn chat_account_data(messages: &[Message]) -> Vec<u8> {
+        let mut account_metadata = AccountMetadata::new("abc", 0).unwrap();
+        let metadata_size = account_metadata.size();
+        let messages_size: usize = messages.iter().map(|message| message.size()).sum();
+        account_metadata.next_free_index = (metadata_size + messages_size) as u32;
+        account_metadata.message_count = messages.len() as u32;
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..metadata_size])
+            .unwrap();
+        serialize_messages(messages, &mut data[metadata_size..]).unwrap();
+        data
+    }
+
+    #[test]
+    fn receive_cursor_carries_across_polls() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let (chat_pk, _) = find_chat_account_pda(&from_user.pubkey(), &program_id, DEFAULT_SEED);
+        let sender = Pubkey::new_unique();
+        let mut messages = vec![
+            Message::new(1, sender, "first".to_string()),
+            Message::new(Message::CONTENT_ID_FLAG | 5, sender, "second".to_string()),
+        ];
+
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(chat_pk, 0, chat_account_data(&messages));
+        // the newest message alone is shown, the one it skipped doesn't come back later
+        let options = ReceiveOptions {
+            reverse: true,
+            limit: Some(1),
+            ..ReceiveOptions::default()
+        };
+        let mut cursor = Cursor::default();
+        receive_messages(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            &mut cursor,
+            &options,
+        )
+        .unwrap();
+        assert!(cursor.unseen(&messages).is_empty());
+
+        // it has the lowest id of all but landed last, so it's still new
+        messages.push(Message::new(2, sender, "third".to_string()));
+        rpc.add_account(chat_pk, 0, chat_account_data(&messages));
+        assert_eq!(cursor.unseen(&messages)[0].msg, "third");
+        receive_messages(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            &mut cursor,
+            &options,
+        )
+        .unwrap();
+        assert!(cursor.unseen(&messages).is_empty());
+    }
+
+    #[test]
+    fn receive_writes_json_file() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let (chat_pk, _) = find_chat_account_pda(&from_user.pubkey(), &program_id, DEFAULT_SEED);
+        let sender = Pubkey::new_unique();
+        let messages = vec![
+            Message::new(0, sender, "first".to_string()),
+            Message::new(1, sender, "second".to_string()),
+        ];
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(chat_pk, 0, chat_account_data(&messages));
+
+        let path = std::env::temp_dir().join(format!("fprog-receive-{}.json", chat_pk));
+        let mut options = ReceiveOptions {
+            format: OutputFormat::Json,
+            output_file: Some(path.clone()),
+            ..ReceiveOptions::default()
+        };
+        receive_messages(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            &mut Cursor::default(),
+            &options,
+        )
+        .unwrap();
+        receive_messages(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            &mut Cursor::default(),
+            &options,
+        )
+        .unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(json["metadata"]["account_name"], "abc");
+        assert_eq!(json["messages"][1]["msg"], "second");
+        assert_eq!(json["messages"][1]["from"], sender.to_string());
+
+        options.append = true;
+        receive_messages(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            &mut Cursor::default(),
+            &options,
+        )
+        .unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        for line in content.lines() {
+            serde_json::from_str::<serde_json::Value>(line).unwrap();
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pages_of_two_until_exhausted() {
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = (1..=5)
+            .map(|id| Message::new(id, sender, id.to_string()))
+            .collect();
+
+        let mut pages = vec![];
+        let mut after_id = None;
+        loop {
+            let (page, next_after) = page_after(messages_after(&messages, after_id), Some(2));
+            pages.push(page.iter().map(|message| message.id).collect::<Vec<_>>());
+            match next_after {
+                Some(next_after) => after_id = Some(next_after),
+                None => break,
+            }
+        }
+        assert_eq!(pages, vec![vec![1, 2], vec![3, 4], vec![5]]);
+
+        let (page, next_after) = page_after(messages_after(&messages, Some(3)), None);
+        assert_eq!(page.len(), 2);
+        assert_eq!(next_after, None);
+    }
+
+    #[test]
+    fn reverse_puts_the_newest_first() {
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = (1..=5)
+            .map(|id| Message::new(id, sender, id.to_string()))
+            .collect();
+        let ids = |page: Vec<&Message>| page.iter().map(|message| message.id).collect::<Vec<_>>();
+
+        let (page, _) = page_after(messages_after(&messages, None), Some(2));
+        assert_eq!(ids(page), vec![1, 2]);
+        let page = newest_first(messages_after(&messages, None), None);
+        assert_eq!(ids(page), vec![5, 4, 3, 2, 1]);
+        let page = newest_first(messages_after(&messages, None), Some(2));
+        assert_eq!(ids(page), vec![5, 4]);
+        let page = newest_first(messages_after(&messages, Some(3)), Some(5));
+        assert_eq!(ids(page), vec![5, 4]);
+    }
+
+    #[test]
+    fn expired_messages_are_hidden() {
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = [None, Some(99), Some(100), Some(101)]
+            .into_iter()
+            .enumerate()
+            .map(|(id, expires_at)| Message {
+                expires_at,
+                ..Message::new(id as u32 + 1, sender, "hi".to_string())
+            })
+            .collect();
+
+        let ids: Vec<u32> = unexpired(messages_after(&messages, None), 100)
+            .iter()
+            .map(|message| message.id)
+            .collect();
+        assert_eq!(ids, vec![1, 4]);
+        assert_eq!(unexpired(messages_after(&messages, None), 0).len(), 4);
+    }
+
+    #[test]
+    fn shared_constants_not_redefined() {
+        // the client and the program must take these from md, not keep their own copies
+        let sources = [
+            include_str!("chat.rs"),
+            include_str!("main.rs"),
+            include_str!("../../program/src/processor.rs"),
+            include_str!("../../program/src/lib.rs"),
+        ];
+        let definitions = [
+            concat!("ACCOUNT_SIZE", ": "),
+            concat!("SEED", ": &"),
+            concat!("MAX_ACCOUNT_NAME_LEN", ": "),
+        ];
+        for source in sources {
+            for definition in definitions {
+                assert!(!source.contains(definition), "{} redefined", definition);
+            }
+        }
+    }
+
+    #[test]
+    fn search_matches_substrings() {
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = ["Lunch at noon?", "no lunch today", "Meeting moved"]
+            .iter()
+            .enumerate()
+            .map(|(id, msg)| Message::new(id as u32 + 1, sender, msg.to_string()))
+            .collect();
+        let ids = |matches: Vec<&Message>| matches.iter().map(|m| m.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(matching_messages(&messages, "lunch", false)), vec![2]);
+        assert_eq!(ids(matching_messages(&messages, "lunch", true)), vec![1, 2]);
+        assert_eq!(ids(matching_messages(&messages, "MEETING", true)), vec![3]);
+        assert!(matching_messages(&messages, "dinner", true).is_empty());
+    }
+
+    #[test]
+    fn reactions_grouped_by_emoji() {
+        let mut account_metadata = AccountMetadata::new("abc", 0).unwrap();
+        for (target_id, emoji) in [(1, "👍"), (2, "🎉"), (1, "👍"), (1, "❤")] {
+            let reaction = Reaction::new(target_id, Pubkey::new_unique(), emoji).unwrap();
+            account_metadata.reactions.push(reaction);
+        }
+
+        assert_eq!(
+            reaction_summary(&account_metadata, 1).as_deref(),
+            Some("👍 x2 ❤ x1")
+        );
+        assert_eq!(
+            reaction_summary(&account_metadata, 2).as_deref(),
+            Some("🎉 x1")
+        );
+        assert_eq!(reaction_summary(&account_metadata, 3), None);
+    }
+
+    #[test]
+    fn names_and_emoji_are_escaped_on_display() {
+        let pubkey = Pubkey::from_str("DidmGHY2FMXTPzxMhiMjNSzwuqcHhJ679yP4NdCQsoqM").unwrap();
+        let mut account_metadata = AccountMetadata::new("a\x1b[2Jb", 255).unwrap();
+        let reaction = Reaction::new(1, Pubkey::new_unique(), "\x07👍").unwrap();
+        account_metadata.reactions.push(reaction);
+
+        assert_eq!(
+            account_header(&pubkey, &account_metadata, 5120),
+            "Account 'a\\u{1b}[2Jb' (Did…oqM) — 0 messages, 80/5120 bytes used, opened @ 0"
+        );
+        assert_eq!(
+            reaction_summary(&account_metadata, 1).as_deref(),
+            Some("\\u{7}👍 x1")
+        );
+    }
+
+    #[test]
+    fn receive_header_and_message_lines() {
+        let pubkey = Pubkey::from_str("DidmGHY2FMXTPzxMhiMjNSzwuqcHhJ679yP4NdCQsoqM").unwrap();
+        let mut account_metadata = AccountMetadata::new("friends", 255).unwrap();
+        account_metadata.message_count = 12;
+        account_metadata.next_free_index = 3200;
+        account_metadata.created_at = 1_650_000_000;
+
+        assert_eq!(
+            account_header(&pubkey, &account_metadata, 5120),
+            "Account 'friends' (Did…oqM) — 12 messages, 3200/5120 bytes used, opened @ 1650000000"
+        );
+
+        let mut message = Message::new(7, pubkey, "hi there".to_string());
+        message.timestamp = 1_700_000_000;
+        assert_eq!(
+            message_line(&message, "alice", None, false),
+            "[7] alice @ 1700000000: hi there"
+        );
+        message.reply_to = Some(3);
+        assert_eq!(
+            message_line(&message, "alice", None, false),
+            "[7] alice @ 1700000000 (reply to 3): hi there"
+        );
+    }
+
+    #[test]
+    fn preview_truncates_on_char_boundaries() {
+        let msg = "héllo 👋🏽 wörld";
+        // byte 2 falls inside the é, byte 8 inside the wave
+        assert!(!msg.is_char_boundary(2) && !msg.is_char_boundary(8));
+
+        assert_eq!(truncate_chars(msg, 1), "h…");
+        assert_eq!(truncate_chars(msg, 7), "héllo 👋…");
+        assert_eq!(truncate_chars(msg, 8), "héllo 👋🏽…");
+        assert_eq!(truncate_chars(msg, 14), msg);
+        assert_eq!(truncate_chars(msg, 40), msg);
+
+        let message = Message::new(1, Pubkey::new_unique(), msg.to_string());
+        assert_eq!(
+            message_line(&message, "alice", Some(2), false),
+            "[1] alice @ 0: hé…"
+        );
+    }
+
+    #[test]
+    fn control_characters_are_escaped_on_display() {
+        // clears the screen and sets the title, then rings the bell
+        let msg = "\x1b[2J\x1b]0;pwned\x07line\u{9b}31m\r\nnext\tok\x7f";
+        let message = Message::new(1, Pubkey::new_unique(), msg.to_string());
+        let line = message_line(&message, "alice", None, false);
+        assert_eq!(
+            line,
+            "[1] alice @ 0: \\u{1b}[2J\\u{1b}]0;pwned\\u{7}line\\u{9b}31m\\u{d}\nnext\tok\\u{7f}"
+        );
+        assert!(!line.contains('\x1b') && !line.contains('\u{9b}'));
+        // a preview counts a control as one char and never splits its escape
+        assert_eq!(
+            message_line(&message, "alice", Some(1), false),
+            "[1] alice @ 0: \\u{1b}…"
+        );
+
+        assert_eq!(strip_controls(msg), "[2J]0;pwnedline31m\nnext\tok");
+    }
+
+    #[test]
+    fn sending_to_your_own_account_needs_allow_self() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let (own_pk, bump) = find_chat_account_pda(&from_user.pubkey(), &program_id, "work");
+        let mut account_metadata = AccountMetadata::with_seed("me", "work", bump).unwrap();
+        account_metadata.owner = from_user.pubkey();
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..account_metadata.size()])
+            .unwrap();
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(own_pk, 0, data);
+        let send = |options: &SendOptions| {
+            send_message(
+                &rpc,
+                &program_id,
+                &from_user,
+                &own_pk,
+                vec!["note to self".to_string()],
+                options,
+            )
+        };
+
+        let err = send(&SendOptions::default()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ChatClientError>(),
+            Some(&ChatClientError::SelfSend(own_pk))
+        );
+        assert!(rpc.sent.borrow().is_empty());
+
+        let options = SendOptions {
+            allow_self: true,
+            ..SendOptions::default()
+        };
+        send(&options).unwrap();
+        assert_eq!(rpc.sent.borrow().len(), 1);
+        // addressed to the owner, the account only says where the message is kept
+        let sent = ChatInstruction::deserialize(
+            &rpc.sent.borrow()[0]
+                .message
+                .instructions
+                .last()
+                .unwrap()
+                .data[..],
+        )
+        .unwrap();
+        match sent {
+            ChatInstruction::SendMessages { messages } => {
+                assert_eq!(messages[0].to, from_user.pubkey())
+            }
+            other => panic!("unexpected instruction {:?}", other),
+        }
+    }
+
+    #[test]
+    fn broadcast_carries_on_past_a_failed_recipient() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let users = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let mut rpc = FakeRpc::default();
+        // the second user never opened an account
+        for user in [users[0], users[2]] {
+            let (chat_pk, _) = find_chat_account_pda(&user, &program_id, DEFAULT_SEED);
+            rpc.add_account(chat_pk, 0, vec![]);
+        }
+
+        let failed = broadcast_message(
+            &rpc,
+            &program_id,
+            &AddressStrategy::default(),
+            &from_user,
+            &users,
+            vec!["hi all".to_string()],
+            &SendOptions::default(),
+        );
+        assert_eq!(failed, vec![users[1]]);
+        assert_eq!(rpc.sent.borrow().len(), 2);
+    }
+
+    #[test]
+    fn separate_payer_signs_and_pays_the_fee() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let payer = Keypair::new();
+        let payer_pk = payer.pubkey();
+        let to_user = Pubkey::new_unique();
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(to_user, 0, vec![]);
+        let options = SendOptions {
+            payer: Some(payer),
+            ..SendOptions::default()
+        };
+
+        send_message(
+            &rpc,
+            &program_id,
+            &from_user,
+            &to_user,
+            vec!["hi".to_string()],
+            &options,
+        )
+        .unwrap();
+
+        let sent = rpc.sent.borrow();
+        let message = &sent[0].message;
+        assert_eq!(message.header.num_required_signatures, 2);
+        assert_eq!(message.account_keys[..2], [payer_pk, from_user.pubkey()]);
+        assert!(sent[0].verify().is_ok());
+    }
+
+    #[test]
+    fn fee_is_estimated_for_each_transaction() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let rpc = FakeRpc {
+            fee: 5_000,
+            ..FakeRpc::default()
+        };
+        let messages = vec![Message::new(0, from_user.pubkey(), "hi".to_string())];
+
+        send_messages_batched(
+            &rpc,
+            &program_id,
+            &from_user,
+            &Pubkey::new_unique(),
+            messages,
+            &ComputeBudget::default(),
+            None,
+        )
+        .unwrap();
+
+        let sent = rpc.sent.borrow();
+        assert_eq!(*rpc.priced.borrow(), vec![sent[0].message.clone()]);
+        assert_eq!(estimate_fee(&rpc, &sent[0]).unwrap(), 5_000);
+    }
+
+    #[test]
+    fn priority_fee_instruction_comes_first() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let to_user = Pubkey::new_unique();
+        let rpc = FakeRpc::default();
+        let messages = vec![Message::new(0, from_user.pubkey(), "hi".to_string())];
+        let compute_budget = ComputeBudget {
+            unit_price: Some(5_000),
+            unit_limit: None,
+        };
+
+        send_messages_batched(
+            &rpc,
+            &program_id,
+            &from_user,
+            &to_user,
+            messages.clone(),
+            &compute_budget,
+            None,
+        )
+        .unwrap();
+        send_messages_batched(
+            &rpc,
+            &program_id,
+            &from_user,
+            &to_user,
+            messages,
+            &ComputeBudget::default(),
+            None,
+        )
+        .unwrap();
+
+        let sent = rpc.sent.borrow();
+        let program_ids = |transaction: &solana_sdk::transaction::Transaction| {
+            transaction
+                .message
+                .instructions
+                .iter()
+                .map(|inst| *inst.program_id(&transaction.message.account_keys))
+                .collect::<Vec<_>>()
+        };
+        // the estimated unit limit goes in front of the price
+        let compute_budget_id = solana_sdk::compute_budget::id();
+        assert_eq!(
+            program_ids(&sent[0]),
+            vec![compute_budget_id, compute_budget_id, program_id]
+        );
+        assert_eq!(program_ids(&sent[1]), vec![compute_budget_id, program_id]);
+    }
+
+    #[test]
+    fn compute_limit_scales_with_batch_size() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let to_user = Pubkey::new_unique();
+        let rpc = FakeRpc::default();
+        let messages = |count: u32| -> Vec<Message> {
+            (0..count)
+                .map(|i| Message::new(i, from_user.pubkey(), "hi".to_string()))
+                .collect()
+        };
+        let send = |messages: Vec<Message>, compute_budget: &ComputeBudget| {
+            send_messages_batched(
+                &rpc,
+                &program_id,
+                &from_user,
+                &to_user,
+                messages,
+                compute_budget,
+                None,
+            )
+            .unwrap()
+        };
+
+        send(messages(1), &ComputeBudget::default());
+        send(messages(4), &ComputeBudget::default());
+        let explicit = ComputeBudget {
+            unit_price: None,
+            unit_limit: Some(50_000),
+        };
+        send(messages(4), &explicit);
+
+        let limits: Vec<Vec<u8>> = rpc
+            .sent
+            .borrow()
+            .iter()
+            .map(|transaction| transaction.message.instructions[0].data.clone())
+            .collect();
+        let limit = |units| ComputeBudgetInstruction::set_compute_unit_limit(units).data;
+        assert_eq!(
+            limits,
+            vec![
+                limit(estimated_send_units(1)),
+                limit(estimated_send_units(4)),
+                limit(50_000),
+            ]
+        );
+        assert!(estimated_send_units(4) > estimated_send_units(1));
+        assert_eq!(estimated_send_units(usize::MAX), 1_400_000);
+    }
+
+    #[test]
+    fn blockhash_is_refetched_every_few_batches() {
+        let from_user = Keypair::new();
+        let rpc = FakeRpc::default();
+        // too long for two to share a transaction
+        let messages: Vec<Message> = (0..5)
+            .map(|i| Message::new(i, from_user.pubkey(), "x".repeat(600)))
+            .collect();
+
+        send_messages_batched(
+            &rpc,
+            &Pubkey::new_unique(),
+            &from_user,
+            &Pubkey::new_unique(),
+            messages,
+            &ComputeBudget::default(),
+            NonZeroUsize::new(2),
+        )
+        .unwrap();
+
+        assert_eq!(*rpc.blockhash_fetches.borrow(), 3);
+        let hashes: Vec<u8> = rpc
+            .sent
+            .borrow()
+            .iter()
+            .map(|transaction| transaction.message.recent_blockhash.to_bytes()[0])
+            .collect();
+        assert_eq!(hashes, vec![1, 1, 2, 2, 3]);
+    }
+
+    #[test]
+    fn batches_split_on_the_size_limit() {
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = (0..5)
+            .map(|i| Message::new(i, sender, "x".repeat(40)))
+            .collect();
+        let message_size = messages[0].size();
+
+        // room for two messages and a bit, so the third one straddles the boundary
+        let max_data_size = 1 + message_size * 2 + message_size / 2;
+        let batches = split_into_batches(messages.clone(), max_data_size).unwrap();
+        assert_eq!(
+            batches.iter().map(|batch| batch.len()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        assert_eq!(batches.concat(), messages);
+
+        // an exact fit stays in one batch
+        let batches = split_into_batches(messages.clone(), 1 + message_size * 5).unwrap();
+        assert_eq!(batches.len(), 1);
+
+        let err = split_into_batches(messages, message_size).unwrap_err();
+        assert_eq!(
+            err,
+            ChatClientError::MessageTooLarge {
+                size: message_size,
+                max: message_size - 1,
+            }
+        );
+    }
+
+    #[test]
+    fn assigned_ids_from_logs() {
+        let logs = vec![
+            "Program DidmGHY2FMXTPzxMhiMjNSzwuqcHhJ679yP4NdCQsoqM invoke [1]".to_string(),
+            "Program log: SendMessages".to_string(),
+            "Program log: Assigned message ids 7..=9".to_string(),
+            "Program DidmGHY2FMXTPzxMhiMjNSzwuqcHhJ679yP4NdCQsoqM success".to_string(),
+        ];
+        assert_eq!(parse_assigned_ids(&logs), Some((7, 9)));
+
+        let logs = vec!["Program log: Assigned message ids 7..=7".to_string()];
+        assert_eq!(parse_assigned_ids(&logs), Some((7, 7)));
+
+        let logs = vec!["Program log: Assigned message ids 7..".to_string()];
+        assert_eq!(parse_assigned_ids(&logs), None);
+        assert_eq!(parse_assigned_ids(&[]), None);
+    }
+
+    #[test]
+    fn wait_for_account_polls_until_it_appears() {
+        let pubkey = Pubkey::new_unique();
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(pubkey, 42, vec![]);
+        rpc.hidden_reads.borrow_mut().insert(pubkey, 3);
+
+        let account = wait_for_account(
+            &rpc,
+            &pubkey,
+            Duration::from_secs(5),
+            Duration::from_millis(1),
+        )
+        .unwrap();
+
+        assert_eq!(account.lamports, 42);
+        assert_eq!(rpc.reads.borrow().len(), 4);
+    }
+
+    #[test]
+    fn wait_next_returns_the_first_message_stored_after_the_start() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let (chat_pk, _) = find_chat_account_pda(&from_user.pubkey(), &program_id, DEFAULT_SEED);
+        let sender = Pubkey::new_unique();
+        // the old message's content id is above anything sequential that lands later
+        let mut messages = vec![Message::new(
+            Message::CONTENT_ID_FLAG | 9,
+            sender,
+            "old".to_string(),
+        )];
+        let before = chat_account_data(&messages);
+        let cursor = Cursor::past(&messages);
+        messages.push(Message::new(2, sender, "new".to_string()));
+        messages.push(Message::new(3, sender, "newer".to_string()));
+
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(chat_pk, 0, chat_account_data(&messages));
+        // nothing new for the first three polls, two messages land at once on the fourth
+        rpc.stale_reads.borrow_mut().insert(chat_pk, (3, before));
+        let message = wait_for_message(
+            &rpc,
+            &chat_pk,
+            &cursor,
+            Duration::from_secs(5),
+            Duration::from_millis(1),
+        )
+        .unwrap();
+        assert_eq!(message.msg, "new");
+        assert_eq!(rpc.reads.borrow().len(), 4);
+
+        let err = wait_for_message(
+            &rpc,
+            &chat_pk,
+            &Cursor::past(&messages),
+            Duration::from_millis(20),
+            Duration::from_millis(1),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ChatClientError>(),
+            Some(&ChatClientError::MessageWaitTimeout { timeout_secs: 0 })
+        );
+    }
+
+    #[test]
+    fn wait_for_account_times_out() {
+        let pubkey = Pubkey::new_unique();
+        let rpc = FakeRpc::default();
+
+        let err = wait_for_account(
+            &rpc,
+            &pubkey,
+            Duration::from_millis(20),
+            Duration::from_millis(1),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<ChatClientError>(),
+            Some(&ChatClientError::AccountWaitTimeout {
+                pubkey,
+                timeout_secs: 0
+            })
+        );
+    }
+
+    #[test]
+    fn missing_account_is_told_apart_from_rpc_failure() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let (chat_pk, _) = find_chat_account_pda(&from_user.pubkey(), &program_id, DEFAULT_SEED);
+        let to_user = Pubkey::new_unique();
+        let receive = |rpc: &FakeRpc| {
+            receive_messages(
+                rpc,
+                &program_id,
+                &AddressStrategy::default(),
+                &from_user,
+                &mut Cursor::default(),
+                &ReceiveOptions::default(),
+            )
+            .unwrap_err()
+        };
+        let send = |rpc: &FakeRpc| {
+            send_message(
+                rpc,
+                &program_id,
+                &from_user,
+                &to_user,
+                vec!["hi".to_string()],
+                &SendOptions::default(),
+            )
+            .unwrap_err()
+        };
+
+        let rpc = FakeRpc::default();
+        assert_eq!(
+            receive(&rpc).downcast_ref::<ChatClientError>(),
+            Some(&ChatClientError::AccountNotOpened(chat_pk))
+        );
+        assert_eq!(
+            send(&rpc).downcast_ref::<ChatClientError>(),
+            Some(&ChatClientError::AccountNotOpened(to_user))
+        );
+
+        let rpc = FakeRpc {
+            unreachable: true,
+            ..FakeRpc::default()
+        };
+        for err in [receive(&rpc), send(&rpc)] {
+            assert!(matches!(
+                err.downcast_ref::<ChatClientError>(),
+                Some(ChatClientError::Rpc(_))
+            ));
+        }
+        assert!(rpc.sent.borrow().is_empty());
+    }
+}
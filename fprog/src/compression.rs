@@ -0,0 +1,76 @@
+use std::error::Error;
+
+use md::data::Message;
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec;
+
+use crate::error::ChatClientError;
+
+const COMPRESSION_LEVEL: u8 = 6;
+
+// Deflates the message body, keeping it uncompressed when that wouldn't save space
+pub fn compress_message(message: &mut Message) {
+    if message.compressed > 0 {
+        return;
+    }
+    let compressed_msg = compress_to_vec(message.msg.as_bytes(), COMPRESSION_LEVEL);
+    if compressed_msg.len() < message.msg.len() {
+        message.compressed = 1;
+        message.compressed_msg = compressed_msg;
+        message.msg.clear();
+    }
+}
+
+pub fn decompress_message(message: &mut Message) -> Result<(), Box<dyn Error>> {
+    if message.compressed == 0 {
+        return Ok(());
+    }
+    let msg = decompress_to_vec(&message.compressed_msg)
+        .map_err(|_e| ChatClientError::InvalidCompressedMessage(message.id))?;
+    if msg.len() != message.msg_size as usize {
+        return Err(Box::new(ChatClientError::InvalidCompressedMessage(
+            message.id,
+        )));
+    }
+    message.msg = String::from_utf8(msg)?;
+    message.compressed = 0;
+    message.compressed_msg.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::{ChatData, Message};
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::{compress_message, decompress_message};
+
+    #[test]
+    fn compressed_round_trip() {
+        let msg = "hello hello hello ".repeat(50);
+        let original = Message::new(3, Pubkey::new_unique(), msg.clone());
+        let mut message = Message::new(3, original.from, msg);
+
+        compress_message(&mut message);
+        assert_eq!(message.compressed, 1);
+        assert!(message.size() < original.size() / 4);
+
+        let mut data = vec![0; message.size()];
+        message.serialize(&mut data[..]).unwrap();
+        let mut message_new = Message::default();
+        message_new.deserialize(&data[..]).unwrap();
+
+        decompress_message(&mut message_new).unwrap();
+        assert_eq!(message_new, original);
+    }
+
+    #[test]
+    fn incompressible_message_is_left_alone() {
+        let mut message = Message::new(0, Pubkey::new_unique(), "hi".to_string());
+
+        compress_message(&mut message);
+
+        assert_eq!(message.compressed, 0);
+        assert_eq!(message.msg, "hi");
+    }
+}
@@ -0,0 +1,221 @@
+use arrayref::array_ref;
+use md::data::{AccountMetadata, Reaction};
+use solana_sdk::pubkey::{Pubkey, PUBKEY_BYTES};
+use solana_sdk::signer::keypair::Keypair;
+use solana_sdk::signer::Signer;
+use std::error::Error;
+use std::mem;
+use std::ops::Range;
+
+use fprog::instructions::AddressStrategy;
+
+use crate::chat::chat_account_to_read;
+use crate::rpc::ChatRpc;
+
+const U8_SIZE: usize = mem::size_of::<u8>();
+const U32_SIZE: usize = mem::size_of::<u32>();
+const I64_SIZE: usize = mem::size_of::<i64>();
+
+const BYTES_PER_LINE: usize = 16;
+
+// the fixed metadata header in storage order
+const HEADER_FIELDS: [(&str, usize); 11] = [
+    ("initialized", U8_SIZE),
+    ("bump", U8_SIZE),
+    ("next_free_index", U32_SIZE),
+    ("last_message_id", U32_SIZE),
+    ("message_count", U32_SIZE),
+    ("blocked_count", U32_SIZE),
+    ("reaction_count", U32_SIZE),
+    ("seed_len", U32_SIZE),
+    ("account_name_len", U32_SIZE),
+    ("owner", PUBKEY_BYTES),
+    ("created_at", I64_SIZE),
+];
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes(*array_ref![data, offset, U32_SIZE]) as usize
+}
+
+fn field_offset(name: &str) -> usize {
+    HEADER_FIELDS
+        .iter()
+        .take_while(|(field, _)| *field != name)
+        .map(|(_, size)| size)
+        .sum()
+}
+
+// byte ranges of the metadata fields and the regions after them, read from the raw lengths
+// rather than the codec so a corrupt account still gets annotated
+pub fn account_layout(data: &[u8]) -> Vec<(&'static str, Range<usize>)> {
+    let mut layout = vec![];
+    if data.len() < AccountMetadata::ACCOUNT_METADATA_BASE_SIZE {
+        return layout;
+    }
+    let mut start = 0;
+    for (name, size) in HEADER_FIELDS {
+        layout.push((name, start..start + size));
+        start += size;
+    }
+    let variable = [
+        (
+            "account_name",
+            read_u32(data, field_offset("account_name_len")),
+        ),
+        ("seed", read_u32(data, field_offset("seed_len"))),
+        (
+            "blocked_senders",
+            read_u32(data, field_offset("blocked_count")) * PUBKEY_BYTES,
+        ),
+        (
+            "reactions",
+            read_u32(data, field_offset("reaction_count")) * Reaction::REACTION_SIZE,
+        ),
+    ];
+    for (name, size) in variable {
+        if size > 0 {
+            layout.push((name, start..start + size));
+        }
+        start += size;
+    }
+    let metadata_size = match AccountMetadata::calculate_size_from_buffer(data) {
+        Ok(metadata_size) => metadata_size,
+        Err(_) => return layout,
+    };
+    let next_free_index = read_u32(data, field_offset("next_free_index"));
+    if next_free_index > metadata_size {
+        layout.push(("messages", metadata_size..next_free_index));
+    }
+    if data.len() > next_free_index.max(metadata_size) {
+        layout.push(("free", next_free_index.max(metadata_size)..data.len()));
+    }
+    layout
+}
+
+fn hex_line(offset: usize, bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let ascii: String = bytes
+        .iter()
+        .map(|byte| {
+            if byte.is_ascii_graphic() || *byte == b' ' {
+                *byte as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    format!("{:08x}  {:<47}  |{}|", offset, hex.join(" "), ascii)
+}
+
+// hexdump -C style, runs of identical lines collapse into a `*`, every field that starts on a
+// line is announced above it
+pub fn hex_dump(data: &[u8], layout: &[(&'static str, Range<usize>)]) -> Vec<String> {
+    let mut lines = vec![];
+    let mut previous: Option<&[u8]> = None;
+    let mut collapsed = false;
+    for (index, bytes) in data.chunks(BYTES_PER_LINE).enumerate() {
+        let offset = index * BYTES_PER_LINE;
+        let fields: Vec<String> = layout
+            .iter()
+            .filter(|(_, range)| (offset..offset + bytes.len()).contains(&range.start))
+            .map(|(name, range)| format!("-- {} {}..{}", name, range.start, range.end))
+            .collect();
+        if fields.is_empty() && previous == Some(bytes) {
+            if !collapsed {
+                lines.push("*".to_string());
+                collapsed = true;
+            }
+            continue;
+        }
+        lines.extend(fields);
+        lines.push(hex_line(offset, bytes));
+        previous = Some(bytes);
+        collapsed = false;
+    }
+    lines.push(format!("{:08x}", data.len()));
+    lines
+}
+
+pub fn dump_account(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    owner_pubkey: Option<&Pubkey>,
+    hex: bool,
+) -> Result<(), Box<dyn Error>> {
+    let chat_account =
+        chat_account_to_read(&from_user.pubkey(), owner_pubkey, program_id, strategy)?;
+    let data = rpc_client.get_account_data(&chat_account)?;
+    let layout = account_layout(&data[..]);
+
+    println!("{} holds {} bytes", chat_account, data.len());
+    if hex {
+        for line in hex_dump(&data[..], &layout) {
+            println!("{}", line);
+        }
+    } else {
+        for (name, range) in &layout {
+            println!("{:>6}..{:<6} {}", range.start, range.end, name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::{serialize_messages, AccountMetadata, ChatData, Message};
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::{account_layout, hex_dump};
+
+    #[test]
+    fn layout_follows_metadata_fields() {
+        let mut account_metadata = AccountMetadata::with_seed("abc", "work", 255).unwrap();
+        account_metadata.blocked_senders.push(Pubkey::new_unique());
+        account_metadata.blocked_count = 1;
+        let metadata_size = account_metadata.size();
+        let message = Message::new(1, Pubkey::new_unique(), "hi".to_string());
+        account_metadata.next_free_index = (metadata_size + message.size()) as u32;
+        let mut data = vec![0; 512];
+        account_metadata
+            .serialize(&mut data[..metadata_size])
+            .unwrap();
+        serialize_messages(&[message], &mut data[metadata_size..]).unwrap();
+
+        let layout = account_layout(&data[..]);
+
+        let range = |field: &str| {
+            layout
+                .iter()
+                .find(|(name, _)| *name == field)
+                .map(|(_, range)| range.clone())
+                .unwrap()
+        };
+        assert_eq!(range("initialized"), 0..1);
+        assert_eq!(range("next_free_index"), 2..6);
+        assert_eq!(
+            range("created_at"),
+            range("owner").end..AccountMetadata::ACCOUNT_METADATA_BASE_SIZE
+        );
+        assert_eq!(
+            range("account_name"),
+            AccountMetadata::ACCOUNT_METADATA_BASE_SIZE
+                ..AccountMetadata::ACCOUNT_METADATA_BASE_SIZE + 3
+        );
+        assert_eq!(range("seed").len(), 4);
+        assert_eq!(range("blocked_senders").len(), 32);
+        assert_eq!(range("blocked_senders").end, metadata_size);
+        assert_eq!(
+            range("messages"),
+            metadata_size..account_metadata.next_free_index as usize
+        );
+        assert_eq!(range("free").end, 512);
+        assert!(layout.iter().all(|(name, _)| *name != "reactions"));
+
+        let lines = hex_dump(&data[..], &layout);
+        assert_eq!(lines[0], "-- initialized 0..1");
+        assert!(lines.contains(&"*".to_string()));
+        assert_eq!(lines.last().unwrap(), "00000200");
+    }
+}
@@ -0,0 +1,222 @@
+use fprog::instructions::{
+    build_open_account_instructions, build_send_instruction, AddressStrategy,
+};
+use md::data::{ChatData, ChatInstruction, Message, MessageBuilder};
+use solana_sdk::pubkey::{Pubkey, PUBKEY_BYTES};
+use std::error::Error;
+use std::mem;
+use std::ops::Range;
+
+use crate::dump::account_layout;
+use crate::export::sha256_hex;
+
+const U8_SIZE: usize = mem::size_of::<u8>();
+const U32_SIZE: usize = mem::size_of::<u32>();
+const I64_SIZE: usize = mem::size_of::<i64>();
+
+// a message in storage order starting at offset, the optional fields only when their flag is set
+fn message_layout(message: &Message, offset: usize) -> Vec<(&'static str, Range<usize>)> {
+    let mut fields = vec![
+        ("id", U32_SIZE),
+        ("from", PUBKEY_BYTES),
+        ("to", PUBKEY_BYTES),
+        ("timestamp", I64_SIZE),
+        ("msg_size", U32_SIZE),
+        ("compressed", U8_SIZE),
+        ("flags", U8_SIZE),
+        ("stored_size", U32_SIZE),
+    ];
+    if message.attachment_hash.is_some() {
+        fields.push(("attachment_hash", Message::ATTACHMENT_HASH_SIZE));
+    }
+    if message.reply_to.is_some() {
+        fields.push(("reply_to", U32_SIZE));
+    }
+    if message.expires_at.is_some() {
+        fields.push(("expires_at", I64_SIZE));
+    }
+    fields.push(("body", message.stored_size()));
+
+    let mut start = offset;
+    fields
+        .into_iter()
+        .map(|(name, size)| {
+            start += size;
+            (name, start - size..start)
+        })
+        .collect()
+}
+
+// byte ranges of the fields in data, the serialized chat_instruction
+fn instruction_layout(
+    chat_instruction: &ChatInstruction,
+    data: &[u8],
+) -> Vec<(&'static str, Range<usize>)> {
+    let mut layout = vec![("tag", 0..U8_SIZE)];
+    match chat_instruction {
+        ChatInstruction::SendMessages { messages }
+        | ChatInstruction::SendUniqueMessages { messages } => {
+            let mut offset = U8_SIZE;
+            for message in messages {
+                layout.extend(message_layout(message, offset));
+                offset += message.size();
+            }
+        }
+        // the metadata goes over the wire exactly as it's stored
+        ChatInstruction::OpenAccount { .. } => {
+            layout.extend(
+                account_layout(&data[U8_SIZE..])
+                    .into_iter()
+                    .map(|(name, range)| (name, range.start + U8_SIZE..range.end + U8_SIZE)),
+            );
+        }
+        _ if chat_instruction.size() > U8_SIZE => {
+            layout.push(("payload", U8_SIZE..chat_instruction.size()));
+        }
+        _ => {}
+    }
+    layout
+}
+
+// the whole instruction data as hex, then a line per field with its range and bytes
+pub fn encoded_lines(data: &[u8]) -> Result<Vec<String>, Box<dyn Error>> {
+    let chat_instruction = ChatInstruction::deserialize(data)?;
+    let mut lines = vec![hex(data)];
+    for (name, range) in instruction_layout(&chat_instruction, data) {
+        lines.push(format!(
+            "{:>4}..{:<4} {:<16} {}",
+            range.start,
+            range.end,
+            name,
+            hex(&data[range.clone()])
+        ));
+    }
+    Ok(lines)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn print_lines(lines: Vec<String>) {
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+// the SendMessages data send would submit, ids and timestamps are left at 0 for the program.
+// offline there's no reading the account's owner, so messages name to_user where send names
+// the owner
+pub fn encode_send(
+    program_id: &Pubkey,
+    from_user: &Pubkey,
+    to_user: &Pubkey,
+    msgs: &[String],
+    attachment_hash: Option<[u8; 32]>,
+    reply_to: Option<u32>,
+    content_ids: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut messages = vec![];
+    for msg in msgs {
+        let mut builder = MessageBuilder::new()
+            .from(*from_user)
+            .to(*to_user)
+            .body(msg)
+            .attachment_hash(attachment_hash);
+        if let Some(reply_to) = reply_to {
+            builder = builder.reply_to(reply_to);
+        }
+        messages.push(builder.build()?);
+    }
+    if let Some(hash) = &attachment_hash {
+        println!("attachment {}", sha256_hex(hash));
+    }
+    let instruction =
+        build_send_instruction(program_id, from_user, to_user, messages, content_ids)?;
+    print_lines(encoded_lines(&instruction.data)?);
+    Ok(())
+}
+
+// the OpenAccount data open_account would submit for the account of owner under strategy
+pub fn encode_open_account(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    strategy: &AddressStrategy,
+    account_name: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (instructions, chat_account) =
+        build_open_account_instructions(program_id, owner, strategy, account_name, None)?;
+    println!("chat account {}", chat_account);
+    print_lines(encoded_lines(&instructions[0].data)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use fprog::instructions::{
+        build_open_account_instructions, build_send_instruction, AddressStrategy,
+    };
+    use md::data::MessageBuilder;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::encoded_lines;
+
+    #[test]
+    fn send_encodes_to_known_hex() {
+        let from = Pubkey::new_from_array([1; 32]);
+        let to = Pubkey::new_from_array([2; 32]);
+        let message = MessageBuilder::new()
+            .from(from)
+            .to(to)
+            .body("hi")
+            .reply_to(7)
+            .build()
+            .unwrap();
+        let instruction =
+            build_send_instruction(&Pubkey::new_unique(), &from, &to, vec![message], false)
+                .unwrap();
+
+        let lines = encoded_lines(&instruction.data).unwrap();
+        let expected = [
+            "00",
+            "00000000",
+            &"01".repeat(32),
+            &"02".repeat(32),
+            "0000000000000000",
+            "02000000",
+            "00",
+            "02",
+            "02000000",
+            "07000000",
+            "6869",
+        ]
+        .concat();
+        assert_eq!(lines[0], expected);
+        assert_eq!(lines[1], "   0..1    tag              00");
+        assert_eq!(lines[2], "   1..5    id               00000000");
+        assert_eq!(lines[10], "  87..91   reply_to         07000000");
+        assert_eq!(lines[11], "  91..93   body             6869");
+        assert_eq!(lines.len(), 12);
+    }
+
+    #[test]
+    fn open_account_reuses_the_metadata_layout() {
+        let (instructions, _) = build_open_account_instructions(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &AddressStrategy::Pda("work".to_string()),
+            "foo",
+            None,
+        )
+        .unwrap();
+        let lines = encoded_lines(&instructions[0].data).unwrap();
+        assert_eq!(lines[1], "   0..1    tag              02");
+        assert!(lines[2].starts_with("   1..2    initialized      00"));
+        assert!(lines
+            .iter()
+            .any(|line| line.ends_with(" account_name     666f6f")));
+        assert!(lines
+            .iter()
+            .any(|line| line.ends_with(" seed             776f726b")));
+    }
+}
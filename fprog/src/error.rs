@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::fmt;
+
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_request::RpcError;
+use solana_sdk::pubkey::Pubkey;
+
+#[derive(Debug, PartialEq)]
+pub enum ChatClientError {
+    InsufficientFunds { required: u64, available: u64 },
+    AirdropOnMainnet,
+    InvalidCompressedMessage(u32),
+    AccountWaitTimeout { pubkey: Pubkey, timeout_secs: u64 },
+    MessageWaitTimeout { timeout_secs: u64 },
+    MessageTooLarge { size: usize, max: usize },
+    AccountNotOpened(Pubkey),
+    SelfSend(Pubkey),
+    Rpc(String),
+}
+
+impl ChatClientError {
+    // RpcClient reports a missing account as a user-facing RPC error, tell it apart from a failed call
+    pub fn from_account_read(pubkey: &Pubkey, err: ClientError) -> Self {
+        match err.kind() {
+            ClientErrorKind::RpcError(RpcError::ForUser(msg))
+                if msg.starts_with("AccountNotFound") =>
+            {
+                ChatClientError::AccountNotOpened(*pubkey)
+            }
+            _ => ChatClientError::Rpc(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ChatClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatClientError::InsufficientFunds {
+                required,
+                available,
+            } => write!(
+                f,
+                "insufficient funds: need {} lamports but only {} available ({} short), \
+                 on devnet/localhost run `solana airdrop 1` first",
+                required,
+                available,
+                required - available
+            ),
+            ChatClientError::AirdropOnMainnet => {
+                write!(
+                    f,
+                    "airdrops are only available on localhost, devnet and testnet"
+                )
+            }
+            ChatClientError::InvalidCompressedMessage(id) => {
+                write!(f, "message {} can't be decompressed", id)
+            }
+            ChatClientError::AccountWaitTimeout {
+                pubkey,
+                timeout_secs,
+            } => write!(
+                f,
+                "account {} didn't appear within {} seconds",
+                pubkey, timeout_secs
+            ),
+            ChatClientError::MessageWaitTimeout { timeout_secs } => {
+                write!(f, "no new message within {} seconds", timeout_secs)
+            }
+            ChatClientError::MessageTooLarge { size, max } => write!(
+                f,
+                "message takes {} bytes but at most {} fit in one transaction",
+                size, max
+            ),
+            ChatClientError::AccountNotOpened(pubkey) => write!(
+                f,
+                "account {} doesn't exist, run `-c open_account` to create it",
+                pubkey
+            ),
+            ChatClientError::SelfSend(pubkey) => write!(
+                f,
+                "account {} is your own chat account, pass --allow-self to send to it anyway",
+                pubkey
+            ),
+            ChatClientError::Rpc(err) => write!(f, "rpc request failed: {}", err),
+        }
+    }
+}
+
+impl Error for ChatClientError {}
@@ -0,0 +1,160 @@
+use md::data::{AccountMetadata, Message};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+pub fn sha256_hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// --attachment-sha256 takes the digest the way sha256sum prints it
+pub fn parse_sha256(hex: &str) -> Result<[u8; 32], String> {
+    let invalid = || format!("{:?} isn't 64 hex digits", hex);
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(hash)
+}
+
+// messages are expected to be decompressed already, so only the text body is written
+pub fn account_json(account_metadata: &AccountMetadata, messages: &[&Message]) -> Value {
+    json!({
+        "metadata": {
+            "account_name": account_metadata.account_name,
+            "owner": account_metadata.owner.to_string(),
+            "bump": account_metadata.bump,
+            "created_at": account_metadata.created_at,
+            "last_message_id": account_metadata.last_message_id,
+            "message_count": account_metadata.message_count,
+            "blocked_senders": account_metadata
+                .blocked_senders
+                .iter()
+                .map(|sender| sender.to_string())
+                .collect::<Vec<_>>(),
+        },
+        "messages": messages
+            .iter()
+            .map(|message| {
+                json!({
+                    "id": message.id,
+                    "from": message.from.to_string(),
+                    "to": message.to.to_string(),
+                    "timestamp": message.timestamp,
+                    "msg": message.msg,
+                    "attachment_sha256": message.attachment_hash.as_ref().map(sha256_hex),
+                    "reactions": account_metadata
+                        .reactions_to(message.id)
+                        .map(|reaction| {
+                            json!({
+                                "from": reaction.from.to_string(),
+                                "emoji": reaction.emoji,
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+// quotes the field when it holds a separator, a quote or a line break, as RFC 4180 asks
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// one row per message with a header, lines end in CRLF like RFC 4180 spreadsheets expect
+pub fn messages_csv(messages: &[&Message]) -> String {
+    let mut csv = String::from("id,from,timestamp,msg\r\n");
+    for message in messages {
+        csv.push_str(&format!(
+            "{},{},{},{}\r\n",
+            message.id,
+            message.from,
+            message.timestamp,
+            csv_field(&message.msg)
+        ));
+    }
+    csv
+}
+
+pub fn write_csv(csv: &str, output_file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    match output_file {
+        Some(path) => fs::write(path, csv)?,
+        None => print!("{}", csv),
+    }
+    Ok(())
+}
+
+// one document per line, so appended dumps stay readable as JSON lines
+pub fn write_json(
+    value: &Value,
+    output_file: Option<&Path>,
+    append: bool,
+) -> Result<(), Box<dyn Error>> {
+    match output_file {
+        Some(path) => {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(path)?;
+            writeln!(file, "{}", value)?;
+        }
+        None => println!("{}", value),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::Message;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::{messages_csv, parse_sha256, sha256_hex};
+
+    #[test]
+    fn sha256_hex_round_trip() {
+        let hex = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let hash = parse_sha256(hex).unwrap();
+        assert_eq!(hash[0], 0xe3);
+        assert_eq!(sha256_hex(&hash), hex);
+        assert!(parse_sha256(&hex[2..]).is_err());
+        assert!(parse_sha256(&hex.replace('e', "g")).is_err());
+    }
+
+    #[test]
+    fn csv_escapes_quotes_and_commas() {
+        let from = Pubkey::new_unique();
+        let mut plain = Message::new(0, from, "hi".to_string());
+        plain.timestamp = 10;
+        let mut quoted = Message::new(1, from, "she said \"hi\", then left".to_string());
+        quoted.timestamp = 20;
+
+        assert_eq!(
+            messages_csv(&[&plain, &quoted]),
+            format!(
+                "id,from,timestamp,msg\r\n0,{from},10,hi\r\n1,{from},20,\"she said \"\"hi\"\", then left\"\r\n",
+                from = from
+            )
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::error::ChatClientError;
+use crate::rpc::ChatRpc;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, PartialEq)]
+pub struct HealthCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl HealthCheck {
+    fn new(name: &'static str, result: Result<String, String>) -> Self {
+        match result {
+            Ok(detail) => HealthCheck {
+                name,
+                ok: true,
+                detail,
+            },
+            Err(detail) => HealthCheck {
+                name,
+                ok: false,
+                detail,
+            },
+        }
+    }
+}
+
+// each check runs even when an earlier one failed, so one run shows everything that's off
+pub fn health_checks(rpc_client: &impl ChatRpc, program_id: &Pubkey) -> Vec<HealthCheck> {
+    let node = rpc_client
+        .get_health()
+        .map(|_| "node is healthy".to_string())
+        .map_err(|err| err.to_string());
+    let version = rpc_client
+        .get_version()
+        .map(|version| format!("solana-core {}", version))
+        .map_err(|err| err.to_string());
+    // a missing program usually means the RPC URL points at the wrong cluster
+    let program = match rpc_client.get_account(program_id) {
+        Ok(account) if account.executable => Ok(format!("{} is deployed", program_id)),
+        Ok(_) => Err(format!("{} exists but isn't executable", program_id)),
+        Err(err) => match ChatClientError::from_account_read(program_id, err) {
+            ChatClientError::AccountNotOpened(_) => {
+                Err(format!("{} isn't deployed on this cluster", program_id))
+            }
+            err => Err(err.to_string()),
+        },
+    };
+    vec![
+        HealthCheck::new("rpc", node),
+        HealthCheck::new("version", version),
+        HealthCheck::new("program", program),
+    ]
+}
+
+// prints a line per check, returns whether all of them passed
+pub fn health(rpc_client: &impl ChatRpc, program_id: &Pubkey) -> bool {
+    let checks = health_checks(rpc_client, program_id);
+    for check in &checks {
+        let (color, status) = if check.ok {
+            (GREEN, "OK")
+        } else {
+            (RED, "FAIL")
+        };
+        println!(
+            "{}{:<5}{} {:<8} {}",
+            color, status, RESET, check.name, check.detail
+        );
+    }
+    checks.iter().all(|check| check.ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::account::Account;
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::rpc::fake::FakeRpc;
+
+    use super::health_checks;
+
+    fn failed(rpc: &FakeRpc, program_id: &Pubkey) -> Vec<&'static str> {
+        health_checks(rpc, program_id)
+            .into_iter()
+            .filter(|check| !check.ok)
+            .map(|check| check.name)
+            .collect()
+    }
+
+    #[test]
+    fn program_must_be_deployed_and_executable() {
+        let program_id = Pubkey::new_unique();
+        let mut rpc = FakeRpc::default();
+
+        assert_eq!(failed(&rpc, &program_id), vec!["program"]);
+
+        rpc.add_account(program_id, 1, vec![]);
+        assert_eq!(failed(&rpc, &program_id), vec!["program"]);
+
+        let program = Account {
+            executable: true,
+            ..Account::default()
+        };
+        rpc.accounts.insert(program_id, program);
+        assert!(failed(&rpc, &program_id).is_empty());
+
+        rpc.unreachable = true;
+        assert_eq!(failed(&rpc, &program_id), vec!["rpc", "version", "program"]);
+    }
+}
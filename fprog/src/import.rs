@@ -0,0 +1,100 @@
+use log::warn;
+use md::data::{Message, MessageBuilder};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signer::keypair::Keypair;
+use solana_sdk::signer::Signer;
+use std::error::Error;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::str::FromStr;
+
+use fprog::instructions::AddressStrategy;
+
+use crate::chat::{infer_chat_account_pubkey, send_messages_batched, ComputeBudget};
+use crate::rpc::ChatRpc;
+
+fn parse_entry(entry: &Value) -> Option<Message> {
+    let from = Pubkey::from_str(entry.get("from")?.as_str()?).ok()?;
+    let msg = entry.get("msg")?.as_str()?;
+    let timestamp = entry.get("timestamp")?.as_i64()?;
+    MessageBuilder::new()
+        .from(from)
+        .timestamp(timestamp)
+        .body(msg)
+        .build()
+        .ok()
+}
+
+// keeps the file order, entries without a valid from, non-empty msg and timestamp are skipped
+pub fn parse_import(json: &str) -> Result<Vec<Message>, Box<dyn Error>> {
+    let entries: Vec<Value> = serde_json::from_str(json)?;
+    let mut messages = vec![];
+    for (index, entry) in entries.iter().enumerate() {
+        match parse_entry(entry) {
+            Some(message) => messages.push(message),
+            None => warn!("Skipping malformed entry {}: {}", index, entry),
+        }
+    }
+    Ok(messages)
+}
+
+// the program stamps ids and timestamps as the messages land, only order and senders carry over
+pub fn import_messages(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    path: &Path,
+    compute_budget: &ComputeBudget,
+    blockhash_refresh_every: Option<NonZeroUsize>,
+) -> Result<(), Box<dyn Error>> {
+    let messages = parse_import(&fs::read_to_string(path)?)?;
+    let chat_pk = infer_chat_account_pubkey(&from_user.pubkey(), program_id, strategy)?;
+    let count = messages.len();
+    if count > 0 {
+        send_messages_batched(
+            rpc_client,
+            program_id,
+            from_user,
+            &chat_pk,
+            messages,
+            compute_budget,
+            blockhash_refresh_every,
+        )?;
+    }
+    println!("Imported {} messages into {}", count, chat_pk);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::Message;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::parse_import;
+
+    #[test]
+    fn import_keeps_order_and_skips_malformed() {
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+        let json = format!(
+            r#"[
+                {{"from": "{alice}", "msg": "hi", "timestamp": 10}},
+                {{"from": "not a pubkey", "msg": "lost", "timestamp": 11}},
+                {{"from": "{bob}", "timestamp": 12}},
+                {{"from": "{bob}", "msg": "hello", "timestamp": 13}}
+            ]"#,
+            alice = alice,
+            bob = bob
+        );
+
+        let mut hi = Message::new(0, alice, "hi".to_string());
+        hi.timestamp = 10;
+        let mut hello = Message::new(0, bob, "hello".to_string());
+        hello.timestamp = 13;
+        assert_eq!(parse_import(&json).unwrap(), vec![hi, hello]);
+        assert!(parse_import("{}").is_err());
+    }
+}
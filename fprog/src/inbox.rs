@@ -0,0 +1,156 @@
+use md::data::{deserialize_account_data, Message};
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signer::keypair::Keypair;
+use solana_sdk::signer::Signer;
+use std::error::Error;
+
+use crate::chat::escape_controls;
+use crate::compression::decompress_message;
+use crate::rpc::ChatRpc;
+use crate::verify::verify_account_data;
+
+// messages addressed to user_pk across every chat account, oldest first; send addresses a
+// message to the owner of the account it's stored in
+pub fn inbox_messages(accounts: &[(Pubkey, Account)], user_pk: &Pubkey) -> Vec<(Pubkey, Message)> {
+    let mut inbox: Vec<(Pubkey, Message)> = accounts
+        .iter()
+        // closed, legacy or corrupt accounts would make the codec panic, so check them first
+        .filter(|(_, account)| verify_account_data(&account.data[..]).is_empty())
+        .filter_map(|(pubkey, account)| {
+            let (_, messages) = deserialize_account_data(&account.data[..]).ok()?;
+            Some((pubkey, messages?))
+        })
+        .flat_map(|(pubkey, messages)| {
+            messages
+                .into_iter()
+                .filter(|message| message.to == *user_pk)
+                .map(move |message| (*pubkey, message))
+        })
+        .collect();
+    inbox.sort_by_key(|(pubkey, message)| (message.timestamp, *pubkey, message.id));
+    inbox
+}
+
+// pages count from 1, a page past the end comes back empty
+pub fn page<T>(items: &[T], page: usize, page_size: usize) -> &[T] {
+    let start = page.saturating_sub(1).saturating_mul(page_size);
+    let end = start.saturating_add(page_size).min(items.len());
+    items.get(start..end).unwrap_or_default()
+}
+
+pub fn inbox(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    from_user: &Keypair,
+    page_number: usize,
+    page_size: usize,
+) -> Result<(), Box<dyn Error>> {
+    let accounts = rpc_client.get_program_accounts(program_id)?;
+    let mut messages = inbox_messages(&accounts, &from_user.pubkey());
+    for (_, message) in messages.iter_mut() {
+        decompress_message(message)?;
+    }
+
+    let page_count = messages.len().div_ceil(page_size.max(1));
+    println!(
+        "Inbox: {} messages, page {}/{}",
+        messages.len(),
+        page_number,
+        page_count
+    );
+    for (chat_account, message) in page(&messages, page_number, page_size) {
+        println!(
+            "[{}] {} in {} #{}: {}",
+            message.timestamp,
+            message.from,
+            chat_account,
+            message.id,
+            escape_controls(&message.msg)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::{serialize_messages, AccountMetadata, ChatData, Message, DEFAULT_SEED};
+    use solana_sdk::account::Account;
+    use solana_sdk::pubkey::Pubkey;
+
+    use fprog::instructions::find_chat_account_pda;
+
+    use super::{inbox_messages, page};
+
+    fn account(messages: &[Message]) -> Account {
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let metadata_size = account_metadata.size();
+        let messages_size: usize = messages.iter().map(|message| message.size()).sum();
+        account_metadata.next_free_index = (metadata_size + messages_size) as u32;
+        account_metadata.message_count = messages.len() as u32;
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..metadata_size])
+            .unwrap();
+        serialize_messages(messages, &mut data[metadata_size..]).unwrap();
+        Account {
+            data,
+            ..Account::default()
+        }
+    }
+
+    fn message(id: u32, to: Pubkey, timestamp: i64, msg: &str) -> Message {
+        let mut message = Message::new(id, Pubkey::new_unique(), msg.to_string());
+        message.to = to;
+        message.timestamp = timestamp;
+        message
+    }
+
+    #[test]
+    fn inbox_collects_messages_to_the_user() {
+        let program_id = Pubkey::new_unique();
+        let me = Pubkey::new_unique();
+        let (my_chat_account, _) = find_chat_account_pda(&me, &program_id, DEFAULT_SEED);
+        let someone = Pubkey::new_unique();
+        let group_a = Pubkey::new_unique();
+        let group_b = Pubkey::new_unique();
+        let accounts = vec![
+            (
+                group_a,
+                account(&[
+                    message(1, me, 30, "third"),
+                    message(2, someone, 10, "not mine"),
+                ]),
+            ),
+            (
+                group_b,
+                account(&[
+                    message(1, me, 20, "second"),
+                    message(2, me, 5, "first"),
+                    // the account a message is stored in isn't who it's for
+                    message(3, my_chat_account, 15, "not mine either"),
+                ]),
+            ),
+            (
+                Pubkey::new_unique(),
+                Account {
+                    data: vec![0; 16],
+                    ..Account::default()
+                },
+            ),
+        ];
+
+        let inbox = inbox_messages(&accounts, &me);
+        assert_eq!(
+            inbox
+                .iter()
+                .map(|(pubkey, message)| (*pubkey, message.msg.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(group_b, "first"), (group_b, "second"), (group_a, "third")]
+        );
+
+        assert_eq!(page(&inbox, 1, 2).len(), 2);
+        assert_eq!(page(&inbox, 2, 2)[0].1.msg, "third");
+        assert!(page(&inbox, 3, 2).is_empty());
+    }
+}
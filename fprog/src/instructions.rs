@@ -0,0 +1,289 @@
+use md::data::{AccountMetadata, ChatInstruction, Message, MessageBuilder, DEFAULT_SEED};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::{Pubkey, PubkeyError};
+use solana_sdk::system_program;
+use std::error::Error;
+
+// the signer first, then the chat account the instruction works on
+pub fn create_chat_instruction(
+    program: Pubkey,
+    from_account: Pubkey,
+    to_account: Pubkey,
+    chat_instruction: ChatInstruction,
+) -> Result<Instruction, Box<dyn Error>> {
+    let data_size = chat_instruction.size();
+    let mut instruction_data = vec![0; data_size];
+    chat_instruction.serialize(&mut instruction_data[..])?;
+    let ac_meta = AccountMeta::new(from_account, true);
+    let new_account_meta = AccountMeta::new(to_account, false);
+    Ok(Instruction::new_with_bytes(
+        program,
+        &instruction_data[..],
+        vec![ac_meta, new_account_meta],
+    ))
+}
+
+// the seed picks one of the user's accounts, the bump is the one OpenAccount stores
+pub fn find_chat_account_pda(user_pk: &Pubkey, program_pk: &Pubkey, seed: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[seed.as_bytes(), user_pk.as_ref()], program_pk)
+}
+
+// how the address of a user's chat account is found, so a deployment can pick its scheme
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressStrategy {
+    // Pubkey::create_with_seed off the user's key, how accounts were made before the PDAs
+    Seed(String),
+    // the program address of the seed and the user's key, what OpenAccount creates
+    Pda(String),
+    // an address handed in from elsewhere, nothing is derived
+    Explicit(Pubkey),
+}
+
+// the PDA under md::data::DEFAULT_SEED, what --seed defaults to
+impl Default for AddressStrategy {
+    fn default() -> Self {
+        AddressStrategy::Pda(DEFAULT_SEED.to_string())
+    }
+}
+
+impl AddressStrategy {
+    // OpenAccount only creates program addresses, the other kinds have no seed to open under
+    pub fn pda_seed(&self) -> Option<&str> {
+        match self {
+            AddressStrategy::Pda(seed) => Some(seed),
+            AddressStrategy::Seed(_) | AddressStrategy::Explicit(_) => None,
+        }
+    }
+}
+
+// the chat account of user_pk as strategy finds it, an explicit address ignores the user
+pub fn infer_chat_account_pubkey(
+    user_pk: &Pubkey,
+    program_pk: &Pubkey,
+    strategy: &AddressStrategy,
+) -> Result<Pubkey, PubkeyError> {
+    match strategy {
+        AddressStrategy::Seed(seed) => Pubkey::create_with_seed(user_pk, seed, program_pk),
+        AddressStrategy::Pda(seed) => Ok(find_chat_account_pda(user_pk, program_pk, seed).0),
+        AddressStrategy::Explicit(pubkey) => Ok(*pubkey),
+    }
+}
+
+// sends messages to the chat account to_account, from_user has to sign; with content_ids the
+// program derives every id from the message itself and refuses repeats
+pub fn build_send_instruction(
+    program_id: &Pubkey,
+    from_user: &Pubkey,
+    to_account: &Pubkey,
+    messages: Vec<Message>,
+    content_ids: bool,
+) -> Result<Instruction, Box<dyn Error>> {
+    let chat_instruction = if content_ids {
+        ChatInstruction::SendUniqueMessages { messages }
+    } else {
+        ChatInstruction::SendMessages { messages }
+    };
+    create_chat_instruction(*program_id, *from_user, *to_account, chat_instruction)
+}
+
+// opens the chat account of owner under a Pda strategy, the owner also has to sign and pays the
+// rent; the welcome message comes second, so in one transaction it lands only if the open does
+pub fn build_open_account_instructions(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    strategy: &AddressStrategy,
+    account_name: &str,
+    welcome_message: Option<&str>,
+) -> Result<(Vec<Instruction>, Pubkey), Box<dyn Error>> {
+    let seed = strategy
+        .pda_seed()
+        .ok_or("only an account at a program address can be opened")?;
+    let (account_pub_key, bump) = find_chat_account_pda(owner, program_id, seed);
+
+    let mut account_metadata = AccountMetadata::with_seed(account_name, seed, bump)?;
+    // the program sets it once it has checked the account isn't open yet
+    account_metadata.initialized = 0;
+
+    // the program creates the PDA itself, so it needs the system program
+    let mut open_account_inst = create_chat_instruction(
+        *program_id,
+        *owner,
+        account_pub_key,
+        ChatInstruction::OpenAccount { account_metadata },
+    )?;
+    open_account_inst
+        .accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+
+    let mut instructions = vec![open_account_inst];
+    if let Some(welcome_message) = welcome_message {
+        let messages = vec![MessageBuilder::new()
+            .from(*owner)
+            .body(welcome_message)
+            .build()?];
+        instructions.push(build_send_instruction(
+            program_id,
+            owner,
+            &account_pub_key,
+            messages,
+            false,
+        )?);
+    }
+    Ok((instructions, account_pub_key))
+}
+
+// one of DeleteMessages, DeleteRange, DeleteAll or PurgeExpired on owner's account as strategy
+// finds it, signed by the owner
+pub fn build_delete_instruction(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    strategy: &AddressStrategy,
+    chat_instruction: ChatInstruction,
+) -> Result<(Instruction, Pubkey), Box<dyn Error>> {
+    let chat_account = infer_chat_account_pubkey(owner, program_id, strategy)?;
+    let instruction = create_chat_instruction(*program_id, *owner, chat_account, chat_instruction)?;
+    Ok((instruction, chat_account))
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::{ChatInstruction, Message};
+    use solana_sdk::instruction::AccountMeta;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::system_program;
+
+    use super::{
+        build_delete_instruction, build_open_account_instructions, build_send_instruction,
+        find_chat_account_pda, infer_chat_account_pubkey, AddressStrategy,
+    };
+
+    #[test]
+    fn each_address_strategy_derives_its_own_address() {
+        let program_id = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let explicit = Pubkey::new_unique();
+
+        let seeded =
+            infer_chat_account_pubkey(&user, &program_id, &AddressStrategy::Seed("work".into()))
+                .unwrap();
+        assert_eq!(
+            seeded,
+            Pubkey::create_with_seed(&user, "work", &program_id).unwrap()
+        );
+        let pda =
+            infer_chat_account_pubkey(&user, &program_id, &AddressStrategy::Pda("work".into()))
+                .unwrap();
+        assert_eq!(pda, find_chat_account_pda(&user, &program_id, "work").0);
+        assert_ne!(seeded, pda);
+        assert_eq!(
+            infer_chat_account_pubkey(&user, &program_id, &AddressStrategy::Explicit(explicit))
+                .unwrap(),
+            explicit
+        );
+        assert!(infer_chat_account_pubkey(
+            &user,
+            &program_id,
+            &AddressStrategy::Seed("x".repeat(33))
+        )
+        .is_err());
+
+        // only a program address can be opened
+        for strategy in [
+            AddressStrategy::Seed("work".to_string()),
+            AddressStrategy::Explicit(explicit),
+        ] {
+            assert!(
+                build_open_account_instructions(&program_id, &user, &strategy, "me", None).is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn built_instructions_target_the_program_and_derived_accounts() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let work = AddressStrategy::Pda("work".to_string());
+        let (chat_account, bump) = find_chat_account_pda(&owner, &program_id, "work");
+
+        let (instructions, pubkey) =
+            build_open_account_instructions(&program_id, &owner, &work, "me", Some("hello"))
+                .unwrap();
+        assert_eq!(pubkey, chat_account);
+        assert_eq!(instructions.len(), 2);
+        let open = &instructions[0];
+        assert_eq!(open.program_id, program_id);
+        assert_eq!(
+            open.accounts,
+            vec![
+                AccountMeta::new(owner, true),
+                AccountMeta::new(chat_account, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ]
+        );
+        match ChatInstruction::deserialize(&open.data).unwrap() {
+            ChatInstruction::OpenAccount { account_metadata } => {
+                assert_eq!(account_metadata.account_name, "me");
+                assert_eq!(account_metadata.bump, bump);
+                assert_eq!(account_metadata.initialized, 0);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match ChatInstruction::deserialize(&instructions[1].data).unwrap() {
+            ChatInstruction::SendMessages { messages } => assert_eq!(messages[0].msg, "hello"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(build_open_account_instructions(
+            &program_id,
+            &owner,
+            &work,
+            &"x".repeat(500),
+            None
+        )
+        .is_err());
+        assert!(build_open_account_instructions(&program_id, &owner, &work, "", None).is_err());
+
+        let to_account = Pubkey::new_unique();
+        let messages = vec![Message::new(0, owner, "hi".to_string())];
+        let send = build_send_instruction(&program_id, &owner, &to_account, messages.clone(), true)
+            .unwrap();
+        assert_eq!(send.program_id, program_id);
+        assert_eq!(
+            send.accounts,
+            vec![
+                AccountMeta::new(owner, true),
+                AccountMeta::new(to_account, false)
+            ]
+        );
+        assert_eq!(
+            ChatInstruction::deserialize(&send.data).unwrap(),
+            ChatInstruction::SendUniqueMessages { messages }
+        );
+
+        let (delete, pubkey) = build_delete_instruction(
+            &program_id,
+            &owner,
+            &work,
+            ChatInstruction::DeleteRange {
+                from_id: 2,
+                to_id: 5,
+            },
+        )
+        .unwrap();
+        assert_eq!(pubkey, chat_account);
+        assert_eq!(delete.program_id, program_id);
+        assert_eq!(
+            delete.accounts,
+            vec![
+                AccountMeta::new(owner, true),
+                AccountMeta::new(chat_account, false)
+            ]
+        );
+        assert_eq!(
+            ChatInstruction::deserialize(&delete.data).unwrap(),
+            ChatInstruction::DeleteRange {
+                from_id: 2,
+                to_id: 5
+            }
+        );
+    }
+}
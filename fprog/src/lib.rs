@@ -0,0 +1,2 @@
+// the parts of the client that are useful on their own, for transactions built elsewhere
+pub mod instructions;
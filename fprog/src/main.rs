@@ -1,18 +1,70 @@
-use clap::Parser;
+use bip39::{Language, Mnemonic};
+use clap::{ArgGroup, CommandFactory, Parser};
+use clap_complete::Shell;
 use core::fmt;
 use core::str::FromStr;
-use solana_client::rpc_client::{self, RpcClient};
-use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::read_keypair_file;
-use solana_sdk::signer::keypair::Keypair;
+use log::LevelFilter;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_sdk::commitment_config::CommitmentLevel;
+use solana_sdk::pubkey::{Pubkey, MAX_SEED_LEN};
+use solana_sdk::signature::{read_keypair, read_keypair_file};
+use solana_sdk::signer::keypair::{keypair_from_seed_phrase_and_passphrase, Keypair};
+use solana_sdk::signer::Signer;
+use std::env;
 use std::error::Error;
-use std::path::Path;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+mod account;
+mod backup;
 mod chat;
+mod compression;
+mod dump;
+mod encode;
+mod error;
+mod export;
+mod health;
+mod import;
+mod inbox;
+mod migrate;
+mod rpc;
+mod selftest;
+mod stats;
+#[cfg(feature = "tui")]
+mod tui;
+mod verify;
 
-use chat::{open_account, receive_messages, send_message};
+use chat::{
+    airdrop, block_sender, broadcast_message, close_account, count_messages, edit_message,
+    export_account, follow_messages, init_account, last_id, open_account, react, receive_messages,
+    search_messages, send_message, send_owner_instruction, stored_message_count,
+    transfer_ownership, wait_next_message, AutoOpen, ComputeBudget, ReceiveOptions, SendOptions,
+};
+use fprog::instructions::AddressStrategy;
+use md::data::{ChatInstruction, DEFAULT_SEED, MAX_MESSAGE_LEN};
+use md::error::ChatError;
 
+use crate::account::Cursor;
+use crate::backup::{backup, restore};
 use crate::chat::infer_chat_address;
+use crate::dump::dump_account;
+use crate::encode::{encode_open_account, encode_send};
+use crate::export::{parse_sha256, OutputFormat};
+use crate::health::health;
+use crate::import::import_messages;
+use crate::inbox::inbox;
+use crate::migrate::migrate;
+use crate::rpc::CliRpc;
+use crate::selftest::run_selftest;
+use crate::stats::stats;
+#[cfg(feature = "tui")]
+use crate::tui::run_tui;
+use crate::verify::{verify_account, verify_program};
 
 #[derive(Debug, Clone)]
 struct CustomError<'a>(&'a str);
@@ -27,67 +79,1360 @@ impl<'a> Error for CustomError<'a> {}
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(
+    after_help = "Flags take precedence over the CHAT_* environment variables, \
+which take precedence over the built-in defaults"
+)]
+#[clap(group(ArgGroup::new("program").args(&["program-keypair", "program-id"])))]
 struct Args {
-    #[clap(short, long)]
-    program_keypair: String,
+    // --shell stands in for the program, completions don't talk to it
+    #[clap(
+        short,
+        long,
+        env = "CHAT_PROGRAM_KEYPAIR",
+        value_parser = parse_keypair_path,
+        required_unless_present_any = &["program-id", "shell"]
+    )]
+    program_keypair: Option<String>,
+
+    /// Program pubkey, an alternative to --program-keypair since only the pubkey is needed
+    #[clap(long, value_parser = parse_pubkey)]
+    program_id: Option<Pubkey>,
 
     #[clap(short, long)]
     command: String,
 
-    #[clap(short, long)]
-    keypair: String,
+    /// Keypair file of the user, `-` reads it from stdin, falls back to CHAT_KEYPAIR
+    #[clap(short, long, value_parser = parse_keypair_path)]
+    keypair: Option<String>,
 
-    #[clap(short, long)]
-    message: Option<String>,
+    /// How --keypair, --payer-keypair and CHAT_KEYPAIR are encoded
+    #[clap(long, value_enum, default_value_t = KeypairFormat::Json)]
+    keypair_format: KeypairFormat,
 
+    /// Keypair file that pays the fees of send and open_account in place of --keypair
+    #[clap(long, value_parser = parse_keypair_path)]
+    payer_keypair: Option<String>,
+
+    /// Message to send, repeat it to send several in order
     #[clap(short, long)]
-    to_user: Option<String>,
+    message: Vec<String>,
+
+    /// UTF-8 file whose contents are sent as the message, in place of --message
+    #[clap(long, conflicts_with = "message")]
+    message_file: Option<PathBuf>,
+
+    #[clap(short, long, value_parser = parse_pubkey)]
+    to_user: Option<Pubkey>,
+
+    /// Comma separated users send-broadcast sends to, at their chat accounts under --seed
+    #[clap(long, value_parser = parse_pubkey, value_delimiter = ',')]
+    to_users: Vec<Pubkey>,
 
     #[clap(short, long)]
     account_name: Option<String>,
+
+    /// First message to post in the same transaction that opens the account
+    #[clap(long)]
+    welcome_message: Option<String>,
+
+    /// Seed of the chat account to use, one user can have an account per seed
+    #[clap(long, env = "CHAT_SEED", default_value = DEFAULT_SEED)]
+    seed: String,
+
+    /// Chat account to use as is, in place of the one derived from --seed
+    #[clap(long, value_parser = parse_pubkey)]
+    chat_account: Option<Pubkey>,
+
+    #[clap(long)]
+    message_id: Option<u32>,
+
+    #[clap(long)]
+    from_id: Option<u32>,
+
+    #[clap(long)]
+    to_id: Option<u32>,
+
+    /// Read, react or edit on the chat account of this user instead of your own
+    #[clap(long, value_parser = parse_pubkey)]
+    owner_pubkey: Option<Pubkey>,
+
+    #[clap(long)]
+    lamports: Option<u64>,
+
+    /// Text to look for with search
+    #[clap(long)]
+    query: Option<String>,
+
+    /// Print a hex and ASCII dump with the field boundaries in dump
+    #[clap(long)]
+    hex: bool,
+
+    /// Make search case-insensitive
+    #[clap(long)]
+    ignore_case: bool,
+
+    /// Emoji to react with, at most 8 bytes
+    #[clap(long)]
+    emoji: Option<String>,
+
+    /// Sender pubkey to block or unblock
+    #[clap(long, value_parser = parse_pubkey)]
+    sender: Option<Pubkey>,
+
+    /// Pubkey that takes over your chat account with transfer
+    #[clap(long, value_parser = parse_pubkey)]
+    new_owner: Option<Pubkey>,
+
+    /// Show sender account names next to their pubkeys in receive
+    #[clap(long)]
+    resolve_names: bool,
+
+    /// Deflate the message body before sending it
+    #[clap(long)]
+    compress: bool,
+
+    /// Hex sha256 of a file shared off chain, only the hash is sent with the message
+    #[clap(long, value_parser = parse_sha256)]
+    attachment_sha256: Option<[u8; 32]>,
+
+    /// Hex sha256 the deployed program must have for verify-program
+    #[clap(long, env = "CHAT_PROGRAM_HASH", value_parser = parse_sha256)]
+    expected_hash: Option<[u8; 32]>,
+
+    /// Open your own chat account under --seed before sending if it doesn't exist, named
+    /// --account-name or your pubkey
+    #[clap(long)]
+    auto_open: bool,
+
+    /// Derive message ids from the content in send, so a repeated send is rejected
+    #[clap(long)]
+    content_ids: bool,
+
+    /// Id of the message send answers
+    #[clap(long)]
+    reply_to: Option<u32>,
+
+    /// Seconds until the messages of send expire, receive hides them after that and purge
+    /// removes them
+    #[clap(long)]
+    ttl_secs: Option<u64>,
+
+    /// Let send post to a chat account you own, refused by default as it's usually a mix-up
+    #[clap(long)]
+    allow_self: bool,
+
+    /// Drop control characters other than newline and tab from the messages of send, receive
+    /// always shows them escaped
+    #[clap(long)]
+    sanitize: bool,
+
+    /// Poll until the recipient account exists before sending
+    #[clap(long)]
+    wait_for_account: bool,
+
+    /// Fetch a new blockhash every this many batches in send and import instead of for every
+    /// batch
+    #[clap(long)]
+    blockhash_refresh_every: Option<NonZeroUsize>,
+
+    /// Seconds --wait-for-account and wait-next give up after
+    #[clap(long, default_value_t = 30)]
+    timeout_secs: u64,
+
+    /// Print the signature and move on instead of waiting for the transaction to land
+    #[clap(long)]
+    no_confirm: bool,
+
+    /// Print the program logs a transaction would write instead of sending it
+    #[clap(long)]
+    simulate: bool,
+
+    /// JSON RPC endpoint of the cluster
+    #[clap(long, env = "CHAT_RPC_URL", default_value = DEFAULT_RPC_URL)]
+    rpc_url: String,
+
+    /// Send without simulating the transaction first
+    #[clap(long)]
+    skip_preflight: bool,
+
+    /// Commitment the preflight simulation runs at: processed, confirmed or finalized
+    #[clap(long, value_parser = parse_commitment)]
+    preflight_commitment: Option<CommitmentLevel>,
+
+    /// Give up on an RPC request after this long, slow remote nodes may need more
+    #[clap(long, default_value_t = 30)]
+    rpc_timeout_secs: u64,
+
+    /// Priority fee in micro-lamports per compute unit for send and open_account
+    #[clap(long)]
+    priority_fee: Option<u64>,
+
+    /// Compute unit limit for send and open_account, send estimates one from the batch size
+    /// when it's not given
+    #[clap(long)]
+    compute_limit: Option<u32>,
+
+    /// Keep polling for new messages in receive until ctrl-c
+    #[clap(long)]
+    follow: bool,
+
+    /// Seconds between polls in receive --follow and wait-next
+    #[clap(long, default_value_t = 5)]
+    interval_secs: u64,
+
+    /// Only receive messages stored after the one with this id
+    #[clap(long)]
+    after: Option<u32>,
+
+    /// Receive at most this many messages and print the --after for the next page
+    #[clap(long)]
+    limit: Option<usize>,
+
+    /// Fetch only the used part of the chat account in receive, fewer bytes but two requests
+    #[clap(long)]
+    data_slice: bool,
+
+    /// Show at most this many characters of each message in receive
+    #[clap(long)]
+    preview: Option<usize>,
+
+    /// Print the newest messages first in receive, with --limit the last N of them
+    #[clap(long)]
+    reverse: bool,
+
+    /// Stop parsing the chat account in receive after this many messages, guards against
+    /// corrupt accounts
+    #[clap(long, default_value_t = DEFAULT_MAX_MESSAGES)]
+    max_messages: usize,
+
+    /// Color the ids and senders in receive: always, never, or auto for a terminal without
+    /// NO_COLOR set
+    #[clap(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Output format of receive, export and stats
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Write received or exported messages to this file, needs --format json or csv; backup
+    /// writes the raw account here
+    #[clap(long, alias = "output")]
+    output_file: Option<PathBuf>,
+
+    /// JSON array of {from, msg, timestamp} objects to import, or a file written by backup to
+    /// restore
+    #[clap(long, alias = "input")]
+    file: Option<PathBuf>,
+
+    /// Append to --output-file instead of overwriting it
+    #[clap(long)]
+    append: bool,
+
+    /// Page of the inbox to show, counting from 1
+    #[clap(long, default_value_t = 1)]
+    page: usize,
+
+    #[clap(long, default_value_t = 20)]
+    page_size: usize,
+
+    /// Shell to print completions for with -c completions
+    #[clap(long, value_enum, hide = true)]
+    shell: Option<Shell>,
+
+    /// Skip the confirmation prompt of delete, delete-range, clear and close
+    #[clap(short, long)]
+    yes: bool,
+
+    /// Increase log verbosity (-v info, -vv debug, -vvv trace), RUST_LOG overrides it
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
-fn load_key_pair(user_key_pair_file: &str) -> Result<Keypair, Box<dyn Error>> {
-    let user_key_pair = read_keypair_file(Path::new(user_key_pair_file))?;
-    Ok(user_key_pair)
+fn init_logger(verbose: u8) {
+    let level = match verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .init();
+}
+
+const DEFAULT_RPC_URL: &str = "http://localhost:8899";
+// far more than fit in a chat account, so only a corrupt one ever reaches it
+const DEFAULT_MAX_MESSAGES: usize = 10_000;
+
+// keypair JSON for when --keypair is left out, so the secret never shows up in argv or on disk
+static KEYPAIR_ENV: &str = "CHAT_KEYPAIR";
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum KeypairFormat {
+    // the byte array solana-keygen writes
+    #[default]
+    Json,
+    // the 64 byte secret key wallets export as a base58 string
+    Base58,
+    // a BIP39 phrase, derived like `solana-keygen recover` without a derivation path
+    SeedPhrase,
+}
+
+fn read_key_pair<R: Read>(
+    reader: &mut R,
+    format: KeypairFormat,
+) -> Result<Keypair, Box<dyn Error>> {
+    let mut text = String::new();
+    match format {
+        KeypairFormat::Json => read_keypair(reader),
+        KeypairFormat::Base58 => {
+            reader.read_to_string(&mut text)?;
+            let secret = bs58::decode(text.trim())
+                .into_vec()
+                .map_err(|_| CustomError("keypair isn't a base58 string"))?;
+            Keypair::from_bytes(&secret).map_err(|_| {
+                CustomError("base58 keypair must decode to a 64 byte secret key").into()
+            })
+        }
+        KeypairFormat::SeedPhrase => {
+            reader.read_to_string(&mut text)?;
+            let mnemonic = Mnemonic::from_phrase(text.trim(), Language::English)
+                .map_err(|_| CustomError("keypair isn't a valid BIP39 seed phrase"))?;
+            keypair_from_seed_phrase_and_passphrase(mnemonic.phrase(), "")
+        }
+    }
+}
+
+fn load_key_pair(
+    user_key_pair_file: Option<&str>,
+    format: KeypairFormat,
+) -> Result<Keypair, Box<dyn Error>> {
+    match user_key_pair_file {
+        Some("-") => read_key_pair(&mut io::stdin().lock(), format),
+        Some(path) if format == KeypairFormat::Json => read_keypair_file(Path::new(path)),
+        Some(path) => read_key_pair(&mut fs::File::open(path)?, format),
+        None => match env::var(KEYPAIR_ENV) {
+            Ok(key_pair) => read_key_pair(&mut key_pair.as_bytes(), format),
+            Err(_) => Err(Box::new(CustomError(
+                "Missing keypair, pass --keypair or set CHAT_KEYPAIR",
+            ))),
+        },
+    }
+}
+
+// editors end the file with a newline that isn't part of the message
+fn read_message_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    let body = contents
+        .strip_suffix('\n')
+        .map(|body| body.strip_suffix('\r').unwrap_or(body))
+        .unwrap_or(&contents);
+    if body.len() > MAX_MESSAGE_LEN {
+        return Err(Box::new(ChatError::MessageTooLong));
+    }
+    Ok(body.to_string())
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorChoice {
+    Always,
+    Auto,
+    Never,
+}
+
+impl ColorChoice {
+    // any non-empty NO_COLOR turns auto off, see no-color.org, an explicit always still wins
+    fn enabled(self, no_color: Option<OsString>, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && no_color.is_none_or(|value| value.is_empty()),
+        }
+    }
+}
+
+// anything but y or Y keeps the data, including an empty line or a closed stdin, so a keypair
+// read from stdin needs --yes as well
+fn confirmed<R: BufRead, W: Write>(
+    yes: bool,
+    prompt: &str,
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+    write!(writer, "{} Continue? [y/N] ", prompt)?;
+    writer.flush()?;
+    let mut answer = String::new();
+    reader.read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y"))
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<Pubkey, String> {
+    Pubkey::from_str(pubkey).map_err(|_| "not a valid base58 pubkey".to_string())
+}
+
+// only checks the file is there, a malformed keypair is still reported when it's read
+fn parse_keypair_path(path: &str) -> Result<String, String> {
+    if path == "-" || Path::new(path).is_file() {
+        Ok(path.to_string())
+    } else {
+        Err("no such keypair file".to_string())
+    }
+}
+
+fn parse_commitment(commitment: &str) -> Result<CommitmentLevel, String> {
+    CommitmentLevel::from_str(commitment)
+        .map_err(|_| "expected processed, confirmed or finalized".to_string())
+}
+
+// same knobs as `solana --skip-preflight`, the rest of the config keeps the RPC defaults
+fn send_config(
+    skip_preflight: bool,
+    preflight_commitment: Option<CommitmentLevel>,
+) -> RpcSendTransactionConfig {
+    RpcSendTransactionConfig {
+        skip_preflight,
+        preflight_commitment,
+        ..RpcSendTransactionConfig::default()
+    }
+}
+
+// the HTTP sender already retries rate limited requests on its own, only the timeout is ours
+fn build_rpc_client(url: String, timeout: Duration) -> RpcClient {
+    RpcClient::new_with_timeout(url, timeout)
+}
+
+fn write_completions<W: io::Write>(shell: Shell, out: &mut W) {
+    clap_complete::generate(shell, &mut Args::command(), "fprog", out);
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
-    let program_keypair: String = args.program_keypair;
+    init_logger(args.verbose);
+    let program_keypair: Option<String> = args.program_keypair;
+    let program_id: Option<Pubkey> = args.program_id;
     let command: String = args.command;
-    let key_pair: String = args.keypair;
-    let message: Option<String> = args.message;
-    let to_user: Option<String> = args.to_user;
+    let key_pair: Option<String> = args.keypair;
+    let payer_key_pair: Option<String> = args.payer_keypair;
+    let keypair_format: KeypairFormat = args.keypair_format;
+    let mut message: Vec<String> = args.message;
+    if let Some(path) = &args.message_file {
+        message.push(read_message_file(path)?);
+    }
+    let to_user: Option<Pubkey> = args.to_user;
+    let to_users: Vec<Pubkey> = args.to_users;
     let account_name: Option<String> = args.account_name;
+    let welcome_message: Option<String> = args.welcome_message;
+    let seed: String = args.seed;
+    let chat_account: Option<Pubkey> = args.chat_account;
+    let message_id: Option<u32> = args.message_id;
+    let from_id: Option<u32> = args.from_id;
+    let to_id: Option<u32> = args.to_id;
+    let owner_pubkey: Option<Pubkey> = args.owner_pubkey;
+    let lamports: Option<u64> = args.lamports;
+    let emoji: Option<String> = args.emoji;
+    let query: Option<String> = args.query;
+    let ignore_case: bool = args.ignore_case;
+    let hex: bool = args.hex;
+    let sender: Option<Pubkey> = args.sender;
+    let new_owner: Option<Pubkey> = args.new_owner;
+    let resolve_names: bool = args.resolve_names;
+    let compress: bool = args.compress;
+    let attachment_sha256: Option<[u8; 32]> = args.attachment_sha256;
+    let expected_hash: Option<[u8; 32]> = args.expected_hash;
+    let auto_open: bool = args.auto_open;
+    let content_ids: bool = args.content_ids;
+    let reply_to: Option<u32> = args.reply_to;
+    let ttl_secs: Option<u64> = args.ttl_secs;
+    let allow_self: bool = args.allow_self;
+    let sanitize: bool = args.sanitize;
+    let wait_for_account: bool = args.wait_for_account;
+    let blockhash_refresh_every: Option<NonZeroUsize> = args.blockhash_refresh_every;
+    let timeout_secs: u64 = args.timeout_secs;
+    let rpc_url: String = args.rpc_url;
+    let rpc_timeout_secs: u64 = args.rpc_timeout_secs;
+    let no_confirm: bool = args.no_confirm;
+    let simulate: bool = args.simulate;
+    let skip_preflight: bool = args.skip_preflight;
+    let preflight_commitment: Option<CommitmentLevel> = args.preflight_commitment;
+    let compute_budget = ComputeBudget {
+        unit_price: args.priority_fee,
+        unit_limit: args.compute_limit,
+    };
+    let follow: bool = args.follow;
+    let interval_secs: u64 = args.interval_secs;
+    let after: Option<u32> = args.after;
+    let limit: Option<usize> = args.limit;
+    let data_slice: bool = args.data_slice;
+    let preview: Option<usize> = args.preview;
+    let max_messages: usize = args.max_messages;
+    let color = args
+        .color
+        .enabled(env::var_os("NO_COLOR"), io::stdout().is_terminal());
+    let reverse: bool = args.reverse;
+    let format: OutputFormat = args.format;
+    let output_file: Option<PathBuf> = args.output_file;
+    let append: bool = args.append;
+    let file: Option<PathBuf> = args.file;
+    let page: usize = args.page;
+    let page_size: usize = args.page_size;
+    let shell: Option<Shell> = args.shell;
+    let yes: bool = args.yes;
 
-    let user_kp = load_key_pair(&key_pair)?;
-    let program_kp = load_key_pair(&program_keypair)?;
-    let rpc_client: RpcClient = RpcClient::new("http://localhost:8899".to_string());
+    if command == "completions" {
+        return match shell {
+            Some(shell) => {
+                write_completions(shell, &mut io::stdout());
+                Ok(())
+            }
+            None => Err(Box::new(CustomError("Missing shell"))),
+        };
+    }
+
+    // selftest only exercises the codec, so it runs before any keypair or RPC setup
+    if command == "selftest" {
+        if run_selftest() {
+            return Ok(());
+        }
+        return Err(Box::new(CustomError("selftest failed")));
+    }
+
+    // longer seeds make the PDA derivation panic
+    if seed.len() > MAX_SEED_LEN {
+        return Err(Box::new(ChatError::SeedTooLong));
+    }
+    let strategy = match chat_account {
+        Some(chat_account) => AddressStrategy::Explicit(chat_account),
+        None => AddressStrategy::Pda(seed),
+    };
+
+    let program_pk = match (program_id, program_keypair) {
+        (Some(program_id), _) => program_id,
+        (None, Some(program_keypair)) => {
+            load_key_pair(Some(&program_keypair), KeypairFormat::Json)?.pubkey()
+        }
+        (None, None) => {
+            return Err(Box::new(CustomError(
+                "Missing program_id or program_keypair",
+            )))
+        }
+    };
+    let rpc_client = CliRpc {
+        client: build_rpc_client(rpc_url, Duration::from_secs(rpc_timeout_secs)),
+        confirm: !no_confirm,
+        simulate,
+        send_config: send_config(skip_preflight, preflight_commitment),
+        cache: Default::default(),
+    };
+
+    // health and verify-program only talk to the cluster, they work before a user keypair is set up
+    if command == "health" {
+        if health(&rpc_client, &program_pk) {
+            return Ok(());
+        }
+        return Err(Box::new(CustomError("health check failed")));
+    }
+    if command == "verify-program" {
+        let expected_hash = match expected_hash {
+            Some(expected_hash) => expected_hash,
+            None => return Err(Box::new(CustomError("Missing expected_hash"))),
+        };
+        if verify_program(&rpc_client, &program_pk, &expected_hash)? {
+            return Ok(());
+        }
+        return Err(Box::new(CustomError("program hash mismatch")));
+    }
+
+    let user_kp = load_key_pair(key_pair.as_deref(), keypair_format)?;
+    let payer_kp = match payer_key_pair {
+        Some(path) => Some(load_key_pair(Some(&path), keypair_format)?),
+        None => None,
+    };
+
+    // the encode commands only build and print instruction data, nothing is sent
+    if command == "encode-send" {
+        if message.is_empty() {
+            return Err(Box::new(CustomError("Missing message")));
+        }
+        return encode_send(
+            &program_pk,
+            &user_kp.pubkey(),
+            &to_user.unwrap_or_default(),
+            &message,
+            attachment_sha256,
+            reply_to,
+            content_ids,
+        );
+    }
+    if command == "encode-open-account" {
+        return match &account_name {
+            Some(account_name) => {
+                encode_open_account(&program_pk, &user_kp.pubkey(), &strategy, account_name)
+            }
+            None => Err(Box::new(CustomError("Missing account_name"))),
+        };
+    }
+
+    let prompt = match command.as_str() {
+        "delete" => message_id.map(|id| format!("This will delete message {}.", id)),
+        "delete-range" => from_id
+            .zip(to_id)
+            .map(|(from_id, to_id)| format!("This will delete messages {} to {}.", from_id, to_id)),
+        "clear" if !yes => Some(format!(
+            "This will delete {} messages.",
+            stored_message_count(&rpc_client, &program_pk, &strategy, &user_kp)?
+        )),
+        "close" if !yes => Some(format!(
+            "This will close the account and delete its {} messages.",
+            stored_message_count(&rpc_client, &program_pk, &strategy, &user_kp)?
+        )),
+        _ => None,
+    };
+    if let Some(prompt) = prompt {
+        if !confirmed(yes, &prompt, &mut io::stdin().lock(), &mut io::stdout())? {
+            return Err(Box::new(CustomError("Aborted")));
+        }
+    }
 
     match command.as_str() {
-        "send" => {
-            if let (Some(to), Some(msg)) = (to_user, message) {
-                let to_pk = Pubkey::from_str(&to).unwrap();
-                send_message(&rpc_client, &program_kp, &user_kp, &to_pk, msg)
+        "send" | "send-broadcast" => {
+            let broadcast = command == "send-broadcast";
+            let has_recipient = if broadcast {
+                !to_users.is_empty()
             } else {
-                panic!("Missing to_user or message !");
+                to_user.is_some()
+            };
+            if has_recipient && !message.is_empty() {
+                let auto_open = auto_open.then(|| AutoOpen {
+                    strategy: strategy.clone(),
+                    account_name: account_name
+                        .clone()
+                        .unwrap_or_else(|| user_kp.pubkey().to_string()),
+                });
+                let options = SendOptions {
+                    compress,
+                    attachment_hash: attachment_sha256,
+                    content_ids,
+                    auto_open,
+                    reply_to,
+                    ttl_secs,
+                    allow_self,
+                    sanitize,
+                    blockhash_refresh_every,
+                    payer: payer_kp,
+                    wait_for_account_timeout: wait_for_account
+                        .then(|| Duration::from_secs(timeout_secs)),
+                    compute_budget,
+                };
+                if broadcast {
+                    // one explicit address can't be every recipient's account
+                    if chat_account.is_some() {
+                        return Err(Box::new(CustomError(
+                            "send-broadcast finds each recipient's account from --seed",
+                        )));
+                    }
+                    let failed = broadcast_message(
+                        &rpc_client,
+                        &program_pk,
+                        &strategy,
+                        &user_kp,
+                        &to_users,
+                        message,
+                        &options,
+                    );
+                    if !failed.is_empty() {
+                        return Err(Box::new(CustomError(
+                            "Broadcast failed for some recipients",
+                        )));
+                    }
+                    Ok(())
+                } else {
+                    send_message(
+                        &rpc_client,
+                        &program_pk,
+                        &user_kp,
+                        &to_user.unwrap(),
+                        message,
+                        &options,
+                    )
+                }
+            } else if broadcast {
+                Err(Box::new(CustomError("Missing to_users or message !")))
+            } else {
+                Err(Box::new(CustomError("Missing to_user or message !")))
             }
         }
         "open_account" => {
             if let Some(name) = account_name {
-                open_account(&rpc_client, &program_kp, &user_kp, &name)
+                open_account(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    payer_kp.as_ref(),
+                    &name,
+                    welcome_message.as_deref(),
+                    &compute_budget,
+                )
+            } else {
+                Err(Box::new(CustomError("Missing account_name")))
+            }
+        }
+        "init-account" => {
+            if let Some(name) = account_name {
+                init_account(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    payer_kp.as_ref(),
+                    &name,
+                    &compute_budget,
+                )
+            } else {
+                Err(Box::new(CustomError("Missing account_name")))
+            }
+        }
+        "last-id" => last_id(
+            &rpc_client,
+            &program_pk,
+            &strategy,
+            &user_kp,
+            owner_pubkey.as_ref(),
+        ),
+        "wait-next" => wait_next_message(
+            &rpc_client,
+            &program_pk,
+            &strategy,
+            &user_kp,
+            owner_pubkey.as_ref(),
+            Duration::from_secs(timeout_secs),
+            Duration::from_secs(interval_secs),
+        ),
+        "receive" => {
+            if format == OutputFormat::Csv {
+                return Err(Box::new(CustomError(
+                    "--format csv is only supported by export",
+                )));
+            }
+            if output_file.is_some() && format != OutputFormat::Json {
+                return Err(Box::new(CustomError("--output-file needs --format json")));
+            }
+            let options = ReceiveOptions {
+                owner_pubkey,
+                resolve_names,
+                format,
+                output_file,
+                append,
+                limit,
+                data_slice,
+                preview,
+                reverse,
+                max_messages: Some(max_messages),
+                color,
+            };
+            if follow {
+                follow_messages(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    after,
+                    options,
+                    Duration::from_secs(interval_secs),
+                )
+            } else {
+                receive_messages(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    &mut Cursor::after(after),
+                    &options,
+                )?;
+                Ok(())
+            }
+        }
+        "inbox" => inbox(&rpc_client, &program_pk, &user_kp, page, page_size),
+        "search" => {
+            if let Some(query) = query {
+                search_messages(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    owner_pubkey.as_ref(),
+                    &query,
+                    ignore_case,
+                )
+            } else {
+                Err(Box::new(CustomError("Missing query")))
+            }
+        }
+        "export" => export_account(
+            &rpc_client,
+            &program_pk,
+            &strategy,
+            &user_kp,
+            owner_pubkey.as_ref(),
+            format,
+            output_file.as_deref(),
+        ),
+        "import" => {
+            if let Some(file) = file {
+                import_messages(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    &file,
+                    &compute_budget,
+                    blockhash_refresh_every,
+                )
+            } else {
+                Err(Box::new(CustomError("Missing file")))
+            }
+        }
+        "backup" => {
+            if let Some(output_file) = output_file {
+                backup(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    owner_pubkey.as_ref(),
+                    &output_file,
+                )
             } else {
-                panic!("Missing account_name");
+                Err(Box::new(CustomError("Missing output_file")))
             }
         }
-        "receive" => receive_messages(&rpc_client, &program_kp, &user_kp, None),
+        "restore" => {
+            if let Some(file) = file {
+                restore(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    &file,
+                    &compute_budget,
+                    blockhash_refresh_every,
+                )
+            } else {
+                Err(Box::new(CustomError("Missing file")))
+            }
+        }
+        "stats" => stats(&rpc_client, &program_pk, format),
+        "count" => count_messages(&rpc_client, &program_pk, &strategy, &user_kp),
         "delete" => {
-            panic!("Not implemented");
+            if let Some(id) = message_id {
+                let chat_instruction = ChatInstruction::DeleteMessages { id };
+                send_owner_instruction(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    chat_instruction,
+                )
+            } else {
+                Err(Box::new(CustomError("Missing message_id")))
+            }
+        }
+        "delete-range" => {
+            if let (Some(from_id), Some(to_id)) = (from_id, to_id) {
+                let chat_instruction = ChatInstruction::DeleteRange { from_id, to_id };
+                send_owner_instruction(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    chat_instruction,
+                )
+            } else {
+                Err(Box::new(CustomError("Missing from_id or to_id")))
+            }
+        }
+        "clear" => send_owner_instruction(
+            &rpc_client,
+            &program_pk,
+            &strategy,
+            &user_kp,
+            ChatInstruction::DeleteAll,
+        ),
+        "purge" => send_owner_instruction(
+            &rpc_client,
+            &program_pk,
+            &strategy,
+            &user_kp,
+            ChatInstruction::PurgeExpired,
+        ),
+        "close" => close_account(&rpc_client, &program_pk, &strategy, &user_kp),
+        "block" | "unblock" => {
+            if let Some(sender_pk) = sender {
+                let block = command == "block";
+                block_sender(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    sender_pk,
+                    block,
+                )
+            } else {
+                Err(Box::new(CustomError("Missing sender")))
+            }
+        }
+        "edit" => {
+            if let (Some(id), [new_msg]) = (message_id, &message[..]) {
+                edit_message(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    owner_pubkey.as_ref(),
+                    id,
+                    new_msg,
+                )
+            } else {
+                Err(Box::new(CustomError(
+                    "Missing message_id or a single message",
+                )))
+            }
+        }
+        "transfer" => {
+            if let Some(new_owner_pk) = new_owner {
+                transfer_ownership(&rpc_client, &program_pk, &strategy, &user_kp, new_owner_pk)
+            } else {
+                Err(Box::new(CustomError("Missing new_owner")))
+            }
+        }
+        "react" => {
+            if let (Some(id), Some(emoji)) = (message_id, emoji) {
+                react(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    owner_pubkey.as_ref(),
+                    id,
+                    &emoji,
+                )
+            } else {
+                Err(Box::new(CustomError("Missing message_id or emoji")))
+            }
+        }
+        "airdrop" => {
+            if let Some(lamports) = lamports {
+                airdrop(&rpc_client, &user_kp, lamports)
+            } else {
+                Err(Box::new(CustomError("Missing lamports")))
+            }
+        }
+        "infer_chat_address" => infer_chat_address(&rpc_client, &program_pk, &strategy, &user_kp),
+        "migrate" => migrate(&rpc_client, &program_pk, &user_kp),
+        #[cfg(feature = "tui")]
+        "tui" => {
+            if let Some(to_pk) = to_user {
+                let options = SendOptions {
+                    compress,
+                    compute_budget,
+                    payer: payer_kp,
+                    ..SendOptions::default()
+                };
+                run_tui(
+                    &rpc_client,
+                    &program_pk,
+                    &strategy,
+                    &user_kp,
+                    &to_pk,
+                    &options,
+                    Duration::from_secs(interval_secs),
+                )
+            } else {
+                Err(Box::new(CustomError("Missing to_user")))
+            }
+        }
+        #[cfg(not(feature = "tui"))]
+        "tui" => Err(Box::new(CustomError(
+            "fprog was built without the tui feature, rebuild it with --features tui",
+        ))),
+        "dump" => dump_account(
+            &rpc_client,
+            &program_pk,
+            &strategy,
+            &user_kp,
+            owner_pubkey.as_ref(),
+            hex,
+        ),
+        "verify" => {
+            if verify_account(
+                &rpc_client,
+                &program_pk,
+                &strategy,
+                &user_kp,
+                owner_pubkey.as_ref(),
+            )? {
+                Ok(())
+            } else {
+                Err(Box::new(CustomError("account is corrupt")))
+            }
         }
-        "infer_chat_address" => infer_chat_address(&rpc_client, &program_kp, &user_kp),
         _ => panic!("Unknown option !"),
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use bip39::{Language, Mnemonic, MnemonicType};
+    use solana_sdk::signature::write_keypair_file;
+    use solana_sdk::signer::keypair::{keypair_from_seed_phrase_and_passphrase, Keypair};
+    use solana_sdk::signer::Signer;
+
+    use clap::{ErrorKind, Parser};
+
+    use clap_complete::Shell;
+
+    use std::env;
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    use solana_client::rpc_config::RpcSendTransactionConfig;
+    use solana_sdk::commitment_config::CommitmentLevel;
+    use solana_sdk::transaction::Transaction;
+
+    use crate::rpc::fake::FakeRpc;
+    use crate::rpc::{ChatRpc, CliRpc};
+
+    use md::data::{Message, MAX_MESSAGE_LEN};
+    use md::error::ChatError;
+
+    use crate::chat::message_line;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::{
+        build_rpc_client, confirmed, load_key_pair, read_key_pair, read_message_file, send_config,
+        write_completions, Args, ColorChoice, KeypairFormat, DEFAULT_RPC_URL, KEYPAIR_ENV,
+    };
+
+    // the environment is shared by the whole test binary, parses that depend on it take turns
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn rpc_timeout_is_applied() {
+        // accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let rpc_client = build_rpc_client(url, Duration::from_millis(200));
+
+        let started = Instant::now();
+        assert!(rpc_client.get_latest_blockhash().is_err());
+        assert!(started.elapsed() < Duration::from_secs(10));
+        drop(listener);
+    }
+
+    // keypair paths are checked while parsing, so they have to exist
+    fn keypair_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("fprog-{}-{}.json", name, std::process::id()));
+        write_keypair_file(&Keypair::new(), &path).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn exactly_one_program_argument() {
+        let _env = ENV_LOCK.lock().unwrap();
+        let parse = |program_args: &[&str]| {
+            let mut args = vec!["fprog", "-c", "receive"];
+            args.extend_from_slice(program_args);
+            Args::try_parse_from(args)
+        };
+        let program_id = solana_sdk::pubkey::Pubkey::new_unique().to_string();
+        let program_keypair = keypair_file("program");
+
+        assert!(parse(&["--program-id", &program_id]).is_ok());
+        assert!(parse(&["--program-keypair", &program_keypair]).is_ok());
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--program-id", &program_id, "-p", &program_keypair]).is_err());
+        std::fs::remove_file(&program_keypair).unwrap();
+    }
+
+    #[test]
+    fn malformed_arguments_are_parse_errors() {
+        let _env = ENV_LOCK.lock().unwrap();
+        let parse = |extra_args: &[&str]| {
+            let mut args = vec!["fprog", "-c", "send", "-m", "hi"];
+            args.extend_from_slice(extra_args);
+            Args::try_parse_from(args)
+        };
+        let program_id = solana_sdk::pubkey::Pubkey::new_unique().to_string();
+
+        let err = parse(&["--program-id", &program_id, "-t", "not-a-pubkey"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueValidation);
+        assert!(err.to_string().contains("not a valid base58 pubkey"));
+        let err = parse(&["--program-id", "0OIl"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueValidation);
+        let err = parse(&["--program-id", &program_id, "-k", "/missing/keypair.json"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueValidation);
+        assert!(err.to_string().contains("no such keypair file"));
+
+        let args = parse(&["--program-id", &program_id, "-k", "-", "-t", &program_id]).unwrap();
+        assert_eq!(args.to_user, args.program_id);
+    }
+
+    #[test]
+    fn environment_fills_in_missing_flags() {
+        let _env = ENV_LOCK.lock().unwrap();
+        let parse = |extra_args: &[&str]| {
+            let mut args = vec!["fprog", "-c", "receive"];
+            args.extend_from_slice(extra_args);
+            Args::try_parse_from(args).unwrap()
+        };
+        let key_pair = Keypair::new();
+        let env_keypair = keypair_file("env");
+        let flag_keypair = keypair_file("flag");
+        env::set_var("CHAT_PROGRAM_KEYPAIR", &env_keypair);
+        env::set_var("CHAT_RPC_URL", "http://env:8899");
+        env::set_var("CHAT_SEED", "env-seed");
+        env::set_var(KEYPAIR_ENV, format!("{:?}", key_pair.to_bytes()));
+
+        let from_env = parse(&[]);
+        let from_flags = parse(&[
+            "-p",
+            &flag_keypair,
+            "--rpc-url",
+            "http://flag:8899",
+            "--seed",
+            "flag-seed",
+        ]);
+        let loaded = load_key_pair(None, KeypairFormat::Json);
+
+        for var in [
+            "CHAT_PROGRAM_KEYPAIR",
+            "CHAT_RPC_URL",
+            "CHAT_SEED",
+            KEYPAIR_ENV,
+        ] {
+            env::remove_var(var);
+        }
+        let defaults = parse(&["--program-id", "11111111111111111111111111111111"]);
+
+        std::fs::remove_file(&env_keypair).unwrap();
+        std::fs::remove_file(&flag_keypair).unwrap();
+
+        assert_eq!(from_env.program_keypair, Some(env_keypair));
+        assert_eq!(from_env.rpc_url, "http://env:8899");
+        assert_eq!(from_env.seed, "env-seed");
+        assert_eq!(loaded.unwrap().pubkey(), key_pair.pubkey());
+        assert_eq!(from_flags.program_keypair, Some(flag_keypair));
+        assert_eq!(from_flags.rpc_url, "http://flag:8899");
+        assert_eq!(from_flags.seed, "flag-seed");
+        assert_eq!(defaults.rpc_url, DEFAULT_RPC_URL);
+        assert_eq!(defaults.seed, md::data::DEFAULT_SEED);
+    }
 
+    #[test]
+    fn preflight_flags_build_the_send_config() {
+        let _env = ENV_LOCK.lock().unwrap();
+        let parse = |extra_args: &[&str]| {
+            let mut args = vec![
+                "fprog",
+                "-c",
+                "send",
+                "--program-id",
+                "11111111111111111111111111111111",
+            ];
+            args.extend_from_slice(extra_args);
+            Args::try_parse_from(args)
+        };
+
+        let args = parse(&["--skip-preflight", "--preflight-commitment", "processed"]).unwrap();
+        let config = send_config(args.skip_preflight, args.preflight_commitment);
+        assert_eq!(
+            config,
+            RpcSendTransactionConfig {
+                skip_preflight: true,
+                preflight_commitment: Some(CommitmentLevel::Processed),
+                ..RpcSendTransactionConfig::default()
+            }
+        );
+        let args = parse(&[]).unwrap();
+        assert_eq!(
+            send_config(args.skip_preflight, args.preflight_commitment),
+            RpcSendTransactionConfig::default()
+        );
+        assert!(parse(&["--preflight-commitment", "soon"]).is_err());
+
+        // confirming sends carry the config down to the client
+        let rpc = CliRpc {
+            client: FakeRpc::default(),
+            confirm: true,
+            simulate: false,
+            send_config: config,
+            cache: Default::default(),
+        };
+        rpc.send_and_confirm_transaction_with_spinner(&Transaction::default())
+            .unwrap();
+        assert_eq!(*rpc.client.send_configs.borrow(), vec![config]);
+    }
+
+    #[test]
+    fn bash_completions_without_program() {
+        let args = Args::try_parse_from(["fprog", "-c", "completions", "--shell", "bash"]).unwrap();
+        assert_eq!(args.shell, Some(Shell::Bash));
+
+        let mut script = vec![];
+        write_completions(Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("--program-id"));
+    }
+
+    #[test]
+    fn key_pair_from_reader_matches_file() {
+        let key_pair = Keypair::new();
+        let path = std::env::temp_dir().join(format!("fprog-keypair-{}.json", key_pair.pubkey()));
+        write_keypair_file(&key_pair, &path).unwrap();
+
+        let from_file = load_key_pair(path.to_str(), KeypairFormat::Json).unwrap();
+        let json = std::fs::read(&path).unwrap();
+        let from_reader = read_key_pair(&mut &json[..], KeypairFormat::Json).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(from_file.pubkey(), key_pair.pubkey());
+        assert_eq!(from_reader.pubkey(), from_file.pubkey());
+    }
+
+    #[test]
+    fn message_file_becomes_the_body() {
+        let path = std::env::temp_dir().join(format!("fprog-message-{}.txt", Pubkey::new_unique()));
+        std::fs::write(&path, "first line\nsecond line\n").unwrap();
+        let body = read_message_file(&path).unwrap();
+        std::fs::write(&path, "a".repeat(MAX_MESSAGE_LEN + 1)).unwrap();
+        let oversized = read_message_file(&path);
+        std::fs::write(&path, [0xff, 0xfe]).unwrap();
+        let not_utf8 = read_message_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(body, "first line\nsecond line");
+        assert_eq!(
+            oversized.unwrap_err().to_string(),
+            ChatError::MessageTooLong.to_string()
+        );
+        assert!(not_utf8.is_err());
+
+        let both = Args::try_parse_from([
+            "fprog",
+            "-c",
+            "send",
+            "-m",
+            "hi",
+            "--message-file",
+            path.to_str().unwrap(),
+        ]);
+        assert_eq!(both.unwrap_err().kind(), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn destructive_commands_need_a_yes() {
+        let mut prompt = vec![];
+        assert!(!confirmed(
+            false,
+            "This will delete 3 messages.",
+            &mut &b"n\n"[..],
+            &mut prompt
+        )
+        .unwrap());
+        assert_eq!(
+            String::from_utf8(prompt).unwrap(),
+            "This will delete 3 messages. Continue? [y/N] "
+        );
+        assert!(!confirmed(false, "", &mut &b""[..], &mut vec![]).unwrap());
+        assert!(confirmed(false, "", &mut &b"Y\n"[..], &mut vec![]).unwrap());
+
+        let _env = ENV_LOCK.lock().unwrap();
+        let args = Args::try_parse_from([
+            "fprog",
+            "-c",
+            "clear",
+            "-y",
+            "--program-id",
+            "11111111111111111111111111111111",
+        ])
+        .unwrap();
+        let mut prompt = vec![];
+        assert!(confirmed(args.yes, "", &mut &b"n\n"[..], &mut prompt).unwrap());
+        assert!(prompt.is_empty());
+    }
+
+    #[test]
+    fn color_never_emits_no_escape_codes() {
+        let _env = ENV_LOCK.lock().unwrap();
+        let args = Args::try_parse_from([
+            "fprog",
+            "-c",
+            "receive",
+            "--color",
+            "never",
+            "--program-id",
+            "11111111111111111111111111111111",
+        ])
+        .unwrap();
+        assert_eq!(args.color, ColorChoice::Never);
+
+        let message = Message::new(3, Pubkey::new_unique(), "hi".to_string());
+        let line = |color| message_line(&message, "alice", None, color);
+        assert!(!line(args.color.enabled(None, true)).contains('\x1b'));
+        assert_eq!(
+            line(ColorChoice::Always.enabled(Some("1".into()), false)),
+            "[\x1b[33m3\x1b[0m] \x1b[36malice\x1b[0m @ 0: hi"
+        );
+        // auto colors a terminal unless NO_COLOR is set to something
+        assert!(ColorChoice::Auto.enabled(None, true));
+        assert!(ColorChoice::Auto.enabled(Some("".into()), true));
+        assert!(!ColorChoice::Auto.enabled(Some("1".into()), true));
+        assert!(!ColorChoice::Auto.enabled(None, false));
+    }
+
+    #[test]
+    fn keypair_formats_load_the_same_key() {
+        let mnemonic = Mnemonic::new(MnemonicType::Words12, Language::English);
+        let key_pair = keypair_from_seed_phrase_and_passphrase(mnemonic.phrase(), "").unwrap();
+        let encodings = [
+            (KeypairFormat::Json, format!("{:?}", key_pair.to_bytes())),
+            (
+                KeypairFormat::Base58,
+                format!("{}\n", key_pair.to_base58_string()),
+            ),
+            (
+                KeypairFormat::SeedPhrase,
+                format!("{}\n", mnemonic.phrase()),
+            ),
+        ];
+        for (format, text) in &encodings {
+            let loaded = read_key_pair(&mut text.as_bytes(), *format).unwrap();
+            assert_eq!(loaded.pubkey(), key_pair.pubkey(), "{:?}", format);
+        }
+
+        let path = std::env::temp_dir().join(format!("fprog-keypair-{}.b58", key_pair.pubkey()));
+        std::fs::write(&path, &encodings[1].1).unwrap();
+        let from_file = load_key_pair(path.to_str(), KeypairFormat::Base58);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(from_file.unwrap().pubkey(), key_pair.pubkey());
+
+        let rejected = [
+            (KeypairFormat::Base58, "not-base58-0OIl"),
+            (KeypairFormat::Base58, "3mJr7AoUXx2Wqd"),
+            (
+                KeypairFormat::SeedPhrase,
+                "these are not the words you are looking for",
+            ),
+            (KeypairFormat::Json, "[1, 2, 3]"),
+        ];
+        for (format, text) in rejected {
+            assert!(read_key_pair(&mut text.as_bytes(), format).is_err());
+        }
+    }
+}
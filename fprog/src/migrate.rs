@@ -0,0 +1,268 @@
+use arrayref::array_ref;
+use log::info;
+use md::data::{deserialize_account_data, ChatDeserializationError, Message};
+use solana_sdk::pubkey::{Pubkey, PUBKEY_BYTES};
+use solana_sdk::signer::keypair::Keypair;
+use solana_sdk::signer::Signer;
+use std::error::Error;
+use std::mem;
+
+use fprog::instructions::AddressStrategy;
+
+use crate::chat::{infer_chat_account_pubkey, open_account, send_messages_batched, ComputeBudget};
+use crate::rpc::ChatRpc;
+
+const U8_SIZE: usize = mem::size_of::<u8>();
+const U32_SIZE: usize = mem::size_of::<u32>();
+
+// layout before the PDA migration: no bump, message count or compression flag
+// metadata: initialized u8, next_free_index u32, last_message_id u32, name_len u32, name
+// message: id u32, from pubkey, msg_size u32, msg
+const LEGACY_METADATA_BASE_SIZE: usize = U8_SIZE + U32_SIZE * 3;
+const LEGACY_MESSAGE_BASE_SIZE: usize = U32_SIZE * 2 + PUBKEY_BYTES;
+
+// legacy accounts were created with this seed only, they migrate into the default PDA
+const LEGACY_SEED: &str = "chat";
+
+#[derive(Debug, PartialEq)]
+pub struct LegacyAccount {
+    pub account_name: String,
+    pub messages: Vec<Message>,
+}
+
+fn legacy_chat_account_pubkey(user_pk: &Pubkey, program_pk: &Pubkey) -> Pubkey {
+    infer_chat_account_pubkey(
+        user_pk,
+        program_pk,
+        &AddressStrategy::Seed(LEGACY_SEED.to_string()),
+    )
+    .unwrap()
+}
+
+fn parse_legacy_account(data: &[u8]) -> Result<LegacyAccount, ChatDeserializationError> {
+    if data.len() < LEGACY_METADATA_BASE_SIZE || data[0] == 0 {
+        return Err(ChatDeserializationError);
+    }
+    let next_free_index = u32::from_le_bytes(*array_ref![data, U8_SIZE, U32_SIZE]) as usize;
+    let name_len = u32::from_le_bytes(*array_ref![data, U8_SIZE + U32_SIZE * 2, U32_SIZE]) as usize;
+    let metadata_size = LEGACY_METADATA_BASE_SIZE + name_len;
+    if metadata_size > next_free_index || next_free_index > data.len() {
+        return Err(ChatDeserializationError);
+    }
+    let account_name =
+        String::from_utf8_lossy(&data[LEGACY_METADATA_BASE_SIZE..metadata_size]).into_owned();
+
+    let mut messages = vec![];
+    let mut start = metadata_size;
+    while start + LEGACY_MESSAGE_BASE_SIZE <= next_free_index {
+        let id = u32::from_le_bytes(*array_ref![data, start, U32_SIZE]);
+        let from = Pubkey::new_from_array(*array_ref![data, start + U32_SIZE, PUBKEY_BYTES]);
+        let msg_size =
+            u32::from_le_bytes(*array_ref![data, start + U32_SIZE + PUBKEY_BYTES, U32_SIZE]);
+        let msg_start = start + LEGACY_MESSAGE_BASE_SIZE;
+        let msg_end = msg_start + msg_size as usize;
+        if msg_end > next_free_index {
+            return Err(ChatDeserializationError);
+        }
+        let msg = String::from_utf8_lossy(&data[msg_start..msg_end]).into_owned();
+        messages.push(Message::new(id, from, msg));
+        start = msg_end;
+    }
+
+    Ok(LegacyAccount {
+        account_name,
+        messages,
+    })
+}
+
+// copies the old seed-derived account into the PDA, skipping whatever an earlier run already
+// copied, so it's safe to re-run after a failure
+pub fn migrate(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    from_user: &Keypair,
+) -> Result<(), Box<dyn Error>> {
+    let legacy_pk = legacy_chat_account_pubkey(&from_user.pubkey(), program_id);
+    let strategy = AddressStrategy::default();
+    let chat_pk = infer_chat_account_pubkey(&from_user.pubkey(), program_id, &strategy)?;
+
+    let legacy_data = rpc_client.get_account_data(&legacy_pk)?;
+    let legacy_account = parse_legacy_account(&legacy_data[..])?;
+    info!(
+        "Account {} has {} messages to migrate",
+        legacy_pk,
+        legacy_account.messages.len()
+    );
+
+    // messages are only ever appended by the migration, so the PDA's count tells how far we got
+    let copied = match rpc_client.get_account_data(&chat_pk) {
+        Ok(data) => {
+            let (account_metadata, _) = deserialize_account_data(&data[..])?;
+            println!("Account {} already exist", chat_pk);
+            account_metadata.message_count as usize
+        }
+        Err(_) => {
+            open_account(
+                rpc_client,
+                program_id,
+                &strategy,
+                from_user,
+                None,
+                &legacy_account.account_name,
+                None,
+                &ComputeBudget::default(),
+            )?;
+            0
+        }
+    };
+
+    let remaining: Vec<Message> = legacy_account.messages.into_iter().skip(copied).collect();
+    if remaining.is_empty() {
+        println!("No messages left to migrate");
+    } else {
+        println!("Migrating {} messages to {}", remaining.len(), chat_pk);
+        send_messages_batched(
+            rpc_client,
+            program_id,
+            from_user,
+            &chat_pk,
+            remaining,
+            &ComputeBudget::default(),
+            None,
+        )?;
+    }
+
+    // the program has no instruction that releases an account, so the rent stays locked for now
+    println!(
+        "Old account {} was left in place, it can't be closed yet",
+        legacy_pk
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::{AccountMetadata, ChatData, ChatInstruction, Message, DEFAULT_SEED};
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signer::keypair::Keypair;
+    use solana_sdk::signer::Signer;
+
+    use crate::rpc::fake::FakeRpc;
+    use fprog::instructions::find_chat_account_pda;
+
+    use super::{legacy_chat_account_pubkey, migrate, parse_legacy_account};
+
+    fn legacy_account_data(account_name: &str, messages: &[(u32, Pubkey, &str)]) -> Vec<u8> {
+        let mut body = vec![];
+        for (id, from, msg) in messages {
+            body.extend_from_slice(&id.to_le_bytes());
+            body.extend_from_slice(from.as_ref());
+            body.extend_from_slice(&(msg.len() as u32).to_le_bytes());
+            body.extend_from_slice(msg.as_bytes());
+        }
+        let metadata_size = 13 + account_name.len();
+        let mut data = vec![1];
+        data.extend_from_slice(&((metadata_size + body.len()) as u32).to_le_bytes());
+        data.extend_from_slice(&(messages.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(account_name.len() as u32).to_le_bytes());
+        data.extend_from_slice(account_name.as_bytes());
+        data.extend_from_slice(&body);
+        data.resize(1024, 0);
+        data
+    }
+
+    fn sent_instructions(rpc: &FakeRpc) -> Vec<ChatInstruction> {
+        rpc.sent
+            .borrow()
+            .iter()
+            .map(|transaction| {
+                ChatInstruction::deserialize(
+                    &transaction.message.instructions.last().unwrap().data[..],
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn legacy_account_parsing() {
+        let alice = Pubkey::new_unique();
+        let data = legacy_account_data("old", &[(0, alice, "hi"), (1, alice, "there")]);
+
+        let legacy_account = parse_legacy_account(&data[..]).unwrap();
+
+        assert_eq!(legacy_account.account_name, "old");
+        assert_eq!(
+            legacy_account.messages,
+            vec![
+                Message::new(0, alice, "hi".to_string()),
+                Message::new(1, alice, "there".to_string()),
+            ]
+        );
+        assert!(parse_legacy_account(&[0; 64]).is_err());
+    }
+
+    #[test]
+    fn migrate_copies_metadata_and_messages() {
+        let program_id = Pubkey::new_unique();
+        let from_user = Keypair::new();
+        let alice = Pubkey::new_unique();
+        let legacy_pk = legacy_chat_account_pubkey(&from_user.pubkey(), &program_id);
+        let (chat_pk, bump) = find_chat_account_pda(&from_user.pubkey(), &program_id, DEFAULT_SEED);
+
+        let opened_account_data = |message_count| {
+            let mut account_metadata = AccountMetadata::new("old", bump).unwrap();
+            account_metadata.message_count = message_count;
+            let mut data = vec![0; 1024];
+            account_metadata
+                .serialize(&mut data[..account_metadata.size()])
+                .unwrap();
+            data
+        };
+
+        let mut rpc = FakeRpc::default();
+        rpc.add_account(from_user.pubkey(), 1_000_000, vec![]);
+        rpc.add_account(
+            legacy_pk,
+            0,
+            legacy_account_data("old", &[(0, alice, "hi"), (1, alice, "there")]),
+        );
+        // missing for the migration's and open_account's checks, there once the open lands
+        rpc.add_account(chat_pk, 0, opened_account_data(0));
+        rpc.hidden_reads.borrow_mut().insert(chat_pk, 2);
+
+        migrate(&rpc, &program_id, &from_user).unwrap();
+
+        assert_eq!(
+            sent_instructions(&rpc),
+            vec![
+                ChatInstruction::OpenAccount {
+                    account_metadata: AccountMetadata {
+                        initialized: 0,
+                        ..AccountMetadata::new("old", bump).unwrap()
+                    },
+                },
+                ChatInstruction::SendMessages {
+                    messages: vec![
+                        Message::new(0, alice, "hi".to_string()),
+                        Message::new(1, alice, "there".to_string()),
+                    ],
+                },
+            ]
+        );
+
+        // a re-run after the first message landed only sends the rest
+        rpc.add_account(chat_pk, 0, opened_account_data(1));
+        rpc.sent.borrow_mut().clear();
+
+        migrate(&rpc, &program_id, &from_user).unwrap();
+
+        assert_eq!(
+            sent_instructions(&rpc),
+            vec![ChatInstruction::SendMessages {
+                messages: vec![Message::new(1, alice, "there".to_string())],
+            }]
+        );
+    }
+}
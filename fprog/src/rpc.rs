@@ -0,0 +1,702 @@
+use log::{debug, warn};
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcSendTransactionConfig, RpcTransactionConfig,
+};
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_sdk::account::Account;
+use solana_sdk::hash::Hash;
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// how the runtime prefixes the program's msg! lines
+static PROGRAM_LOG_PREFIX: &str = "Program log: ";
+
+// The subset of RpcClient the chat commands use, so they can run against a fake in tests
+#[allow(clippy::result_large_err)]
+pub trait ChatRpc {
+    fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account>;
+    fn get_account_data(&self, pubkey: &Pubkey) -> ClientResult<Vec<u8>>;
+    // only data[offset..offset + length], cut short where the account ends
+    fn get_account_data_slice(
+        &self,
+        pubkey: &Pubkey,
+        offset: usize,
+        length: usize,
+    ) -> ClientResult<Vec<u8>>;
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64>;
+    fn get_latest_blockhash(&self) -> ClientResult<Hash>;
+    fn get_fee_for_message(&self, message: &Message) -> ClientResult<u64>;
+    fn send_and_confirm_transaction_with_spinner(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature>;
+    fn send_and_confirm_transaction_with_config(
+        &self,
+        transaction: &Transaction,
+        config: RpcSendTransactionConfig,
+    ) -> ClientResult<Signature>;
+    fn send_transaction(&self, transaction: &Transaction) -> ClientResult<Signature>;
+    // the error the transaction would fail with and the logs it would write, nothing is sent
+    fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<(Option<TransactionError>, Vec<String>)>;
+    // whether a returned signature means the transaction landed
+    fn confirms(&self) -> bool {
+        true
+    }
+    fn get_genesis_hash(&self) -> ClientResult<Hash>;
+    fn request_airdrop(&self, pubkey: &Pubkey, lamports: u64) -> ClientResult<Signature>;
+    fn poll_for_signature(&self, signature: &Signature) -> ClientResult<()>;
+    fn get_transaction_logs(&self, signature: &Signature) -> ClientResult<Vec<String>>;
+    fn get_program_accounts(&self, program_id: &Pubkey) -> ClientResult<Vec<(Pubkey, Account)>>;
+    fn get_health(&self) -> ClientResult<()>;
+    // version of the node's solana-core
+    fn get_version(&self) -> ClientResult<String>;
+    // get_account_data that skips any cached copy, for reads that must see the latest state
+    fn refresh(&self, pubkey: &Pubkey) -> ClientResult<Vec<u8>> {
+        self.get_account_data(pubkey)
+    }
+}
+
+impl ChatRpc for RpcClient {
+    fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+        RpcClient::get_account(self, pubkey)
+    }
+
+    fn get_account_data(&self, pubkey: &Pubkey) -> ClientResult<Vec<u8>> {
+        RpcClient::get_account_data(self, pubkey)
+    }
+
+    fn get_account_data_slice(
+        &self,
+        pubkey: &Pubkey,
+        offset: usize,
+        length: usize,
+    ) -> ClientResult<Vec<u8>> {
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            data_slice: Some(UiDataSliceConfig { offset, length }),
+            commitment: Some(self.commitment()),
+            min_context_slot: None,
+        };
+        match RpcClient::get_account_with_config(self, pubkey, config)?.value {
+            Some(account) => Ok(account.data),
+            // same error get_account_data gives, so callers can tell a missing account apart
+            None => Err(RpcError::ForUser(format!("AccountNotFound: pubkey={}", pubkey)).into()),
+        }
+    }
+
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64> {
+        RpcClient::get_minimum_balance_for_rent_exemption(self, data_len)
+    }
+
+    fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        RpcClient::get_latest_blockhash(self)
+    }
+
+    fn get_fee_for_message(&self, message: &Message) -> ClientResult<u64> {
+        RpcClient::get_fee_for_message(self, message)
+    }
+
+    fn send_and_confirm_transaction_with_spinner(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        RpcClient::send_and_confirm_transaction_with_spinner(self, transaction)
+    }
+
+    fn send_and_confirm_transaction_with_config(
+        &self,
+        transaction: &Transaction,
+        config: RpcSendTransactionConfig,
+    ) -> ClientResult<Signature> {
+        RpcClient::send_and_confirm_transaction_with_spinner_and_config(
+            self,
+            transaction,
+            self.commitment(),
+            config,
+        )
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+        RpcClient::send_transaction(self, transaction)
+    }
+
+    fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<(Option<TransactionError>, Vec<String>)> {
+        let result = RpcClient::simulate_transaction(self, transaction)?.value;
+        Ok((result.err, result.logs.unwrap_or_default()))
+    }
+
+    fn get_genesis_hash(&self) -> ClientResult<Hash> {
+        RpcClient::get_genesis_hash(self)
+    }
+
+    fn request_airdrop(&self, pubkey: &Pubkey, lamports: u64) -> ClientResult<Signature> {
+        RpcClient::request_airdrop(self, pubkey, lamports)
+    }
+
+    fn poll_for_signature(&self, signature: &Signature) -> ClientResult<()> {
+        RpcClient::poll_for_signature(self, signature)
+    }
+
+    fn get_transaction_logs(&self, signature: &Signature) -> ClientResult<Vec<String>> {
+        let config = RpcTransactionConfig {
+            commitment: Some(self.commitment()),
+            max_supported_transaction_version: Some(0),
+            ..RpcTransactionConfig::default()
+        };
+        let transaction = RpcClient::get_transaction_with_config(self, signature, config)?;
+        let logs = transaction
+            .transaction
+            .meta
+            .and_then(|meta| meta.log_messages.into());
+        Ok(logs.unwrap_or_default())
+    }
+
+    fn get_program_accounts(&self, program_id: &Pubkey) -> ClientResult<Vec<(Pubkey, Account)>> {
+        RpcClient::get_program_accounts(self, program_id)
+    }
+
+    fn get_health(&self) -> ClientResult<()> {
+        RpcClient::get_health(self)
+    }
+
+    fn get_version(&self) -> ClientResult<String> {
+        RpcClient::get_version(self).map(|version| version.solana_core)
+    }
+}
+
+// The client the commands run against, --no-confirm swaps every confirming send for a
+// fire-and-forget one so scripts don't wait on the spinner, confirming sends use send_config
+pub struct CliRpc<R> {
+    pub client: R,
+    pub confirm: bool,
+    // --simulate, every send only prints the logs the program would write
+    pub simulate: bool,
+    pub send_config: RpcSendTransactionConfig,
+    // account data read during this command, dropped by every send since it may change it
+    pub cache: RefCell<HashMap<Pubkey, Vec<u8>>>,
+}
+
+#[allow(clippy::result_large_err)]
+impl<R: ChatRpc> CliRpc<R> {
+    // a failing simulation is returned as the send error, a passing one as the signature the
+    // transaction would have had
+    fn simulate(&self, transaction: &Transaction) -> ClientResult<Signature> {
+        let (err, logs) = self.client.simulate_transaction(transaction)?;
+        println!("Simulated, nothing was sent. Program logs:");
+        for log in &logs {
+            println!("  {}", log);
+        }
+        match err {
+            Some(err) => Err(err.into()),
+            None => Ok(transaction.signatures.first().copied().unwrap_or_default()),
+        }
+    }
+
+    // the program's msg! lines behind a failed send, a failed preflight comes with them,
+    // otherwise the transaction is simulated again to get them
+    fn failure_logs(&self, transaction: &Transaction, err: &ClientError) -> Vec<String> {
+        let logs = match err.kind() {
+            ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+                ..
+            }) => result.logs.clone().unwrap_or_default(),
+            _ => match self.client.simulate_transaction(transaction) {
+                Ok((_, logs)) => logs,
+                Err(simulate_err) => {
+                    debug!(
+                        "Couldn't simulate the failed transaction: {:?}",
+                        simulate_err
+                    );
+                    vec![]
+                }
+            },
+        };
+        logs.iter()
+            .filter_map(|log| log.strip_prefix(PROGRAM_LOG_PREFIX))
+            .map(str::to_string)
+            .collect()
+    }
+
+    // the error alone rarely says why the program refused, the logs usually do
+    fn report_failure(&self, transaction: &Transaction, err: &ClientError) {
+        let lines = self.failure_logs(transaction, err);
+        if !lines.is_empty() {
+            println!("Program logs:");
+            for line in lines {
+                println!("  {}", line);
+            }
+        }
+    }
+}
+
+#[allow(clippy::result_large_err)]
+impl<R: ChatRpc> ChatRpc for CliRpc<R> {
+    fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+        self.client.get_account(pubkey)
+    }
+
+    // missing accounts aren't cached, a later read may find them opened
+    fn get_account_data(&self, pubkey: &Pubkey) -> ClientResult<Vec<u8>> {
+        if let Some(data) = self.cache.borrow().get(pubkey) {
+            return Ok(data.clone());
+        }
+        let data = self.client.get_account_data(pubkey)?;
+        self.cache.borrow_mut().insert(*pubkey, data.clone());
+        Ok(data)
+    }
+
+    fn get_account_data_slice(
+        &self,
+        pubkey: &Pubkey,
+        offset: usize,
+        length: usize,
+    ) -> ClientResult<Vec<u8>> {
+        self.client.get_account_data_slice(pubkey, offset, length)
+    }
+
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ClientResult<u64> {
+        self.client.get_minimum_balance_for_rent_exemption(data_len)
+    }
+
+    fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+        self.client.get_latest_blockhash()
+    }
+
+    fn get_fee_for_message(&self, message: &Message) -> ClientResult<u64> {
+        self.client.get_fee_for_message(message)
+    }
+
+    fn send_and_confirm_transaction_with_spinner(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<Signature> {
+        self.cache.borrow_mut().clear();
+        if self.simulate {
+            return self.simulate(transaction);
+        }
+        if self.confirm {
+            return self
+                .client
+                .send_and_confirm_transaction_with_config(transaction, self.send_config)
+                .inspect_err(|err| self.report_failure(transaction, err));
+        }
+        let signature = self
+            .client
+            .send_transaction(transaction)
+            .inspect_err(|err| self.report_failure(transaction, err))?;
+        warn!(
+            "Not waiting for {}, the transaction may not have landed",
+            signature
+        );
+        Ok(signature)
+    }
+
+    fn send_and_confirm_transaction_with_config(
+        &self,
+        transaction: &Transaction,
+        config: RpcSendTransactionConfig,
+    ) -> ClientResult<Signature> {
+        self.cache.borrow_mut().clear();
+        if self.simulate {
+            return self.simulate(transaction);
+        }
+        self.client
+            .send_and_confirm_transaction_with_config(transaction, config)
+            .inspect_err(|err| self.report_failure(transaction, err))
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+        self.cache.borrow_mut().clear();
+        if self.simulate {
+            return self.simulate(transaction);
+        }
+        self.client
+            .send_transaction(transaction)
+            .inspect_err(|err| self.report_failure(transaction, err))
+    }
+
+    fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ClientResult<(Option<TransactionError>, Vec<String>)> {
+        self.client.simulate_transaction(transaction)
+    }
+
+    // a simulated send never lands, so there's nothing to check for afterwards
+    fn confirms(&self) -> bool {
+        self.confirm && !self.simulate
+    }
+
+    fn get_genesis_hash(&self) -> ClientResult<Hash> {
+        self.client.get_genesis_hash()
+    }
+
+    fn request_airdrop(&self, pubkey: &Pubkey, lamports: u64) -> ClientResult<Signature> {
+        self.client.request_airdrop(pubkey, lamports)
+    }
+
+    fn poll_for_signature(&self, signature: &Signature) -> ClientResult<()> {
+        self.client.poll_for_signature(signature)
+    }
+
+    fn get_transaction_logs(&self, signature: &Signature) -> ClientResult<Vec<String>> {
+        self.client.get_transaction_logs(signature)
+    }
+
+    fn get_program_accounts(&self, program_id: &Pubkey) -> ClientResult<Vec<(Pubkey, Account)>> {
+        self.client.get_program_accounts(program_id)
+    }
+
+    fn get_health(&self) -> ClientResult<()> {
+        self.client.get_health()
+    }
+
+    fn get_version(&self) -> ClientResult<String> {
+        self.client.get_version()
+    }
+
+    fn refresh(&self, pubkey: &Pubkey) -> ClientResult<Vec<u8>> {
+        self.cache.borrow_mut().remove(pubkey);
+        self.get_account_data(pubkey)
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use solana_client::client_error::Result as ClientResult;
+    use solana_client::rpc_config::RpcSendTransactionConfig;
+    use solana_client::rpc_request::RpcError;
+    use solana_sdk::account::Account;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::Message;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Signature;
+    use solana_sdk::transaction::{Transaction, TransactionError};
+
+    use super::ChatRpc;
+
+    #[derive(Default)]
+    pub struct FakeRpc {
+        pub accounts: HashMap<Pubkey, Account>,
+        pub rent: u64,
+        pub genesis_hash: Hash,
+        pub sent: RefCell<Vec<Transaction>>,
+        pub airdrops: RefCell<Vec<(Pubkey, u64)>>,
+        pub reads: RefCell<Vec<Pubkey>>,
+        // accounts reported as missing for this many more reads
+        pub hidden_reads: RefCell<HashMap<Pubkey, usize>>,
+        // reads of an account that fail like a dropped connection for this many more reads
+        pub failing_reads: RefCell<HashMap<Pubkey, usize>>,
+        // older data returned for this many more reads, before the account's current data
+        pub stale_reads: RefCell<HashMap<Pubkey, (usize, Vec<u8>)>>,
+        pub logs: Vec<String>,
+        // every account read fails as if the node couldn't be reached
+        pub unreachable: bool,
+        // transactions are recorded but fail with this error
+        pub send_error: Option<TransactionError>,
+        pub fee: u64,
+        // messages a fee was asked for
+        pub priced: RefCell<Vec<Message>>,
+        // transactions sent without waiting for confirmation
+        pub unconfirmed: RefCell<Vec<Transaction>>,
+        // (pubkey, offset, length) of every sliced read
+        pub slices: RefCell<Vec<(Pubkey, usize, usize)>>,
+        // configs of the sends that were given one
+        pub send_configs: RefCell<Vec<RpcSendTransactionConfig>>,
+        // transactions only simulated, they fail with send_error and log logs
+        pub simulated: RefCell<Vec<Transaction>>,
+        // every fetch hands out a new blockhash, filled with the number of the fetch
+        pub blockhash_fetches: RefCell<u8>,
+    }
+
+    impl FakeRpc {
+        pub fn add_account(&mut self, pubkey: Pubkey, lamports: u64, data: Vec<u8>) {
+            let account = Account {
+                lamports,
+                data,
+                ..Account::default()
+            };
+            self.accounts.insert(pubkey, account);
+        }
+    }
+
+    impl ChatRpc for FakeRpc {
+        fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Account> {
+            self.reads.borrow_mut().push(*pubkey);
+            if self.unreachable {
+                return Err(RpcError::RpcRequestError("connection refused".to_string()).into());
+            }
+            if let Some(failing) = self.failing_reads.borrow_mut().get_mut(pubkey) {
+                if *failing > 0 {
+                    *failing -= 1;
+                    return Err(RpcError::RpcRequestError("connection reset".to_string()).into());
+                }
+            }
+            if let Some(hidden) = self.hidden_reads.borrow_mut().get_mut(pubkey) {
+                if *hidden > 0 {
+                    *hidden -= 1;
+                    return Err(
+                        RpcError::ForUser(format!("AccountNotFound: pubkey={}", pubkey)).into(),
+                    );
+                }
+            }
+            let mut account = self.accounts.get(pubkey).cloned();
+            if let (Some(account), Some((reads, data))) = (
+                account.as_mut(),
+                self.stale_reads.borrow_mut().get_mut(pubkey),
+            ) {
+                if *reads > 0 {
+                    *reads -= 1;
+                    account.data = data.clone();
+                }
+            }
+            account.ok_or_else(|| {
+                RpcError::ForUser(format!("AccountNotFound: pubkey={}", pubkey)).into()
+            })
+        }
+
+        fn get_account_data(&self, pubkey: &Pubkey) -> ClientResult<Vec<u8>> {
+            self.get_account(pubkey).map(|account| account.data)
+        }
+
+        fn get_account_data_slice(
+            &self,
+            pubkey: &Pubkey,
+            offset: usize,
+            length: usize,
+        ) -> ClientResult<Vec<u8>> {
+            self.slices.borrow_mut().push((*pubkey, offset, length));
+            let data = self.get_account_data(pubkey)?;
+            let start = offset.min(data.len());
+            let end = offset.saturating_add(length).min(data.len());
+            Ok(data[start..end].to_vec())
+        }
+
+        fn get_minimum_balance_for_rent_exemption(&self, _data_len: usize) -> ClientResult<u64> {
+            Ok(self.rent)
+        }
+
+        fn get_latest_blockhash(&self) -> ClientResult<Hash> {
+            let mut fetches = self.blockhash_fetches.borrow_mut();
+            *fetches = fetches.wrapping_add(1);
+            Ok(Hash::new_from_array([*fetches; 32]))
+        }
+
+        fn get_fee_for_message(&self, message: &Message) -> ClientResult<u64> {
+            self.priced.borrow_mut().push(message.clone());
+            Ok(self.fee)
+        }
+
+        fn send_and_confirm_transaction_with_spinner(
+            &self,
+            transaction: &Transaction,
+        ) -> ClientResult<Signature> {
+            self.sent.borrow_mut().push(transaction.clone());
+            match &self.send_error {
+                Some(err) => Err(err.clone().into()),
+                None => Ok(Signature::default()),
+            }
+        }
+
+        fn send_and_confirm_transaction_with_config(
+            &self,
+            transaction: &Transaction,
+            config: RpcSendTransactionConfig,
+        ) -> ClientResult<Signature> {
+            self.send_configs.borrow_mut().push(config);
+            self.send_and_confirm_transaction_with_spinner(transaction)
+        }
+
+        fn send_transaction(&self, transaction: &Transaction) -> ClientResult<Signature> {
+            self.unconfirmed.borrow_mut().push(transaction.clone());
+            Ok(Signature::default())
+        }
+
+        fn simulate_transaction(
+            &self,
+            transaction: &Transaction,
+        ) -> ClientResult<(Option<TransactionError>, Vec<String>)> {
+            self.simulated.borrow_mut().push(transaction.clone());
+            Ok((self.send_error.clone(), self.logs.clone()))
+        }
+
+        fn get_genesis_hash(&self) -> ClientResult<Hash> {
+            Ok(self.genesis_hash)
+        }
+
+        fn request_airdrop(&self, pubkey: &Pubkey, lamports: u64) -> ClientResult<Signature> {
+            self.airdrops.borrow_mut().push((*pubkey, lamports));
+            Ok(Signature::default())
+        }
+
+        fn poll_for_signature(&self, _signature: &Signature) -> ClientResult<()> {
+            Ok(())
+        }
+
+        fn get_transaction_logs(&self, _signature: &Signature) -> ClientResult<Vec<String>> {
+            Ok(self.logs.clone())
+        }
+
+        fn get_program_accounts(
+            &self,
+            _program_id: &Pubkey,
+        ) -> ClientResult<Vec<(Pubkey, Account)>> {
+            Ok(self
+                .accounts
+                .iter()
+                .map(|(pubkey, account)| (*pubkey, account.clone()))
+                .collect())
+        }
+
+        fn get_health(&self) -> ClientResult<()> {
+            if self.unreachable {
+                return Err(RpcError::RpcRequestError("connection refused".to_string()).into());
+            }
+            Ok(())
+        }
+
+        fn get_version(&self) -> ClientResult<String> {
+            if self.unreachable {
+                return Err(RpcError::RpcRequestError("connection refused".to_string()).into());
+            }
+            Ok("1.18.26".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_client::client_error::ClientError;
+    use solana_client::rpc_config::RpcSendTransactionConfig;
+    use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+    use solana_client::rpc_response::RpcSimulateTransactionResult;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::transaction::{Transaction, TransactionError};
+
+    use super::fake::FakeRpc;
+    use super::{ChatRpc, CliRpc};
+
+    #[test]
+    fn account_data_is_read_once_per_command() {
+        let pubkey = Pubkey::new_unique();
+        let mut client = FakeRpc::default();
+        client.add_account(pubkey, 0, vec![1, 2, 3]);
+        let rpc = CliRpc {
+            client,
+            confirm: true,
+            simulate: false,
+            send_config: RpcSendTransactionConfig::default(),
+            cache: Default::default(),
+        };
+
+        assert_eq!(rpc.get_account_data(&pubkey).unwrap(), vec![1, 2, 3]);
+        assert_eq!(rpc.get_account_data(&pubkey).unwrap(), vec![1, 2, 3]);
+        assert_eq!(*rpc.client.reads.borrow(), vec![pubkey]);
+
+        rpc.refresh(&pubkey).unwrap();
+        assert_eq!(rpc.client.reads.borrow().len(), 2);
+
+        // a send may have changed the account, the next read goes to the node again
+        rpc.send_and_confirm_transaction_with_spinner(&Transaction::default())
+            .unwrap();
+        rpc.get_account_data(&pubkey).unwrap();
+        assert_eq!(rpc.client.reads.borrow().len(), 3);
+    }
+
+    #[test]
+    fn failed_send_surfaces_the_program_logs() {
+        let client = FakeRpc {
+            logs: vec![
+                "Program DidmGHY2FMXTPzxMhiMjNSzwuqcHhJ679yP4NdCQsoqM invoke [1]".to_string(),
+                "Program log: OpenAccount".to_string(),
+                "Program log: Account: work already exist".to_string(),
+                "Program DidmGHY2FMXTPzxMhiMjNSzwuqcHhJ679yP4NdCQsoqM failed".to_string(),
+            ],
+            send_error: Some(TransactionError::AccountInUse),
+            ..FakeRpc::default()
+        };
+        let rpc = CliRpc {
+            client,
+            confirm: true,
+            simulate: false,
+            send_config: RpcSendTransactionConfig::default(),
+            cache: Default::default(),
+        };
+        let transaction = Transaction::default();
+
+        let err = rpc
+            .send_and_confirm_transaction_with_spinner(&transaction)
+            .unwrap_err();
+        assert_eq!(rpc.client.simulated.borrow().len(), 1);
+        assert_eq!(
+            rpc.failure_logs(&transaction, &err),
+            vec!["OpenAccount", "Account: work already exist"]
+        );
+
+        // a failed preflight already carries the logs, nothing is simulated for it
+        let preflight = ClientError::from(RpcError::RpcResponseError {
+            code: -32002,
+            message: "Transaction simulation failed".to_string(),
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(
+                RpcSimulateTransactionResult {
+                    err: Some(TransactionError::AccountInUse),
+                    logs: Some(vec!["Program log: Account name is empty".to_string()]),
+                    accounts: None,
+                    units_consumed: None,
+                    return_data: None,
+                    inner_instructions: None,
+                },
+            ),
+        });
+        assert_eq!(
+            rpc.failure_logs(&transaction, &preflight),
+            vec!["Account name is empty"]
+        );
+        assert_eq!(rpc.client.simulated.borrow().len(), 2);
+    }
+
+    #[test]
+    fn simulate_never_sends() {
+        let client = FakeRpc {
+            logs: vec!["Program log: OpenAccount".to_string()],
+            ..FakeRpc::default()
+        };
+        let mut rpc = CliRpc {
+            client,
+            confirm: true,
+            simulate: true,
+            send_config: RpcSendTransactionConfig::default(),
+            cache: Default::default(),
+        };
+
+        rpc.send_and_confirm_transaction_with_spinner(&Transaction::default())
+            .unwrap();
+        rpc.send_transaction(&Transaction::default()).unwrap();
+        assert!(!rpc.confirms());
+        assert_eq!(rpc.client.simulated.borrow().len(), 2);
+        assert!(rpc.client.sent.borrow().is_empty());
+        assert!(rpc.client.unconfirmed.borrow().is_empty());
+
+        // a transaction that would fail reports the error it would fail with
+        rpc.client.send_error = Some(TransactionError::AccountNotFound);
+        assert!(rpc
+            .send_and_confirm_transaction_with_spinner(&Transaction::default())
+            .is_err());
+    }
+}
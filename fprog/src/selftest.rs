@@ -0,0 +1,117 @@
+use md::data::{
+    AccountMetadata, ChatData, ChatDeserializationError, ChatInstruction, Message, Reaction,
+};
+use solana_sdk::pubkey::Pubkey;
+
+fn roundtrip_data<T: ChatData + Default + PartialEq>(
+    value: &T,
+) -> Result<bool, ChatDeserializationError> {
+    let mut data = vec![0; value.size()];
+    value.serialize(&mut data[..])?;
+    let mut value_new = T::default();
+    value_new.deserialize(&data[..])?;
+    Ok(*value == value_new)
+}
+
+fn roundtrip_instruction(
+    chat_instruction: &ChatInstruction,
+) -> Result<bool, ChatDeserializationError> {
+    let mut data = vec![0; chat_instruction.size()];
+    chat_instruction.serialize(&mut data[..])?;
+    Ok(*chat_instruction == ChatInstruction::deserialize(&data[..])?)
+}
+
+fn report(name: &str, result: Result<bool, ChatDeserializationError>) -> bool {
+    let passed = matches!(result, Ok(true));
+    match result {
+        Ok(true) => println!("PASS {}", name),
+        Ok(false) => println!("FAIL {}: round trip mismatch", name),
+        Err(err) => println!("FAIL {}: {}", name, err),
+    }
+    passed
+}
+
+fn sample_message(id: u32, msg: &str) -> Message {
+    Message::new(id, Pubkey::new_unique(), msg.to_string())
+}
+
+// Round trips a sample of every wire type through the codec, returns true when all match
+pub fn run_selftest() -> bool {
+    let account_metadata = AccountMetadata::new("selftest", 255).unwrap();
+    let instructions = vec![
+        (
+            "ChatInstruction::SendMessages",
+            ChatInstruction::SendMessages {
+                messages: vec![sample_message(0, "hello"), sample_message(1, "world")],
+            },
+        ),
+        (
+            "ChatInstruction::SendUniqueMessages",
+            ChatInstruction::SendUniqueMessages {
+                messages: vec![sample_message(0, "once")],
+            },
+        ),
+        (
+            "ChatInstruction::DeleteMessages",
+            ChatInstruction::DeleteMessages { id: 7 },
+        ),
+        (
+            "ChatInstruction::OpenAccount",
+            ChatInstruction::OpenAccount {
+                account_metadata: AccountMetadata::new("selftest", 254).unwrap(),
+            },
+        ),
+        (
+            "ChatInstruction::DeleteRange",
+            ChatInstruction::DeleteRange {
+                from_id: 1,
+                to_id: 3,
+            },
+        ),
+        ("ChatInstruction::DeleteAll", ChatInstruction::DeleteAll),
+        (
+            "ChatInstruction::CloseAccount",
+            ChatInstruction::CloseAccount,
+        ),
+        (
+            "ChatInstruction::React",
+            ChatInstruction::React {
+                target_id: 1,
+                emoji: "👍".to_string(),
+            },
+        ),
+        (
+            "ChatInstruction::EditMessage",
+            ChatInstruction::EditMessage {
+                id: 1,
+                new_msg: "selftest".to_string(),
+            },
+        ),
+        (
+            "ChatInstruction::TransferOwnership",
+            ChatInstruction::TransferOwnership {
+                new_owner: Pubkey::new_unique(),
+            },
+        ),
+    ];
+
+    let mut passed = report("Message", roundtrip_data(&sample_message(1, "selftest")));
+    passed &= report("AccountMetadata", roundtrip_data(&account_metadata));
+    let reaction = Reaction::new(1, Pubkey::new_unique(), "👍").unwrap();
+    passed &= report("Reaction", roundtrip_data(&reaction));
+    for (name, chat_instruction) in &instructions {
+        passed &= report(name, roundtrip_instruction(chat_instruction));
+    }
+
+    passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_selftest;
+
+    #[test]
+    fn selftest_passes() {
+        assert!(run_selftest());
+    }
+}
@@ -0,0 +1,142 @@
+use md::data::deserialize_account_data;
+use serde_json::json;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use std::error::Error;
+
+use crate::export::OutputFormat;
+use crate::rpc::ChatRpc;
+use crate::verify::verify_account_data;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct UsageStats {
+    pub accounts: usize,
+    pub messages: u64,
+    pub bytes_used: u64,
+    pub bytes_allocated: u64,
+    // program accounts the codec can't read, counted but left out of the totals
+    pub unreadable: usize,
+}
+
+impl UsageStats {
+    pub fn average_messages(&self) -> f64 {
+        if self.accounts == 0 {
+            return 0.0;
+        }
+        self.messages as f64 / self.accounts as f64
+    }
+}
+
+pub fn usage_stats(accounts: &[(Pubkey, Account)]) -> UsageStats {
+    let mut stats = UsageStats::default();
+    for (_, account) in accounts {
+        // corrupt accounts would make the codec panic, so check them first
+        let account_metadata = if verify_account_data(&account.data[..]).is_empty() {
+            deserialize_account_data(&account.data[..]).ok()
+        } else {
+            None
+        };
+        match account_metadata {
+            Some((account_metadata, _)) => {
+                stats.accounts += 1;
+                stats.messages += account_metadata.message_count as u64;
+                stats.bytes_used += account_metadata.next_free_index as u64;
+                stats.bytes_allocated += account.data.len() as u64;
+            }
+            None => stats.unreadable += 1,
+        }
+    }
+    stats
+}
+
+pub fn stats(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let accounts = rpc_client.get_program_accounts(program_id)?;
+    let stats = usage_stats(&accounts);
+
+    if format == OutputFormat::Json {
+        let json = json!({
+            "accounts": stats.accounts,
+            "messages": stats.messages,
+            "average_messages": stats.average_messages(),
+            "bytes_used": stats.bytes_used,
+            "bytes_allocated": stats.bytes_allocated,
+            "unreadable": stats.unreadable,
+        });
+        println!("{}", json);
+        return Ok(());
+    }
+    println!("{:<20}{:>12}", "accounts", stats.accounts);
+    println!("{:<20}{:>12}", "messages", stats.messages);
+    println!(
+        "{:<20}{:>12.2}",
+        "average messages",
+        stats.average_messages()
+    );
+    println!("{:<20}{:>12}", "bytes used", stats.bytes_used);
+    println!("{:<20}{:>12}", "bytes allocated", stats.bytes_allocated);
+    println!("{:<20}{:>12}", "unreadable", stats.unreadable);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::{serialize_messages, AccountMetadata, ChatData, Message};
+    use solana_sdk::account::Account;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::{usage_stats, UsageStats};
+
+    fn account(message_count: usize) -> (Pubkey, Account) {
+        let messages: Vec<Message> = (0..message_count)
+            .map(|id| Message::new(id as u32, Pubkey::new_unique(), "hi".to_string()))
+            .collect();
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let metadata_size = account_metadata.size();
+        let messages_size: usize = messages.iter().map(|message| message.size()).sum();
+        account_metadata.next_free_index = (metadata_size + messages_size) as u32;
+        account_metadata.message_count = messages.len() as u32;
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..metadata_size])
+            .unwrap();
+        serialize_messages(&messages, &mut data[metadata_size..]).unwrap();
+        let account = Account {
+            data,
+            ..Account::default()
+        };
+        (Pubkey::new_unique(), account)
+    }
+
+    #[test]
+    fn stats_over_accounts() {
+        let one = account(1);
+        let three = account(3);
+        let metadata_size = AccountMetadata::new("abc", 255).unwrap().size() as u64;
+        let message_size = (Message::MESSAGE_BASE_SIZE + 2) as u64;
+        let garbage = (
+            Pubkey::new_unique(),
+            Account {
+                data: vec![1; 8],
+                ..Account::default()
+            },
+        );
+
+        let stats = usage_stats(&[one, three, garbage]);
+
+        assert_eq!(
+            stats,
+            UsageStats {
+                accounts: 2,
+                messages: 4,
+                bytes_used: 2 * metadata_size + 4 * message_size,
+                bytes_allocated: 2048,
+                unreadable: 1,
+            }
+        );
+        assert_eq!(stats.average_messages(), 2.0);
+    }
+}
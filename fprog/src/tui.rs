@@ -0,0 +1,274 @@
+use md::data::Message;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signer::keypair::Keypair;
+use solana_sdk::signer::Signer;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use fprog::instructions::AddressStrategy;
+
+use crate::account::Cursor;
+use crate::chat::{
+    chat_account_to_read, message_line, read_chat_account, send_message, SendOptions,
+};
+use crate::rpc::ChatRpc;
+
+// how long to wait for a key before redrawing and checking whether a poll is due
+const TICK: Duration = Duration::from_millis(100);
+const PAGE: usize = 10;
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    None,
+    Send(String),
+    Quit,
+}
+
+#[derive(Default)]
+struct App {
+    account_name: String,
+    lines: Vec<String>,
+    cursor: Cursor,
+    input: String,
+    // lines scrolled back from the newest one
+    scroll: usize,
+    status: String,
+}
+
+impl App {
+    // raw mode turns ctrl-c into a key press, so it quits like Esc instead of killing the
+    // process with the terminal still in raw mode
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.kind != KeyEventKind::Press {
+            return Action::None;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+            KeyCode::Esc => Action::Quit,
+            KeyCode::Enter if !self.input.trim().is_empty() => {
+                Action::Send(std::mem::take(&mut self.input))
+            }
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                Action::None
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                Action::None
+            }
+            KeyCode::Up => self.scroll_back(1),
+            KeyCode::PageUp => self.scroll_back(PAGE),
+            KeyCode::Down => self.scroll_forward(1),
+            KeyCode::PageDown => self.scroll_forward(PAGE),
+            _ => Action::None,
+        }
+    }
+
+    fn scroll_back(&mut self, lines: usize) -> Action {
+        self.scroll = (self.scroll + lines).min(self.lines.len().saturating_sub(1));
+        Action::None
+    }
+
+    fn scroll_forward(&mut self, lines: usize) -> Action {
+        self.scroll = self.scroll.saturating_sub(lines);
+        Action::None
+    }
+
+    // keeps a scrolled back view on the same lines while new ones arrive below
+    fn push_line(&mut self, line: String) {
+        self.lines.push(line);
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+
+    fn push_messages(&mut self, messages: &[&Message]) {
+        for message in messages {
+            self.push_line(message_line(
+                message,
+                &message.from.to_string(),
+                None,
+                false,
+            ));
+        }
+    }
+
+    fn poll(&mut self, rpc_client: &impl ChatRpc, chat_account: &Pubkey) {
+        match read_chat_account(rpc_client, chat_account, false, None) {
+            Ok(Some(chat_account)) => {
+                self.account_name = chat_account.name().to_string();
+                let messages = self.cursor.unseen(&chat_account.messages);
+                self.cursor.advance(&chat_account.messages, &messages);
+                self.push_messages(&messages);
+                self.status = format!("{} messages", chat_account.messages.len());
+            }
+            Ok(None) => self.status = "account is empty".to_string(),
+            Err(err) => self.status = err.to_string(),
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame, to_user: &Pubkey) {
+        let [messages_area, input_area, status_area] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let height = messages_area.height.saturating_sub(2) as usize;
+        let end = self.lines.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(height);
+        let messages = Paragraph::new(self.lines[start..end].join("\n")).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" {} ", self.account_name)),
+        );
+        frame.render_widget(messages, messages_area);
+
+        let input = Paragraph::new(self.input.as_str()).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" to {} ", to_user)),
+        );
+        frame.render_widget(input, input_area);
+        frame.set_cursor_position((
+            input_area.x + 1 + self.input.chars().count() as u16,
+            input_area.y + 1,
+        ));
+
+        let help = "Enter sends, Up/Down/PgUp/PgDn scroll, Esc quits";
+        frame.render_widget(
+            Paragraph::new(format!("{} | {}", self.status, help)),
+            status_area,
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    to_user: &Pubkey,
+    options: &SendOptions,
+    interval: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let chat_account = chat_account_to_read(&from_user.pubkey(), None, program_id, strategy)?;
+    let mut app = App::default();
+    let mut next_poll = Instant::now();
+    loop {
+        if Instant::now() >= next_poll {
+            app.poll(rpc_client, &chat_account);
+            next_poll = Instant::now() + interval;
+        }
+        terminal.draw(|frame| app.draw(frame, to_user))?;
+
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+        match app.handle_key(key) {
+            Action::None => {}
+            Action::Quit => return Ok(()),
+            Action::Send(msg) => {
+                let result = send_message(
+                    rpc_client,
+                    program_id,
+                    from_user,
+                    to_user,
+                    vec![msg.clone()],
+                    options,
+                );
+                // send_message reports on stdout, which lands on top of the screen
+                terminal.clear()?;
+                match result {
+                    Ok(()) => {
+                        app.push_line(format!("[sent] you: {}", msg));
+                        app.status = "sent".to_string();
+                    }
+                    Err(err) => {
+                        app.input = msg;
+                        app.status = format!("send failed: {}", err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// full screen chat with the messages of your own account above an input box that sends to
+// to_user, the terminal is restored however the loop ends
+pub fn run_tui(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    to_user: &Pubkey,
+    options: &SendOptions,
+    interval: Duration,
+) -> Result<(), Box<dyn Error>> {
+    // also installs a panic hook that restores the terminal before the message is printed
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(
+        &mut terminal,
+        rpc_client,
+        program_id,
+        strategy,
+        from_user,
+        to_user,
+        options,
+        interval,
+    );
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::{Action, App};
+
+    fn press(app: &mut App, code: KeyCode) -> Action {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn keys_edit_send_scroll_and_quit() {
+        let mut app = App::default();
+        assert_eq!(press(&mut app, KeyCode::Enter), Action::None);
+        for c in "hiy".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Backspace);
+        assert_eq!(
+            press(&mut app, KeyCode::Enter),
+            Action::Send("hi".to_string())
+        );
+        assert!(app.input.is_empty());
+
+        for line in 0..5 {
+            app.push_line(line.to_string());
+        }
+        press(&mut app, KeyCode::PageUp);
+        assert_eq!(app.scroll, 4);
+        // new lines keep a scrolled back view in place
+        app.push_line("5".to_string());
+        assert_eq!(app.scroll, 5);
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.scroll, 4);
+
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(app.handle_key(ctrl_c), Action::Quit);
+        assert_eq!(press(&mut app, KeyCode::Esc), Action::Quit);
+    }
+}
@@ -0,0 +1,289 @@
+use log::error;
+use md::data::{AccountMetadata, ChatData, Message};
+use solana_sdk::account_utils::StateMut;
+use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_sdk::hash::hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signer::keypair::Keypair;
+use solana_sdk::signer::Signer;
+use std::error::Error;
+use std::mem;
+
+use fprog::instructions::AddressStrategy;
+
+use crate::chat::chat_account_to_read;
+use crate::error::ChatClientError;
+use crate::export::sha256_hex;
+use crate::rpc::ChatRpc;
+
+const U8_SIZE: usize = mem::size_of::<u8>();
+const U32_SIZE: usize = mem::size_of::<u32>();
+
+// walks the account buffer by hand so a corrupt length is reported instead of panicking
+pub fn verify_account_data(data: &[u8]) -> Vec<String> {
+    let mut problems = vec![];
+    if data.len() < AccountMetadata::ACCOUNT_METADATA_BASE_SIZE {
+        problems.push(format!("account holds only {} bytes", data.len()));
+        return problems;
+    }
+    let metadata_size = match AccountMetadata::calculate_size_from_buffer(data) {
+        Ok(metadata_size) => metadata_size,
+        Err(_) => {
+            problems.push("metadata lengths overflow".to_string());
+            return problems;
+        }
+    };
+    if metadata_size > data.len() {
+        problems.push(format!(
+            "metadata takes {} bytes but the account holds {}",
+            metadata_size,
+            data.len()
+        ));
+        return problems;
+    }
+    let mut account_metadata = AccountMetadata::default();
+    if account_metadata
+        .deserialize(&data[..metadata_size])
+        .is_err()
+    {
+        problems.push("metadata can't be deserialized".to_string());
+        return problems;
+    }
+    if account_metadata.initialized == 0 {
+        problems.push("account isn't initialized".to_string());
+    }
+
+    let next_free_index = account_metadata.next_free_index as usize;
+    if next_free_index < metadata_size || next_free_index > data.len() {
+        problems.push(format!(
+            "next_free_index={} is outside the message region {}..{}",
+            next_free_index,
+            metadata_size,
+            data.len()
+        ));
+        return problems;
+    }
+
+    let mut start = metadata_size;
+    let mut message_count = 0;
+    while start + Message::MESSAGE_BASE_SIZE <= next_free_index {
+        let stored_size_offset = start + Message::MESSAGE_BASE_SIZE - U32_SIZE;
+        let mut stored_size = [0; U32_SIZE];
+        stored_size.copy_from_slice(&data[stored_size_offset..stored_size_offset + U32_SIZE]);
+        let flags_size = Message::flags_size(data[stored_size_offset - U8_SIZE]);
+        let end = start
+            + Message::MESSAGE_BASE_SIZE
+            + flags_size
+            + u32::from_le_bytes(stored_size) as usize;
+        if end > next_free_index {
+            problems.push(format!(
+                "message at offset {} runs past next_free_index={}",
+                start, next_free_index
+            ));
+            break;
+        }
+        let mut message = Message::default();
+        if message.deserialize(&data[start..end]).is_err() {
+            problems.push(format!("message at offset {} can't be deserialized", start));
+            break;
+        }
+        message_count += 1;
+        start = end;
+    }
+
+    if start != next_free_index {
+        problems.push(format!(
+            "next_free_index={} but metadata and messages occupy {} bytes",
+            next_free_index, start
+        ));
+    }
+    if message_count != account_metadata.message_count {
+        problems.push(format!(
+            "message_count={} but {} messages were found",
+            account_metadata.message_count, message_count
+        ));
+    }
+    if data[next_free_index..].iter().any(|b| *b != 0) {
+        problems.push(format!(
+            "bytes after next_free_index={} aren't zeroed",
+            next_free_index
+        ));
+    }
+
+    problems
+}
+
+// prints OK or CORRUPT with every problem found, returns whether the account is sound
+pub fn verify_account(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    strategy: &AddressStrategy,
+    from_user: &Keypair,
+    owner_pubkey: Option<&Pubkey>,
+) -> Result<bool, Box<dyn Error>> {
+    let chat_account =
+        chat_account_to_read(&from_user.pubkey(), owner_pubkey, program_id, strategy)?;
+    let data = rpc_client.get_account_data(&chat_account)?;
+
+    let problems = verify_account_data(&data[..]);
+    if problems.is_empty() {
+        println!("OK {}", chat_account);
+        return Ok(true);
+    }
+    println!("CORRUPT {}", chat_account);
+    for problem in &problems {
+        println!("  {}", problem);
+    }
+    Ok(false)
+}
+
+// the code the loader runs: the program account itself for the older loaders, the programdata
+// account it points at for the upgradeable one, without the zero padding a --max-len deploy
+// leaves after the ELF
+pub fn program_executable(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let program = rpc_client
+        .get_account(program_id)
+        .map_err(|err| ChatClientError::from_account_read(program_id, err))?;
+    let mut data = if program.owner == bpf_loader_upgradeable::id() {
+        let programdata_address = match program.state()? {
+            UpgradeableLoaderState::Program {
+                programdata_address,
+            } => programdata_address,
+            state => return Err(format!("{} isn't a program but {:?}", program_id, state).into()),
+        };
+        let programdata = rpc_client.get_account_data(&programdata_address)?;
+        let metadata_size = UpgradeableLoaderState::size_of_programdata_metadata();
+        if programdata.len() < metadata_size {
+            return Err(format!("programdata {} is truncated", programdata_address).into());
+        }
+        programdata[metadata_size..].to_vec()
+    } else {
+        program.data
+    };
+    let end = data
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |last| last + 1);
+    data.truncate(end);
+    Ok(data)
+}
+
+// prints the hash either way, returns whether it's the expected one
+pub fn verify_program(
+    rpc_client: &impl ChatRpc,
+    program_id: &Pubkey,
+    expected_hash: &[u8; 32],
+) -> Result<bool, Box<dyn Error>> {
+    let executable = program_executable(rpc_client, program_id)?;
+    let actual_hash = hash(&executable).to_bytes();
+    if actual_hash == *expected_hash {
+        println!("OK {} has sha256 {}", program_id, sha256_hex(&actual_hash));
+        return Ok(true);
+    }
+    error!("Program {} doesn't match the expected hash", program_id);
+    println!("MISMATCH {} may have been tampered with", program_id);
+    println!("  expected sha256 {}", sha256_hex(expected_hash));
+    println!("  actual   sha256 {}", sha256_hex(&actual_hash));
+    println!("  don't send anything to it until you know why");
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use md::data::{serialize_messages, AccountMetadata, ChatData, Message};
+    use solana_sdk::account::Account;
+    use solana_sdk::account_utils::StateMut;
+    use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+    use solana_sdk::hash::hash;
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::rpc::fake::FakeRpc;
+
+    use super::{verify_account_data, verify_program};
+
+    fn account_data(messages: &[Message]) -> (AccountMetadata, Vec<u8>) {
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let metadata_size = account_metadata.size();
+        let messages_size: usize = messages.iter().map(|message| message.size()).sum();
+        account_metadata.next_free_index = (metadata_size + messages_size) as u32;
+        account_metadata.message_count = messages.len() as u32;
+        let mut data = vec![0; 1024];
+        account_metadata
+            .serialize(&mut data[..metadata_size])
+            .unwrap();
+        serialize_messages(messages, &mut data[metadata_size..]).unwrap();
+        (account_metadata, data)
+    }
+
+    #[test]
+    fn inconsistent_account_is_flagged() {
+        let from = Pubkey::new_unique();
+        let messages = vec![
+            Message::new(0, from, "one".to_string()),
+            Message::new(1, from, "two".to_string()),
+        ];
+        let (mut account_metadata, mut data) = account_data(&messages);
+        assert!(verify_account_data(&data).is_empty());
+
+        // pretend the second message is shorter than it is
+        let metadata_size = account_metadata.size();
+        account_metadata.next_free_index -= 2;
+        account_metadata
+            .serialize(&mut data[..metadata_size])
+            .unwrap();
+
+        let problems = verify_account_data(&data);
+        assert!(problems.contains(&format!(
+            "message at offset {} runs past next_free_index={}",
+            metadata_size + messages[0].size(),
+            account_metadata.next_free_index
+        )));
+        assert!(problems.contains(&"message_count=2 but 1 messages were found".to_string()));
+        assert_eq!(verify_account_data(&data[..4]).len(), 1);
+    }
+
+    #[test]
+    fn program_hash_covers_the_programdata_elf() {
+        let elf = b"\x7fELF chat program".to_vec();
+        let program_id = Pubkey::new_unique();
+        let programdata_address = Pubkey::new_unique();
+
+        let mut program = Account {
+            owner: bpf_loader_upgradeable::id(),
+            executable: true,
+            data: vec![0; UpgradeableLoaderState::size_of_program()],
+            ..Account::default()
+        };
+        program
+            .set_state(&UpgradeableLoaderState::Program {
+                programdata_address,
+            })
+            .unwrap();
+        // deployed with room to grow, the padding isn't part of the program
+        let metadata_size = UpgradeableLoaderState::size_of_programdata_metadata();
+        let mut programdata = Account {
+            owner: bpf_loader_upgradeable::id(),
+            data: vec![0; metadata_size + elf.len() + 64],
+            ..Account::default()
+        };
+        programdata
+            .set_state(&UpgradeableLoaderState::ProgramData {
+                slot: 1,
+                upgrade_authority_address: Some(Pubkey::new_unique()),
+            })
+            .unwrap();
+        programdata.data[metadata_size..metadata_size + elf.len()].copy_from_slice(&elf);
+
+        let mut rpc = FakeRpc::default();
+        rpc.accounts.insert(program_id, program);
+        rpc.accounts.insert(programdata_address, programdata);
+
+        let expected = hash(&elf).to_bytes();
+        assert!(verify_program(&rpc, &program_id, &expected).unwrap());
+        assert!(!verify_program(&rpc, &program_id, &[0; 32]).unwrap());
+        assert!(verify_program(&rpc, &Pubkey::new_unique(), &expected).is_err());
+    }
+}
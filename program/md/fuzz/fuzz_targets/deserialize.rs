@@ -0,0 +1,42 @@
+// Run from program/md with a nightly toolchain:
+//
+//     cargo install cargo-fuzz
+//     cargo +nightly fuzz run deserialize
+//
+// crashing inputs land in fuzz/artifacts/deserialize, replay one by passing its path after the
+// target name
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use md::data::{
+    deserialize_account_data, deserialize_account_data_up_to, deserialize_messages,
+    AccountMetadata, ChatData, ChatInstruction, Message,
+};
+
+// whatever decodes has to encode into exactly its size and decode back to itself
+fn round_trip(messages: &[Message]) {
+    for message in messages {
+        let mut bytes = vec![0; message.size()];
+        message.serialize(&mut bytes).unwrap();
+        let mut decoded = Message::default();
+        decoded.deserialize(&bytes).unwrap();
+        assert_eq!(&decoded, message);
+    }
+}
+
+// the same bytes go through every entry point, garbage may only ever come back as an Err
+fuzz_target!(|data: &[u8]| {
+    let _ = ChatInstruction::deserialize(data);
+    let _ = AccountMetadata::default().deserialize(data);
+    let _ = AccountMetadata::calculate_size_from_buffer(data);
+    if let Ok(messages) = deserialize_messages(data) {
+        round_trip(&messages);
+    }
+    if let Ok((_, Some(messages))) = deserialize_account_data(data) {
+        round_trip(&messages);
+    }
+    if let Ok((_, Some(messages), _)) = deserialize_account_data_up_to(data, 4) {
+        assert!(messages.len() <= 4);
+        round_trip(&messages);
+    }
+});
@@ -1,26 +1,85 @@
 use std::{fmt, mem};
 
 use arrayref::array_ref;
-use solana_program::{
-    msg,
-    pubkey::{Pubkey, PUBKEY_BYTES},
-};
+use solana_program::hash::hashv;
+use solana_program::pubkey::{Pubkey, MAX_SEED_LEN, PUBKEY_BYTES};
 
-pub const MINIMUM_MESSAGE_DATA_SIZE: usize =
-    mem::size_of::<u32>() + PUBKEY_BYTES + mem::size_of::<u32>() + 1;
+use crate::error::ChatError;
+
+pub const MINIMUM_MESSAGE_DATA_SIZE: usize = mem::size_of::<u32>()
+    + PUBKEY_BYTES * 2
+    + mem::size_of::<i64>()
+    + mem::size_of::<u32>()
+    + mem::size_of::<u8>()
+    + mem::size_of::<u8>()
+    + mem::size_of::<u32>()
+    + 1;
 
 pub const MINIMUM_OPEN_ACCOUNT_DATA_SIZE: usize =
-    (mem::size_of::<u32>() * 3) + mem::size_of::<u8>() + 1 + 1;
+    (mem::size_of::<u32>() * 7) + (mem::size_of::<u8>() * 2) + PUBKEY_BYTES + 1 + 1;
+
+pub const MAX_ACCOUNT_NAME_LEN: usize = 64;
+
+// chat accounts are PDAs of [seed, user, bump], a user gets one account per seed
+pub const DEFAULT_SEED: &str = "chat";
+
+// the program allocates this much and the client pays rent for it, so both read it from here
+pub const CHAT_ACCOUNT_SIZE: usize = 5 * 1024;
+
+// each blocked sender takes a pubkey in the metadata, pushing the messages back by 32 bytes
+pub const MAX_BLOCKED_SENDERS: usize = 16;
+
+// reactions sit after the block list in fixed MAX_EMOJI_LEN slots, so the metadata
+// size can still be computed from the counts alone
+pub const MAX_REACTIONS: usize = 32;
+pub const MAX_EMOJI_LEN: usize = 8;
+
+// longest body MessageBuilder accepts, before compression
+pub const MAX_MESSAGE_LEN: usize = 1024;
 
 const U32_SIZE: usize = mem::size_of::<u32>();
 const U8_SIZE: usize = mem::size_of::<u8>();
+const I64_SIZE: usize = mem::size_of::<i64>();
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum ChatCommand {
     SendMessages = 0,
     DeleteMessages = 1,
     OpenAccount = 2,
+    DeleteRange = 3,
+    DeleteAll = 4,
+    CloseAccount = 5,
+    BlockSender = 6,
+    React = 7,
+    TransferOwnership = 8,
+    EditMessage = 9,
+    UnblockSender = 10,
+    SendUniqueMessages = 11,
+    PurgeExpired = 12,
+}
+
+impl TryFrom<u8> for ChatCommand {
+    type Error = ChatDeserializationError;
+
+    fn try_from(tag: u8) -> Result<Self, Self::Error> {
+        match tag {
+            0 => Ok(ChatCommand::SendMessages),
+            1 => Ok(ChatCommand::DeleteMessages),
+            2 => Ok(ChatCommand::OpenAccount),
+            3 => Ok(ChatCommand::DeleteRange),
+            4 => Ok(ChatCommand::DeleteAll),
+            5 => Ok(ChatCommand::CloseAccount),
+            6 => Ok(ChatCommand::BlockSender),
+            7 => Ok(ChatCommand::React),
+            8 => Ok(ChatCommand::TransferOwnership),
+            9 => Ok(ChatCommand::EditMessage),
+            10 => Ok(ChatCommand::UnblockSender),
+            11 => Ok(ChatCommand::SendUniqueMessages),
+            12 => Ok(ChatCommand::PurgeExpired),
+            _ => Err(ChatDeserializationError),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -39,43 +98,266 @@ pub trait ChatData {
     fn deserialize(&mut self, data: &[u8]) -> Result<(), ChatDeserializationError>;
 }
 
-#[derive(Debug, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Message {
     pub id: u32,
     pub from: Pubkey,
+    // user the message is addressed to, left as the default pubkey for the account owner
+    pub to: Pubkey,
+    // unix time the program stored the message at, whatever the client sends is overwritten
+    pub timestamp: i64,
     pub msg_size: u32,
     pub msg: String,
+    // when set the body is stored as compressed_msg, msg_size stays the uncompressed length
+    pub compressed: u8,
+    pub compressed_msg: Vec<u8>,
+    // sha256 of a file kept off chain, stored ahead of the body behind a presence flag
+    pub attachment_hash: Option<[u8; 32]>,
+    // id of the message this one answers, stored after the attachment hash behind its own flag
+    pub reply_to: Option<u32>,
+    // unix time after which readers hide the message and PurgeExpired removes it
+    pub expires_at: Option<i64>,
 }
 
 impl Message {
+    pub const MESSAGE_BASE_SIZE: usize =
+        U32_SIZE + PUBKEY_BYTES * 2 + I64_SIZE + U32_SIZE + U8_SIZE + U8_SIZE + U32_SIZE;
+    pub const ATTACHMENT_HASH_SIZE: usize = 32;
+    // bits of the presence byte ahead of the stored size, 0 and 1 keep their old meaning
+    pub const ATTACHMENT_FLAG: u8 = 1;
+    pub const REPLY_FLAG: u8 = 2;
+    pub const EXPIRY_FLAG: u8 = 4;
+    // content ids keep the top bit set, sequential ids stay below it, so the two never meet
+    pub const CONTENT_ID_FLAG: u32 = 1 << 31;
+
     pub fn new(id: u32, from: Pubkey, msg: String) -> Self {
         let mut message = Message {
             id,
             from,
             msg_size: 0,
             msg,
+            ..Message::default()
         };
         message.msg_size = message.msg.len() as u32;
         message
     }
+
+    // number of body bytes actually stored on chain
+    pub fn stored_size(&self) -> usize {
+        if self.compressed > 0 {
+            self.compressed_msg.len()
+        } else {
+            self.msg_size as usize
+        }
+    }
+
+    // low 31 bits of sha256(from || timestamp || body) with CONTENT_ID_FLAG set, the id
+    // SendUniqueMessages stores, computed over the timestamp the client sent before the
+    // program stamps its own
+    pub fn content_id(&self) -> u32 {
+        let body = if self.compressed > 0 {
+            &self.compressed_msg[..]
+        } else {
+            self.msg.as_bytes()
+        };
+        let hash = hashv(&[self.from.as_ref(), &self.timestamp.to_le_bytes(), body]);
+        u32::from_le_bytes(*array_ref![hash.as_ref(), 0, U32_SIZE]) | Message::CONTENT_ID_FLAG
+    }
+
+    pub fn has_content_id(&self) -> bool {
+        self.id & Message::CONTENT_ID_FLAG != 0
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.attachment_hash.is_some() {
+            flags |= Message::ATTACHMENT_FLAG;
+        }
+        if self.reply_to.is_some() {
+            flags |= Message::REPLY_FLAG;
+        }
+        if self.expires_at.is_some() {
+            flags |= Message::EXPIRY_FLAG;
+        }
+        flags
+    }
+
+    // bytes between the fixed part and the body for a given presence byte
+    pub fn flags_size(flags: u8) -> usize {
+        let mut size = 0;
+        if flags & Message::ATTACHMENT_FLAG > 0 {
+            size += Message::ATTACHMENT_HASH_SIZE;
+        }
+        if flags & Message::REPLY_FLAG > 0 {
+            size += U32_SIZE;
+        }
+        if flags & Message::EXPIRY_FLAG > 0 {
+            size += I64_SIZE;
+        }
+        size
+    }
+}
+
+// checks what Message::new takes on trust: a sender, a non-empty body and its length
+#[derive(Debug, Default)]
+pub struct MessageBuilder {
+    id: u32,
+    from: Option<Pubkey>,
+    to: Pubkey,
+    timestamp: i64,
+    body: String,
+    attachment_hash: Option<[u8; 32]>,
+    reply_to: Option<u32>,
+    expires_at: Option<i64>,
+}
+
+impl MessageBuilder {
+    pub fn new() -> Self {
+        MessageBuilder::default()
+    }
+
+    pub fn id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn from(mut self, from: Pubkey) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn to(mut self, to: Pubkey) -> Self {
+        self.to = to;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+
+    pub fn attachment_hash(mut self, attachment_hash: Option<[u8; 32]>) -> Self {
+        self.attachment_hash = attachment_hash;
+        self
+    }
+
+    pub fn reply_to(mut self, reply_to: u32) -> Self {
+        self.reply_to = Some(reply_to);
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: i64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn build(self) -> Result<Message, ChatError> {
+        let from = self.from.ok_or(ChatError::MissingSender)?;
+        if self.body.is_empty() {
+            return Err(ChatError::EmptyMessage);
+        }
+        if self.body.len() > MAX_MESSAGE_LEN {
+            return Err(ChatError::MessageTooLong);
+        }
+        let mut message = Message::new(self.id, from, self.body);
+        message.to = self.to;
+        message.timestamp = self.timestamp;
+        message.attachment_hash = self.attachment_hash;
+        message.reply_to = self.reply_to;
+        message.expires_at = self.expires_at;
+        Ok(message)
+    }
 }
 
 impl ChatData for Message {
     fn size(&self) -> usize {
-        U32_SIZE + PUBKEY_BYTES + self.msg_size as usize + U32_SIZE
+        Message::MESSAGE_BASE_SIZE + Message::flags_size(self.flags()) + self.stored_size()
     }
     fn deserialize(&mut self, data: &[u8]) -> Result<(), ChatDeserializationError> {
-        let id = u32::from_le_bytes(*array_ref!(data, 0, U32_SIZE));
-        let from = Pubkey::new_from_array(*array_ref!(data, U32_SIZE, PUBKEY_BYTES));
-        let msg_size = u32::from_le_bytes(*array_ref!(data, U32_SIZE + PUBKEY_BYTES, U32_SIZE));
-        let msg_start = (U32_SIZE * 2) + PUBKEY_BYTES;
-        let msg_end = msg_start + msg_size as usize;
-        let msg = String::from_utf8_lossy(&data[msg_start..msg_end]).into_owned();
+        if data.len() < Message::MESSAGE_BASE_SIZE {
+            return Err(ChatDeserializationError);
+        }
+        let mut start: usize = 0;
+        let id = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let from = Pubkey::new_from_array(*array_ref!(data, start, PUBKEY_BYTES));
+
+        start += PUBKEY_BYTES;
+        let to = Pubkey::new_from_array(*array_ref!(data, start, PUBKEY_BYTES));
+
+        start += PUBKEY_BYTES;
+        let timestamp = i64::from_le_bytes(*array_ref!(data, start, I64_SIZE));
+
+        start += I64_SIZE;
+        let msg_size = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let compressed = u8::from_le_bytes(*array_ref!(data, start, U8_SIZE));
+
+        start += U8_SIZE;
+        let flags = u8::from_le_bytes(*array_ref!(data, start, U8_SIZE));
+
+        start += U8_SIZE;
+        let stored_size = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        if data.len() < start + Message::flags_size(flags) + stored_size as usize {
+            return Err(ChatDeserializationError);
+        }
+        // an uncompressed body is stored as is, serialize sizes its slot by msg_size
+        if compressed == 0 && msg_size != stored_size {
+            return Err(ChatDeserializationError);
+        }
+        let attachment_hash = if flags & Message::ATTACHMENT_FLAG > 0 {
+            let hash = *array_ref!(data, start, Message::ATTACHMENT_HASH_SIZE);
+            start += Message::ATTACHMENT_HASH_SIZE;
+            Some(hash)
+        } else {
+            None
+        };
+        let reply_to = if flags & Message::REPLY_FLAG > 0 {
+            let reply_to = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+            start += U32_SIZE;
+            Some(reply_to)
+        } else {
+            None
+        };
+        let expires_at = if flags & Message::EXPIRY_FLAG > 0 {
+            let expires_at = i64::from_le_bytes(*array_ref!(data, start, I64_SIZE));
+            start += I64_SIZE;
+            Some(expires_at)
+        } else {
+            None
+        };
+        let body = &data[start..start + stored_size as usize];
 
         self.id = id;
         self.from = from;
+        self.to = to;
+        self.timestamp = timestamp;
         self.msg_size = msg_size;
-        self.msg = msg;
+        self.compressed = compressed;
+        self.attachment_hash = attachment_hash;
+        self.reply_to = reply_to;
+        self.expires_at = expires_at;
+        if compressed > 0 {
+            self.msg = String::new();
+            self.compressed_msg = body.to_vec();
+        } else {
+            // a lossy decode would change the length msg_size promises
+            self.msg = String::from_utf8(body.to_vec()).map_err(|_| ChatDeserializationError)?;
+            self.compressed_msg = Vec::new();
+        }
 
         Ok(())
     }
@@ -93,35 +375,82 @@ impl ChatData for Message {
         end += PUBKEY_BYTES;
         data[start..end].copy_from_slice(&Pubkey::to_bytes(self.from)[..]);
 
+        start = end;
+        end += PUBKEY_BYTES;
+        data[start..end].copy_from_slice(&Pubkey::to_bytes(self.to)[..]);
+
+        start = end;
+        end += I64_SIZE;
+        data[start..end].copy_from_slice(&i64::to_le_bytes(self.timestamp));
+
         start = end;
         end += U32_SIZE;
         data[start..end].copy_from_slice(&u32::to_le_bytes(self.msg_size));
 
         start = end;
-        end += self.msg_size as usize;
-        data[start..end].copy_from_slice(String::as_bytes(&self.msg));
+        end += U8_SIZE;
+        data[start..end].copy_from_slice(&u8::to_le_bytes(self.compressed));
+
+        start = end;
+        end += U8_SIZE;
+        data[start..end].copy_from_slice(&u8::to_le_bytes(self.flags()));
+
+        start = end;
+        end += U32_SIZE;
+        data[start..end].copy_from_slice(&u32::to_le_bytes(self.stored_size() as u32));
+
+        if let Some(hash) = &self.attachment_hash {
+            start = end;
+            end += Message::ATTACHMENT_HASH_SIZE;
+            data[start..end].copy_from_slice(hash);
+        }
+
+        if let Some(reply_to) = self.reply_to {
+            start = end;
+            end += U32_SIZE;
+            data[start..end].copy_from_slice(&u32::to_le_bytes(reply_to));
+        }
+
+        if let Some(expires_at) = self.expires_at {
+            start = end;
+            end += I64_SIZE;
+            data[start..end].copy_from_slice(&i64::to_le_bytes(expires_at));
+        }
+
+        start = end;
+        end += self.stored_size();
+        if self.compressed > 0 {
+            data[start..end].copy_from_slice(&self.compressed_msg);
+        } else {
+            data[start..end].copy_from_slice(String::as_bytes(&self.msg));
+        }
 
         Ok(())
     }
 }
 
 pub fn deserialize_messages(data: &[u8]) -> Result<Vec<Message>, ChatDeserializationError> {
+    deserialize_messages_up_to(data, usize::MAX).map(|(messages, _)| messages)
+}
+
+// stops after max_messages so a corrupt region can't grow the vector without bound, the flag
+// is set when data was left over
+pub fn deserialize_messages_up_to(
+    data: &[u8],
+    max_messages: usize,
+) -> Result<(Vec<Message>, bool), ChatDeserializationError> {
     let mut messages = Vec::new();
     let mut start = 0;
-    if data.is_empty() {
-        return Ok(messages);
-    }
-    loop {
+    while start < data.len() {
+        if messages.len() >= max_messages {
+            return Ok((messages, true));
+        }
         let mut msg = Message::default();
         msg.deserialize(&data[start..])?;
-        let size = msg.size();
+        start += msg.size();
         messages.push(msg);
-        start += size;
-        if start >= data.len() {
-            break;
-        }
     }
-    Ok(messages)
+    Ok((messages, false))
 }
 
 pub fn serialize_messages(
@@ -140,19 +469,59 @@ pub fn serialize_messages(
 #[derive(Debug, PartialEq)]
 pub enum ChatInstruction {
     SendMessages { messages: Vec<Message> },
+    // like SendMessages but ids come from Message::content_id and repeats are rejected
+    SendUniqueMessages { messages: Vec<Message> },
     DeleteMessages { id: u32 },
     OpenAccount { account_metadata: AccountMetadata },
+    DeleteRange { from_id: u32, to_id: u32 },
+    DeleteAll,
+    CloseAccount,
+    BlockSender { pubkey: Pubkey },
+    UnblockSender { pubkey: Pubkey },
+    React { target_id: u32, emoji: String },
+    TransferOwnership { new_owner: Pubkey },
+    EditMessage { id: u32, new_msg: String },
+    // drops the messages whose expires_at is at or before the cluster clock
+    PurgeExpired,
 }
 
 impl ChatInstruction {
+    pub fn command(&self) -> ChatCommand {
+        match self {
+            ChatInstruction::SendMessages { .. } => ChatCommand::SendMessages,
+            ChatInstruction::SendUniqueMessages { .. } => ChatCommand::SendUniqueMessages,
+            ChatInstruction::DeleteMessages { .. } => ChatCommand::DeleteMessages,
+            ChatInstruction::OpenAccount { .. } => ChatCommand::OpenAccount,
+            ChatInstruction::DeleteRange { .. } => ChatCommand::DeleteRange,
+            ChatInstruction::DeleteAll => ChatCommand::DeleteAll,
+            ChatInstruction::CloseAccount => ChatCommand::CloseAccount,
+            ChatInstruction::BlockSender { .. } => ChatCommand::BlockSender,
+            ChatInstruction::UnblockSender { .. } => ChatCommand::UnblockSender,
+            ChatInstruction::React { .. } => ChatCommand::React,
+            ChatInstruction::TransferOwnership { .. } => ChatCommand::TransferOwnership,
+            ChatInstruction::EditMessage { .. } => ChatCommand::EditMessage,
+            ChatInstruction::PurgeExpired => ChatCommand::PurgeExpired,
+        }
+    }
+
     pub fn size(&self) -> usize {
         mem::size_of::<u8>()
             + match self {
-                ChatInstruction::SendMessages { messages } => {
+                ChatInstruction::SendMessages { messages }
+                | ChatInstruction::SendUniqueMessages { messages } => {
                     messages.iter().map(|c| c.size()).sum()
                 }
                 ChatInstruction::DeleteMessages { id: _ } => mem::size_of::<u32>(),
                 ChatInstruction::OpenAccount { account_metadata } => account_metadata.size(),
+                ChatInstruction::DeleteRange { .. } => mem::size_of::<u32>() * 2,
+                ChatInstruction::DeleteAll
+                | ChatInstruction::CloseAccount
+                | ChatInstruction::PurgeExpired => 0,
+                ChatInstruction::BlockSender { .. }
+                | ChatInstruction::UnblockSender { .. }
+                | ChatInstruction::TransferOwnership { .. } => PUBKEY_BYTES,
+                ChatInstruction::React { emoji, .. } => U32_SIZE + U8_SIZE + emoji.len(),
+                ChatInstruction::EditMessage { new_msg, .. } => U32_SIZE * 2 + new_msg.len(),
             }
     }
 
@@ -161,92 +530,377 @@ impl ChatInstruction {
             return Err(ChatDeserializationError {});
         }
 
+        data[0] = self.command() as u8;
         match self {
-            ChatInstruction::SendMessages { messages } => {
-                data[0] = 0;
+            ChatInstruction::SendMessages { messages }
+            | ChatInstruction::SendUniqueMessages { messages } => {
                 serialize_messages(messages, &mut data[mem::size_of::<u8>()..])?;
                 Ok(())
             }
 
             ChatInstruction::DeleteMessages { id } => {
-                data[0] = 1;
-                data[mem::size_of::<u8>()..].copy_from_slice(&u32::to_le_bytes(*id));
+                // already implied by the size check, kept so a change to size() can't panic here
+                let payload = &mut data[mem::size_of::<u8>()..];
+                if payload.len() != U32_SIZE {
+                    return Err(ChatDeserializationError);
+                }
+                payload.copy_from_slice(&u32::to_le_bytes(*id));
                 Ok(())
             }
             ChatInstruction::OpenAccount { account_metadata } => {
-                data[0] = 2;
                 account_metadata.serialize(&mut data[mem::size_of::<u8>()..])?;
                 Ok(())
             }
+            ChatInstruction::DeleteRange { from_id, to_id } => {
+                let start = mem::size_of::<u8>();
+                data[start..start + U32_SIZE].copy_from_slice(&u32::to_le_bytes(*from_id));
+                data[start + U32_SIZE..].copy_from_slice(&u32::to_le_bytes(*to_id));
+                Ok(())
+            }
+            ChatInstruction::DeleteAll
+            | ChatInstruction::CloseAccount
+            | ChatInstruction::PurgeExpired => Ok(()),
+            ChatInstruction::BlockSender { pubkey }
+            | ChatInstruction::UnblockSender { pubkey }
+            | ChatInstruction::TransferOwnership { new_owner: pubkey } => {
+                data[mem::size_of::<u8>()..].copy_from_slice(pubkey.as_ref());
+                Ok(())
+            }
+            ChatInstruction::React { target_id, emoji } => {
+                if emoji.len() > u8::MAX as usize {
+                    return Err(ChatDeserializationError);
+                }
+                let mut start = mem::size_of::<u8>();
+                data[start..start + U32_SIZE].copy_from_slice(&u32::to_le_bytes(*target_id));
+                start += U32_SIZE;
+                data[start] = emoji.len() as u8;
+                start += U8_SIZE;
+                data[start..].copy_from_slice(emoji.as_bytes());
+                Ok(())
+            }
+            ChatInstruction::EditMessage { id, new_msg } => {
+                let mut start = mem::size_of::<u8>();
+                data[start..start + U32_SIZE].copy_from_slice(&u32::to_le_bytes(*id));
+                start += U32_SIZE;
+                data[start..start + U32_SIZE]
+                    .copy_from_slice(&u32::to_le_bytes(new_msg.len() as u32));
+                start += U32_SIZE;
+                data[start..].copy_from_slice(new_msg.as_bytes());
+                Ok(())
+            }
         }
     }
 
     pub fn deserialize(data: &[u8]) -> Result<Self, ChatDeserializationError> {
         let (tag, rest) = data.split_first().ok_or(ChatDeserializationError)?;
-        match tag {
-            0 => Ok(ChatInstruction::SendMessages {
+        let command = ChatCommand::try_from(*tag)?;
+        // the fixed part of each payload, anything shorter would read past the end
+        let min_size = match command {
+            ChatCommand::SendMessages
+            | ChatCommand::SendUniqueMessages
+            | ChatCommand::DeleteAll
+            | ChatCommand::CloseAccount
+            | ChatCommand::PurgeExpired => 0,
+            ChatCommand::DeleteMessages => U32_SIZE,
+            ChatCommand::OpenAccount => AccountMetadata::ACCOUNT_METADATA_BASE_SIZE,
+            ChatCommand::DeleteRange | ChatCommand::EditMessage => U32_SIZE * 2,
+            ChatCommand::BlockSender
+            | ChatCommand::UnblockSender
+            | ChatCommand::TransferOwnership => PUBKEY_BYTES,
+            ChatCommand::React => U32_SIZE + U8_SIZE,
+        };
+        if rest.len() < min_size {
+            return Err(ChatDeserializationError);
+        }
+        match command {
+            ChatCommand::SendMessages => Ok(ChatInstruction::SendMessages {
                 messages: deserialize_messages(rest)?,
             }),
-            1 => Ok(ChatInstruction::DeleteMessages {
+            ChatCommand::SendUniqueMessages => Ok(ChatInstruction::SendUniqueMessages {
+                messages: deserialize_messages(rest)?,
+            }),
+            ChatCommand::DeleteMessages => Ok(ChatInstruction::DeleteMessages {
                 id: u32::from_le_bytes(*array_ref![rest, 0, mem::size_of::<u32>()]),
             }),
-            2 => {
+            ChatCommand::OpenAccount => {
                 let mut account_metadata = AccountMetadata::default();
                 account_metadata.deserialize(rest)?;
                 Ok(ChatInstruction::OpenAccount { account_metadata })
             }
-            _ => Err(ChatDeserializationError),
+            ChatCommand::DeleteRange => Ok(ChatInstruction::DeleteRange {
+                from_id: u32::from_le_bytes(*array_ref![rest, 0, U32_SIZE]),
+                to_id: u32::from_le_bytes(*array_ref![rest, U32_SIZE, U32_SIZE]),
+            }),
+            ChatCommand::DeleteAll => Ok(ChatInstruction::DeleteAll),
+            ChatCommand::CloseAccount => Ok(ChatInstruction::CloseAccount),
+            ChatCommand::PurgeExpired => Ok(ChatInstruction::PurgeExpired),
+            ChatCommand::BlockSender => Ok(ChatInstruction::BlockSender {
+                pubkey: Pubkey::new_from_array(*array_ref![rest, 0, PUBKEY_BYTES]),
+            }),
+            ChatCommand::UnblockSender => Ok(ChatInstruction::UnblockSender {
+                pubkey: Pubkey::new_from_array(*array_ref![rest, 0, PUBKEY_BYTES]),
+            }),
+            ChatCommand::TransferOwnership => Ok(ChatInstruction::TransferOwnership {
+                new_owner: Pubkey::new_from_array(*array_ref![rest, 0, PUBKEY_BYTES]),
+            }),
+            ChatCommand::EditMessage => {
+                let id = u32::from_le_bytes(*array_ref![rest, 0, U32_SIZE]);
+                let msg_len = u32::from_le_bytes(*array_ref![rest, U32_SIZE, U32_SIZE]) as usize;
+                let msg_start = U32_SIZE * 2;
+                let new_msg = rest
+                    .get(msg_start..msg_start + msg_len)
+                    .ok_or(ChatDeserializationError)?;
+                Ok(ChatInstruction::EditMessage {
+                    id,
+                    new_msg: String::from_utf8_lossy(new_msg).into_owned(),
+                })
+            }
+            ChatCommand::React => {
+                let target_id = u32::from_le_bytes(*array_ref![rest, 0, U32_SIZE]);
+                let emoji_len = rest[U32_SIZE] as usize;
+                let emoji_start = U32_SIZE + U8_SIZE;
+                let emoji = rest
+                    .get(emoji_start..emoji_start + emoji_len)
+                    .ok_or(ChatDeserializationError)?;
+                Ok(ChatInstruction::React {
+                    target_id,
+                    emoji: String::from_utf8_lossy(emoji).into_owned(),
+                })
+            }
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Reaction {
+    pub target_id: u32,
+    pub from: Pubkey,
+    pub emoji_len: u8,
+    pub emoji: String,
+}
+
+impl Reaction {
+    // the emoji always takes MAX_EMOJI_LEN bytes, zero padded
+    pub const REACTION_SIZE: usize = U32_SIZE + PUBKEY_BYTES + U8_SIZE + MAX_EMOJI_LEN;
+
+    pub fn new(target_id: u32, from: Pubkey, emoji: &str) -> Result<Self, ChatError> {
+        if emoji.is_empty() || emoji.len() > MAX_EMOJI_LEN {
+            return Err(ChatError::EmojiTooLong);
+        }
+        Ok(Reaction {
+            target_id,
+            from,
+            emoji_len: emoji.len() as u8,
+            emoji: emoji.to_string(),
+        })
+    }
+}
+
+impl ChatData for Reaction {
+    fn size(&self) -> usize {
+        Reaction::REACTION_SIZE
+    }
+
+    fn serialize(&self, data: &mut [u8]) -> Result<(), ChatDeserializationError> {
+        if self.size() != data.len() || self.emoji_len as usize > MAX_EMOJI_LEN {
+            return Err(ChatDeserializationError {});
+        }
+
+        let mut start: usize = 0;
+        let mut end: usize = U32_SIZE;
+        data[start..end].copy_from_slice(&u32::to_le_bytes(self.target_id));
+
+        start = end;
+        end += PUBKEY_BYTES;
+        data[start..end].copy_from_slice(self.from.as_ref());
+
+        start = end;
+        end += U8_SIZE;
+        data[start..end].copy_from_slice(&u8::to_le_bytes(self.emoji_len));
+
+        start = end;
+        end += self.emoji_len as usize;
+        data[start..end].copy_from_slice(self.emoji.as_bytes());
+        data[end..].fill(0);
+
+        Ok(())
+    }
+
+    fn deserialize(&mut self, data: &[u8]) -> Result<(), ChatDeserializationError> {
+        let mut start: usize = 0;
+        let target_id = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let from = Pubkey::new_from_array(*array_ref!(data, start, PUBKEY_BYTES));
+
+        start += PUBKEY_BYTES;
+        let emoji_len = u8::from_le_bytes(*array_ref!(data, start, U8_SIZE));
+        if emoji_len as usize > MAX_EMOJI_LEN {
+            return Err(ChatDeserializationError);
+        }
+
+        start += U8_SIZE;
+        let emoji = String::from_utf8_lossy(&data[start..start + emoji_len as usize]).into_owned();
+
+        self.target_id = target_id;
+        self.from = from;
+        self.emoji_len = emoji_len;
+        self.emoji = emoji;
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, PartialEq, Default)]
 pub struct AccountMetadata {
     pub initialized: u8,
+    pub bump: u8,
     pub next_free_index: u32,
     pub last_message_id: u32,
+    pub message_count: u32,
+    pub blocked_count: u32,
+    pub reaction_count: u32,
+    pub seed_len: u32,
     pub account_name_len: u32,
+    // user allowed to manage the account, starts as the one the address is derived from
+    pub owner: Pubkey,
+    // unix time the program opened the account at, whatever the client sends is overwritten
+    pub created_at: i64,
     pub account_name: String,
+    pub seed: String,
+    pub blocked_senders: Vec<Pubkey>,
+    pub reactions: Vec<Reaction>,
 }
 
 impl AccountMetadata {
-    const ACCOUNT_METADATA_BASE_SIZE: usize = (mem::size_of::<u32>() * 3) + mem::size_of::<u8>();
+    pub const ACCOUNT_METADATA_BASE_SIZE: usize =
+        (mem::size_of::<u32>() * 7) + (mem::size_of::<u8>() * 2) + PUBKEY_BYTES + I64_SIZE;
     // FIXME, set next_free_index to account_metadata.size()
-    pub fn new(account_name: &str) -> Self {
+    pub fn new(account_name: &str, bump: u8) -> Result<Self, ChatError> {
+        AccountMetadata::with_seed(account_name, DEFAULT_SEED, bump)
+    }
+
+    pub fn with_seed(account_name: &str, seed: &str, bump: u8) -> Result<Self, ChatError> {
+        // the name is how the account shows up to others, open_account defaults it to the pubkey
+        if account_name.is_empty() {
+            return Err(ChatError::NameRequired);
+        }
+        if account_name.len() > MAX_ACCOUNT_NAME_LEN {
+            return Err(ChatError::NameTooLong);
+        }
+        if seed.len() > MAX_SEED_LEN {
+            return Err(ChatError::SeedTooLong);
+        }
+
         let name = account_name.to_string();
         let mut account_metadata = AccountMetadata {
             initialized: 1,
+            bump,
             next_free_index: 0,
             last_message_id: 0,
+            message_count: 0,
+            blocked_count: 0,
+            reaction_count: 0,
+            seed_len: seed.len() as u32,
             account_name_len: name.len() as u32,
+            owner: Pubkey::default(),
+            created_at: 0,
             account_name: name,
+            seed: seed.to_string(),
+            blocked_senders: vec![],
+            reactions: vec![],
         };
         account_metadata.next_free_index = account_metadata.size() as u32;
-        account_metadata
+        Ok(account_metadata)
     }
 
-    pub fn calculate_size_from_buffer(data: &[u8]) -> usize {
-        let account_name_len_offset = U8_SIZE + (2 * U32_SIZE);
-        let account_name_len =
-            u32::from_le_bytes(*array_ref![data, account_name_len_offset, U32_SIZE]);
-        AccountMetadata::ACCOUNT_METADATA_BASE_SIZE + account_name_len as usize
+    // the size the stored lengths add up to, it may be more than data holds when only the
+    // header was read
+    pub fn calculate_size_from_buffer(data: &[u8]) -> Result<usize, ChatDeserializationError> {
+        if data.len() < AccountMetadata::ACCOUNT_METADATA_BASE_SIZE {
+            return Err(ChatDeserializationError);
+        }
+        let blocked_count_offset = (2 * U8_SIZE) + (3 * U32_SIZE);
+        let blocked_count = u32::from_le_bytes(*array_ref![data, blocked_count_offset, U32_SIZE]);
+        let reaction_count =
+            u32::from_le_bytes(*array_ref![data, blocked_count_offset + U32_SIZE, U32_SIZE]);
+        let seed_len = u32::from_le_bytes(*array_ref![
+            data,
+            blocked_count_offset + U32_SIZE * 2,
+            U32_SIZE
+        ]);
+        let account_name_len = u32::from_le_bytes(*array_ref![
+            data,
+            blocked_count_offset + U32_SIZE * 3,
+            U32_SIZE
+        ]);
+        let blocked_size = (blocked_count as usize).checked_mul(PUBKEY_BYTES);
+        let reactions_size = (reaction_count as usize).checked_mul(Reaction::REACTION_SIZE);
+        AccountMetadata::ACCOUNT_METADATA_BASE_SIZE
+            .checked_add(account_name_len as usize)
+            .and_then(|size| size.checked_add(seed_len as usize))
+            .and_then(|size| size.checked_add(blocked_size?))
+            .and_then(|size| size.checked_add(reactions_size?))
+            .ok_or(ChatDeserializationError)
+    }
+
+    // writes only next_free_index, last_message_id and message_count, the fields a message
+    // append changes, so the name and lists after them aren't rewritten every send
+    pub fn serialize_counters(&self, data: &mut [u8]) -> Result<(), ChatDeserializationError> {
+        let start = 2 * U8_SIZE;
+        let end = start + 3 * U32_SIZE;
+        if data.len() < end {
+            return Err(ChatDeserializationError);
+        }
+        data[start..start + U32_SIZE].copy_from_slice(&u32::to_le_bytes(self.next_free_index));
+        data[start + U32_SIZE..start + 2 * U32_SIZE]
+            .copy_from_slice(&u32::to_le_bytes(self.last_message_id));
+        data[start + 2 * U32_SIZE..end].copy_from_slice(&u32::to_le_bytes(self.message_count));
+        Ok(())
+    }
+
+    pub fn is_blocked(&self, sender: &Pubkey) -> bool {
+        self.blocked_senders.contains(sender)
+    }
+
+    pub fn reactions_to(&self, target_id: u32) -> impl Iterator<Item = &Reaction> {
+        self.reactions
+            .iter()
+            .filter(move |reaction| reaction.target_id == target_id)
     }
 }
 
 impl ChatData for AccountMetadata {
     fn size(&self) -> usize {
-        AccountMetadata::ACCOUNT_METADATA_BASE_SIZE as usize + self.account_name_len as usize
+        AccountMetadata::ACCOUNT_METADATA_BASE_SIZE
+            + self.account_name_len as usize
+            + self.seed_len as usize
+            + self.blocked_count as usize * PUBKEY_BYTES
+            + self.reaction_count as usize * Reaction::REACTION_SIZE
     }
 
     fn serialize(&self, data: &mut [u8]) -> Result<(), ChatDeserializationError> {
         if self.size() != data.len() {
             return Err(ChatDeserializationError {});
         }
+        // size() goes by the stored lengths, the values have to match them or the copies below
+        // would overrun their fields; a name with invalid UTF-8 grows when it's decoded lossily
+        if self.account_name.len() != self.account_name_len as usize
+            || self.seed.len() != self.seed_len as usize
+            || self.blocked_senders.len() != self.blocked_count as usize
+            || self.reactions.len() != self.reaction_count as usize
+        {
+            return Err(ChatDeserializationError {});
+        }
 
         let mut start: usize = 0;
         let mut end = start + mem::size_of::<u8>();
         data[start..end].copy_from_slice(&u8::to_le_bytes(self.initialized));
 
+        start = end;
+        end += U8_SIZE;
+        data[start..end].copy_from_slice(&u8::to_le_bytes(self.bump));
+
         start = end;
         end += U32_SIZE;
         data[start..end].copy_from_slice(&u32::to_le_bytes(self.next_free_index));
@@ -255,63 +909,409 @@ impl ChatData for AccountMetadata {
         end += U32_SIZE;
         data[start..end].copy_from_slice(&u32::to_le_bytes(self.last_message_id));
 
+        start = end;
+        end += U32_SIZE;
+        data[start..end].copy_from_slice(&u32::to_le_bytes(self.message_count));
+
+        start = end;
+        end += U32_SIZE;
+        data[start..end].copy_from_slice(&u32::to_le_bytes(self.blocked_count));
+
+        start = end;
+        end += U32_SIZE;
+        data[start..end].copy_from_slice(&u32::to_le_bytes(self.reaction_count));
+
+        start = end;
+        end += U32_SIZE;
+        data[start..end].copy_from_slice(&u32::to_le_bytes(self.seed_len));
+
         start = end;
         end += U32_SIZE;
         data[start..end].copy_from_slice(&u32::to_le_bytes(self.account_name_len));
 
+        start = end;
+        end += PUBKEY_BYTES;
+        data[start..end].copy_from_slice(self.owner.as_ref());
+
+        start = end;
+        end += I64_SIZE;
+        data[start..end].copy_from_slice(&i64::to_le_bytes(self.created_at));
+
         start = end;
         end += self.account_name_len as usize;
         data[start..end].copy_from_slice(String::as_bytes(&self.account_name));
 
+        start = end;
+        end += self.seed_len as usize;
+        data[start..end].copy_from_slice(String::as_bytes(&self.seed));
+
+        for sender in &self.blocked_senders {
+            start = end;
+            end += PUBKEY_BYTES;
+            data[start..end].copy_from_slice(sender.as_ref());
+        }
+
+        for reaction in &self.reactions {
+            start = end;
+            end += Reaction::REACTION_SIZE;
+            reaction.serialize(&mut data[start..end])?;
+        }
+
         Ok(())
     }
 
     fn deserialize(&mut self, data: &[u8]) -> Result<(), ChatDeserializationError> {
-        const U8_SIZE: usize = mem::size_of::<u8>();
-        let initialized = u8::from_le_bytes(*array_ref!(data, 0, U8_SIZE));
-        let next_free_index = u32::from_le_bytes(*array_ref!(data, U8_SIZE, U32_SIZE));
-        let last_message_id = u32::from_le_bytes(*array_ref!(data, U32_SIZE + U8_SIZE, U32_SIZE));
-        let account_name_len =
-            u32::from_le_bytes(*array_ref!(data, (U32_SIZE * 2) + U8_SIZE, U32_SIZE));
-
-        let account_name = String::from_utf8_lossy(
-            &data[(U32_SIZE * 3) + U8_SIZE..(U32_SIZE * 3) + U8_SIZE + account_name_len as usize],
-        )
-        .into_owned();
+        if data.len() < AccountMetadata::ACCOUNT_METADATA_BASE_SIZE {
+            return Err(ChatDeserializationError);
+        }
+        let mut start: usize = 0;
+        let initialized = u8::from_le_bytes(*array_ref!(data, start, U8_SIZE));
+
+        start += U8_SIZE;
+        let bump = u8::from_le_bytes(*array_ref!(data, start, U8_SIZE));
+
+        start += U8_SIZE;
+        let next_free_index = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let last_message_id = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let message_count = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let blocked_count = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let reaction_count = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let seed_len = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let account_name_len = u32::from_le_bytes(*array_ref!(data, start, U32_SIZE));
+
+        start += U32_SIZE;
+        let owner = Pubkey::new_from_array(*array_ref!(data, start, PUBKEY_BYTES));
+
+        start += PUBKEY_BYTES;
+        let created_at = i64::from_le_bytes(*array_ref!(data, start, I64_SIZE));
+
+        start += I64_SIZE;
+        if data.len() < start + account_name_len as usize {
+            return Err(ChatDeserializationError);
+        }
+        let account_name =
+            String::from_utf8_lossy(&data[start..start + account_name_len as usize]).into_owned();
+
+        start += account_name_len as usize;
+        if seed_len as usize > MAX_SEED_LEN || data.len() < start + seed_len as usize {
+            return Err(ChatDeserializationError);
+        }
+        let seed = String::from_utf8_lossy(&data[start..start + seed_len as usize]).into_owned();
+
+        start += seed_len as usize;
+        if blocked_count as usize > MAX_BLOCKED_SENDERS
+            || data.len() < start + blocked_count as usize * PUBKEY_BYTES
+        {
+            return Err(ChatDeserializationError);
+        }
+        let blocked_senders = (0..blocked_count as usize)
+            .map(|i| {
+                Pubkey::new_from_array(*array_ref!(data, start + i * PUBKEY_BYTES, PUBKEY_BYTES))
+            })
+            .collect();
+
+        start += blocked_count as usize * PUBKEY_BYTES;
+        if reaction_count as usize > MAX_REACTIONS
+            || data.len() < start + reaction_count as usize * Reaction::REACTION_SIZE
+        {
+            return Err(ChatDeserializationError);
+        }
+        let mut reactions = Vec::with_capacity(reaction_count as usize);
+        for i in 0..reaction_count as usize {
+            let reaction_start = start + i * Reaction::REACTION_SIZE;
+            let mut reaction = Reaction::default();
+            reaction
+                .deserialize(&data[reaction_start..reaction_start + Reaction::REACTION_SIZE])?;
+            reactions.push(reaction);
+        }
 
         self.initialized = initialized;
+        self.bump = bump;
         self.next_free_index = next_free_index;
         self.last_message_id = last_message_id;
+        self.message_count = message_count;
+        self.blocked_count = blocked_count;
+        self.reaction_count = reaction_count;
+        self.seed_len = seed_len;
         self.account_name_len = account_name_len;
+        self.owner = owner;
+        self.created_at = created_at;
         self.account_name = account_name;
+        self.seed = seed;
+        self.blocked_senders = blocked_senders;
+        self.reactions = reactions;
 
         Ok(())
     }
 }
 
+// messages come back in buffer order, the order they were stored in. SendMessages ids only
+// grow, but SendUniqueMessages takes its ids from the content, so a buffer may hold them out of
+// id order, see deserialize_account_data_sorted
 pub fn deserialize_account_data(
     data: &[u8],
 ) -> Result<(AccountMetadata, Option<Vec<Message>>), ChatDeserializationError> {
-    let account_metadata_size = AccountMetadata::calculate_size_from_buffer(data);
+    deserialize_account_data_up_to(data, usize::MAX)
+        .map(|(account_metadata, messages, _)| (account_metadata, messages))
+}
+
+// like deserialize_account_data with the messages sorted by id, for readers that look them
+// up or page through them by id rather than showing them as stored
+pub fn deserialize_account_data_sorted(
+    data: &[u8],
+) -> Result<(AccountMetadata, Option<Vec<Message>>), ChatDeserializationError> {
+    let (account_metadata, mut messages) = deserialize_account_data(data)?;
+    if let Some(messages) = messages.as_mut() {
+        messages.sort_by_key(|message| message.id);
+    }
+    Ok((account_metadata, messages))
+}
+
+// like deserialize_account_data, with the messages capped as in deserialize_messages_up_to
+pub fn deserialize_account_data_up_to(
+    data: &[u8],
+    max_messages: usize,
+) -> Result<(AccountMetadata, Option<Vec<Message>>, bool), ChatDeserializationError> {
+    let account_metadata_size = AccountMetadata::calculate_size_from_buffer(data)?;
+    if account_metadata_size > data.len() {
+        return Err(ChatDeserializationError);
+    }
     let mut account_metadata = AccountMetadata::default();
     account_metadata.deserialize(&data[..account_metadata_size])?;
     let next_free_index = account_metadata.next_free_index as usize;
+    // a corrupt index pointing inside the metadata or past the buffer can't be sliced
+    if next_free_index < account_metadata_size || next_free_index > data.len() {
+        return Err(ChatDeserializationError);
+    }
     if next_free_index > account_metadata_size {
-        let messages = deserialize_messages(&data[account_metadata_size..next_free_index])?;
-        Ok((account_metadata, Some(messages)))
+        let (messages, truncated) = deserialize_messages_up_to(
+            &data[account_metadata_size..next_free_index],
+            max_messages,
+        )?;
+        Ok((account_metadata, Some(messages), truncated))
     } else {
-        Ok((account_metadata, None))
+        Ok((account_metadata, None, false))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::data::{deserialize_messages, serialize_messages, ChatData};
-
-    use super::{AccountMetadata, ChatDeserializationError, ChatInstruction};
+    use crate::{
+        data::{
+            deserialize_account_data, deserialize_account_data_sorted,
+            deserialize_account_data_up_to, deserialize_messages, deserialize_messages_up_to,
+            serialize_messages, ChatData, MAX_ACCOUNT_NAME_LEN,
+        },
+        error::ChatError,
+    };
+
+    use super::{
+        AccountMetadata, ChatCommand, ChatDeserializationError, ChatInstruction, Reaction,
+    };
 
     static PROGRAM_ADDRESS: &str = "DidmGHY2FMXTPzxMhiMjNSzwuqcHhJ679yP4NdCQsoqM";
 
+    #[test]
+    fn chat_command_tags() -> Result<(), ChatDeserializationError> {
+        use solana_program::pubkey::Pubkey;
+
+        let instructions = vec![
+            (
+                ChatCommand::SendMessages,
+                ChatInstruction::SendMessages { messages: vec![] },
+            ),
+            (
+                ChatCommand::SendUniqueMessages,
+                ChatInstruction::SendUniqueMessages { messages: vec![] },
+            ),
+            (
+                ChatCommand::DeleteMessages,
+                ChatInstruction::DeleteMessages { id: 1 },
+            ),
+            (
+                ChatCommand::OpenAccount,
+                ChatInstruction::OpenAccount {
+                    account_metadata: AccountMetadata::default(),
+                },
+            ),
+            (
+                ChatCommand::DeleteRange,
+                ChatInstruction::DeleteRange {
+                    from_id: 1,
+                    to_id: 2,
+                },
+            ),
+            (ChatCommand::DeleteAll, ChatInstruction::DeleteAll),
+            (ChatCommand::CloseAccount, ChatInstruction::CloseAccount),
+            (
+                ChatCommand::BlockSender,
+                ChatInstruction::BlockSender {
+                    pubkey: Pubkey::new_unique(),
+                },
+            ),
+            (
+                ChatCommand::UnblockSender,
+                ChatInstruction::UnblockSender {
+                    pubkey: Pubkey::new_unique(),
+                },
+            ),
+            (
+                ChatCommand::React,
+                ChatInstruction::React {
+                    target_id: 3,
+                    emoji: "🎉".to_string(),
+                },
+            ),
+            (
+                ChatCommand::TransferOwnership,
+                ChatInstruction::TransferOwnership {
+                    new_owner: Pubkey::new_unique(),
+                },
+            ),
+            (
+                ChatCommand::EditMessage,
+                ChatInstruction::EditMessage {
+                    id: 4,
+                    new_msg: "fixed".to_string(),
+                },
+            ),
+            (ChatCommand::PurgeExpired, ChatInstruction::PurgeExpired),
+        ];
+
+        for (command, chat_inst) in instructions {
+            let mut data = vec![0; chat_inst.size()];
+            chat_inst.serialize(&mut data[..])?;
+            assert_eq!(data[0], command as u8);
+            assert_eq!(ChatCommand::try_from(data[0])?, command);
+            assert_eq!(ChatInstruction::deserialize(&data)?, chat_inst);
+        }
+        assert!(ChatCommand::try_from(255).is_err());
+        assert!(ChatInstruction::deserialize(&[255]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn mis_sized_buffers_are_rejected() {
+        let chat_inst = ChatInstruction::DeleteMessages { id: 7 };
+        assert_eq!(chat_inst.size(), 5);
+
+        for len in [0, 1, 4, 6, 9] {
+            let mut data = vec![0; len];
+            assert!(chat_inst.serialize(&mut data[..]).is_err());
+        }
+        let mut data = vec![0; 5];
+        assert!(chat_inst.serialize(&mut data[..]).is_ok());
+        assert_eq!(data, [1, 7, 0, 0, 0]);
+    }
+
+    fn serialized_abc() -> (crate::data::Message, Vec<u8>) {
+        use solana_program::pubkey::Pubkey;
+
+        use crate::data::Message;
+
+        let message = Message::new(0, Pubkey::new_unique(), "abc".to_string());
+        let mut data = vec![0; message.size()];
+        message.serialize(&mut data[..]).unwrap();
+        (message, data)
+    }
+
+    #[test]
+    fn uncompressed_msg_size_must_match_the_body() {
+        use crate::data::Message;
+
+        let (message, mut data) = serialized_abc();
+        let mut decoded = Message::default();
+        decoded.deserialize(&data).unwrap();
+        assert_eq!(decoded, message);
+
+        // msg_size claims more than the stored body, serialize would overrun its slot
+        let msg_size_start = 4 + 32 + 32 + 8;
+        data[msg_size_start..msg_size_start + 4].copy_from_slice(&10u32.to_le_bytes());
+        assert!(Message::default().deserialize(&data).is_err());
+    }
+
+    #[test]
+    fn body_must_be_utf8() {
+        use crate::data::Message;
+
+        let (message, mut data) = serialized_abc();
+        data[message.size() - 3] = 0xff;
+        assert!(Message::default().deserialize(&data).is_err());
+    }
+
+    #[test]
+    fn truncated_instructions_are_rejected() {
+        use solana_program::pubkey::Pubkey;
+
+        use crate::data::Message;
+
+        let message = Message::new(0, Pubkey::new_unique(), "hello".to_string());
+        let instructions = vec![
+            ChatInstruction::SendMessages {
+                messages: vec![message.clone()],
+            },
+            ChatInstruction::SendUniqueMessages {
+                messages: vec![message],
+            },
+            ChatInstruction::DeleteMessages { id: 1 },
+            ChatInstruction::OpenAccount {
+                account_metadata: AccountMetadata::new("abc", 255).unwrap(),
+            },
+            ChatInstruction::DeleteRange {
+                from_id: 1,
+                to_id: 2,
+            },
+            ChatInstruction::BlockSender {
+                pubkey: Pubkey::new_unique(),
+            },
+            ChatInstruction::UnblockSender {
+                pubkey: Pubkey::new_unique(),
+            },
+            ChatInstruction::React {
+                target_id: 3,
+                emoji: "🎉".to_string(),
+            },
+            ChatInstruction::TransferOwnership {
+                new_owner: Pubkey::new_unique(),
+            },
+            ChatInstruction::EditMessage {
+                id: 4,
+                new_msg: "fixed".to_string(),
+            },
+        ];
+        for instruction in instructions {
+            let mut data = vec![0; instruction.size()];
+            instruction.serialize(&mut data[..]).unwrap();
+            // a bare send tag is an empty batch, every other cut is short of the payload
+            let shortest = match instruction {
+                ChatInstruction::SendMessages { .. }
+                | ChatInstruction::SendUniqueMessages { .. } => 2,
+                _ => 1,
+            };
+            for len in shortest..data.len() {
+                assert!(
+                    ChatInstruction::deserialize(&data[..len]).is_err(),
+                    "{:?} cut to {} bytes",
+                    instruction.command(),
+                    len
+                );
+            }
+        }
+    }
+
     #[test]
     fn message_serialization() -> Result<(), ChatDeserializationError> {
         use std::str::FromStr;
@@ -320,11 +1320,12 @@ mod tests {
 
         use crate::data::Message;
 
-        let message = Message::new(
+        let mut message = Message::new(
             1,
-            Pubkey::from_str(&PROGRAM_ADDRESS.to_string()).unwrap(),
+            Pubkey::from_str(PROGRAM_ADDRESS).unwrap(),
             "12345".to_string(),
         );
+        message.timestamp = 1_650_000_000;
 
         let size = message.size();
         let mut data = vec![0; size];
@@ -345,17 +1346,20 @@ mod tests {
 
         let msg1 = Message {
             id: 1,
-            from: Pubkey::from_str(&PROGRAM_ADDRESS.to_string()).unwrap(),
+            from: Pubkey::from_str(PROGRAM_ADDRESS).unwrap(),
             msg_size: 5,
             msg: "12345".to_string(),
+            ..Message::default()
         };
 
         let msg2 = Message {
             id: 2,
 
-            from: Pubkey::from_str(&PROGRAM_ADDRESS.to_string()).unwrap(),
+            from: Pubkey::from_str(PROGRAM_ADDRESS).unwrap(),
+            to: Pubkey::new_unique(),
             msg_size: 3,
             msg: "abc".to_string(),
+            ..Message::default()
         };
 
         let size = msg1.size() + msg2.size();
@@ -369,20 +1373,155 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn compressed_message_serialization() -> Result<(), ChatDeserializationError> {
+        use std::str::FromStr;
+
+        use solana_program::pubkey::Pubkey;
+
+        use crate::data::Message;
+
+        let message = Message {
+            id: 1,
+            from: Pubkey::from_str(PROGRAM_ADDRESS).unwrap(),
+            msg_size: 100,
+            compressed: 1,
+            compressed_msg: vec![0xff, 0x00, 0x7f],
+            ..Message::default()
+        };
+
+        let size = message.size();
+        let mut data = vec![0; size];
+
+        message.serialize(&mut data[..])?;
+        let mut message_new = Message::default();
+        message_new.deserialize(&data[..])?;
+        assert_eq!(&message, &message_new);
+        assert_eq!(message_new.stored_size(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn attachment_hash_serialization() -> Result<(), ChatDeserializationError> {
+        use solana_program::pubkey::Pubkey;
+
+        use crate::data::Message;
+
+        let plain = Message::new(1, Pubkey::new_unique(), "see file".to_string());
+        let attached = Message {
+            attachment_hash: Some([7; 32]),
+            ..plain.clone()
+        };
+        assert_eq!(
+            attached.size(),
+            plain.size() + Message::ATTACHMENT_HASH_SIZE
+        );
+        // every optional field at once keeps its order: hash, reply, expiry, body
+        let expiring = Message {
+            reply_to: Some(3),
+            expires_at: Some(1_700_000_000),
+            ..attached.clone()
+        };
+        assert_eq!(expiring.size(), attached.size() + 4 + 8);
+        assert!(!expiring.is_expired(1_699_999_999));
+        assert!(expiring.is_expired(1_700_000_000));
+        assert!(!plain.is_expired(i64::MAX));
+
+        for message in [plain, attached, expiring] {
+            let mut data = vec![0; message.size()];
+            message.serialize(&mut data[..])?;
+            let mut message_new = Message::default();
+            message_new.deserialize(&data[..])?;
+            assert_eq!(message, message_new);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn message_builder_validates() -> Result<(), ChatDeserializationError> {
+        use solana_program::pubkey::Pubkey;
+
+        use crate::data::{Message, MessageBuilder, MAX_MESSAGE_LEN};
+        use crate::error::ChatError;
+
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        assert_eq!(
+            MessageBuilder::new().body("hi").build(),
+            Err(ChatError::MissingSender)
+        );
+        assert_eq!(
+            MessageBuilder::new().from(from).build(),
+            Err(ChatError::EmptyMessage)
+        );
+        assert_eq!(
+            MessageBuilder::new()
+                .from(from)
+                .body(&"a".repeat(MAX_MESSAGE_LEN + 1))
+                .build(),
+            Err(ChatError::MessageTooLong)
+        );
+
+        let message = MessageBuilder::new()
+            .from(from)
+            .to(to)
+            .body("thanks")
+            .reply_to(4)
+            .build()
+            .unwrap();
+        assert_eq!(
+            (message.from, message.to, message.msg_size, message.reply_to),
+            (from, to, 6, Some(4))
+        );
+        assert_eq!(message.size(), Message::MESSAGE_BASE_SIZE + 4 + 6);
+
+        let mut data = vec![0; message.size()];
+        message.serialize(&mut data[..])?;
+        let mut message_new = Message::default();
+        message_new.deserialize(&data[..])?;
+        assert_eq!(message, message_new);
+        Ok(())
+    }
+
     #[test]
     fn acount_metadata_serialization() -> Result<(), ChatDeserializationError> {
+        use solana_program::pubkey::{Pubkey, PUBKEY_BYTES};
+
         let account_metadata = AccountMetadata {
             initialized: 1,
+            bump: 254,
             next_free_index: 2,
             last_message_id: 3,
+            message_count: 4,
+            blocked_count: 2,
+            reaction_count: 1,
+            seed_len: 4,
             account_name_len: 3,
+            owner: Pubkey::new_unique(),
+            created_at: 1_650_000_000,
             account_name: "abc".to_string(),
+            seed: "work".to_string(),
+            blocked_senders: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            reactions: vec![Reaction::new(3, Pubkey::new_unique(), "👍").unwrap()],
         };
 
         let size = account_metadata.size();
         let mut data = vec![0; size];
 
         account_metadata.serialize(&mut data[..])?;
+        // the owner and creation time sit between the fixed counters and the name, readers
+        // sizing the metadata from the raw buffer have to account for them
+        assert_eq!(AccountMetadata::calculate_size_from_buffer(&data)?, size);
+        let created_at_end = AccountMetadata::ACCOUNT_METADATA_BASE_SIZE;
+        let owner_end = created_at_end - 8;
+        assert_eq!(
+            &data[owner_end - PUBKEY_BYTES..owner_end],
+            account_metadata.owner.as_ref()
+        );
+        assert_eq!(
+            data[owner_end..created_at_end],
+            1_650_000_000i64.to_le_bytes()
+        );
 
         let mut s_account_metadata = AccountMetadata::default();
         s_account_metadata.deserialize(&data[..])?;
@@ -392,15 +1531,158 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn next_free_index_must_stay_between_metadata_and_buffer_end() {
+        let mut account_metadata = AccountMetadata::with_seed("abc", "work", 255).unwrap();
+        let size = account_metadata.size();
+        let mut data = vec![0; size + 16];
+        account_metadata.serialize(&mut data[..size]).unwrap();
+        let (_, messages) = deserialize_account_data(&data).unwrap();
+        assert!(messages.is_none());
+
+        account_metadata.next_free_index = size as u32 - 1;
+        account_metadata.serialize(&mut data[..size]).unwrap();
+        assert!(deserialize_account_data(&data).is_err());
+
+        account_metadata.next_free_index = (data.len() + 1) as u32;
+        account_metadata.serialize(&mut data[..size]).unwrap();
+        assert!(deserialize_account_data(&data).is_err());
+    }
+
+    #[test]
+    fn lengths_past_the_buffer_are_rejected() {
+        // shorter than the fixed header
+        assert!(AccountMetadata::calculate_size_from_buffer(&[1; 10]).is_err());
+        assert!(deserialize_account_data(&[1; 10]).is_err());
+
+        let account_metadata = AccountMetadata::with_seed("abc", "work", 255).unwrap();
+        let size = account_metadata.size();
+        let mut data = vec![0; size + 16];
+        account_metadata.serialize(&mut data[..size]).unwrap();
+        let account_name_len_start = 2 + 6 * 4;
+        data[account_name_len_start..account_name_len_start + 4]
+            .copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(AccountMetadata::calculate_size_from_buffer(&data).unwrap() > data.len());
+        assert!(deserialize_account_data(&data).is_err());
+    }
+
+    #[test]
+    fn deserialization_stops_at_the_cap() {
+        use solana_program::pubkey::Pubkey;
+
+        use crate::data::Message;
+
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = (1..=5)
+            .map(|id| Message::new(id, sender, format!("message {}", id)))
+            .collect();
+        let mut data = vec![0; messages.iter().map(|message| message.size()).sum()];
+        serialize_messages(&messages, &mut data).unwrap();
+
+        let (capped, truncated) = deserialize_messages_up_to(&data, 3).unwrap();
+        assert_eq!(capped, messages[..3]);
+        assert!(truncated);
+        let (all, truncated) = deserialize_messages_up_to(&data, 5).unwrap();
+        assert_eq!(all, messages);
+        assert!(!truncated);
+        assert_eq!(deserialize_messages_up_to(&[], 0).unwrap(), (vec![], false));
+
+        let mut account_metadata = AccountMetadata::with_seed("abc", "work", 255).unwrap();
+        let size = account_metadata.size();
+        account_metadata.next_free_index = (size + data.len()) as u32;
+        let mut account = vec![0; size];
+        account_metadata.serialize(&mut account).unwrap();
+        account.extend_from_slice(&data);
+        let (_, capped, truncated) = deserialize_account_data_up_to(&account, 1).unwrap();
+        assert_eq!(capped.unwrap(), messages[..1]);
+        assert!(truncated);
+    }
+
+    #[test]
+    fn sorted_account_data_orders_by_id() {
+        use solana_program::pubkey::Pubkey;
+
+        use crate::data::Message;
+
+        let sender = Pubkey::new_unique();
+        let messages: Vec<Message> = [7, 2, 9, 4]
+            .into_iter()
+            .map(|id| Message::new(id, sender, format!("message {}", id)))
+            .collect();
+        let mut account_metadata = AccountMetadata::with_seed("abc", "work", 255).unwrap();
+        let size = account_metadata.size();
+        let messages_size: usize = messages.iter().map(|message| message.size()).sum();
+        account_metadata.next_free_index = (size + messages_size) as u32;
+        let mut data = vec![0; size + messages_size];
+        account_metadata.serialize(&mut data[..size]).unwrap();
+        serialize_messages(&messages, &mut data[size..]).unwrap();
+
+        let ids = |messages: Option<Vec<Message>>| -> Vec<u32> {
+            messages.unwrap().iter().map(|message| message.id).collect()
+        };
+        let (_, stored) = deserialize_account_data(&data).unwrap();
+        assert_eq!(ids(stored), vec![7, 2, 9, 4]);
+        let (sorted_metadata, sorted) = deserialize_account_data_sorted(&data).unwrap();
+        assert_eq!(ids(sorted), vec![2, 4, 7, 9]);
+        assert_eq!(sorted_metadata, account_metadata);
+    }
+
+    #[test]
+    fn metadata_lengths_must_match_the_values() {
+        let mut account_metadata = AccountMetadata::with_seed("abc", "work", 255).unwrap();
+        account_metadata.account_name_len = 2;
+        let mut data = vec![0; account_metadata.size()];
+        assert!(account_metadata.serialize(&mut data).is_err());
+
+        // a name that isn't UTF-8 decodes to more bytes than its length says
+        let mut account_metadata = AccountMetadata::with_seed("abc", "work", 255).unwrap();
+        let size = account_metadata.size();
+        let mut data = vec![0; size];
+        account_metadata.serialize(&mut data).unwrap();
+        let name_start = AccountMetadata::ACCOUNT_METADATA_BASE_SIZE;
+        data[name_start..name_start + 3].copy_from_slice(&[0xff, 0xfe, 0xfd]);
+        account_metadata.deserialize(&data).unwrap();
+        assert_eq!(account_metadata.account_name_len, 3);
+        assert_ne!(account_metadata.account_name.len(), 3);
+        assert!(account_metadata.serialize(&mut data).is_err());
+    }
+
+    #[test]
+    fn account_name_length_limit() {
+        let name = "a".repeat(MAX_ACCOUNT_NAME_LEN);
+        let account_metadata = AccountMetadata::new(&name, 255).unwrap();
+        assert_eq!(
+            account_metadata.account_name_len as usize,
+            MAX_ACCOUNT_NAME_LEN
+        );
+
+        let name = "a".repeat(MAX_ACCOUNT_NAME_LEN + 1);
+        assert_eq!(
+            AccountMetadata::new(&name, 255),
+            Err(ChatError::NameTooLong)
+        );
+        assert_eq!(AccountMetadata::new("", 255), Err(ChatError::NameRequired));
+    }
+
     #[test]
     fn chat_instruction_serializtion_oa() -> Result<(), ChatDeserializationError> {
         let chat_inst = ChatInstruction::OpenAccount {
             account_metadata: AccountMetadata {
                 initialized: 0,
+                bump: 255,
                 next_free_index: 20,
                 last_message_id: 3,
+                message_count: 4,
+                blocked_count: 0,
+                reaction_count: 0,
+                seed_len: 4,
                 account_name_len: 3,
+                owner: solana_program::pubkey::Pubkey::new_unique(),
+                created_at: 0,
                 account_name: "abc".to_string(),
+                seed: "chat".to_string(),
+                blocked_senders: vec![],
+                reactions: vec![],
             },
         };
 
@@ -415,6 +1697,7 @@ mod tests {
     }
 
     #[cfg(test)]
+    #[allow(clippy::module_inception)]
     mod tests {
         use std::str::FromStr;
 
@@ -436,7 +1719,7 @@ mod tests {
 
             let message = Message::new(
                 1,
-                Pubkey::from_str(&PROGRAM_ADDRESS.to_string()).unwrap(),
+                Pubkey::from_str(PROGRAM_ADDRESS).unwrap(),
                 "12345".to_string(),
             );
 
@@ -459,17 +1742,19 @@ mod tests {
 
             let msg1 = Message {
                 id: 1,
-                from: Pubkey::from_str(&PROGRAM_ADDRESS.to_string()).unwrap(),
+                from: Pubkey::from_str(PROGRAM_ADDRESS).unwrap(),
                 msg_size: 5,
                 msg: "12345".to_string(),
+                ..Message::default()
             };
 
             let msg2 = Message {
                 id: 2,
 
-                from: Pubkey::from_str(&PROGRAM_ADDRESS.to_string()).unwrap(),
+                from: Pubkey::from_str(PROGRAM_ADDRESS).unwrap(),
                 msg_size: 3,
                 msg: "abc".to_string(),
+                ..Message::default()
             };
 
             let size = msg1.size() + msg2.size();
@@ -487,10 +1772,20 @@ mod tests {
         fn acount_metadata_serialization() -> Result<(), ChatDeserializationError> {
             let account_metadata = AccountMetadata {
                 initialized: 1,
+                bump: 254,
                 next_free_index: 2,
                 last_message_id: 3,
+                message_count: 4,
+                blocked_count: 0,
+                reaction_count: 0,
+                seed_len: 4,
                 account_name_len: 3,
+                owner: Pubkey::new_unique(),
+                created_at: -1,
                 account_name: "abc".to_string(),
+                seed: "chat".to_string(),
+                blocked_senders: vec![],
+                reactions: vec![],
             };
 
             let size = account_metadata.size();
@@ -524,12 +1819,12 @@ mod tests {
         fn chat_instruction_serializtion_sm2() -> Result<(), ChatDeserializationError> {
             let message1 = Message::new(
                 0,
-                Pubkey::from_str(&PROGRAM_ADDRESS.to_string()).unwrap(),
+                Pubkey::from_str(PROGRAM_ADDRESS).unwrap(),
                 "message message".to_string(),
             );
             let message2 = Message::new(
                 1,
-                Pubkey::from_str(&PROGRAM_ADDRESS.to_string()).unwrap(),
+                Pubkey::from_str(PROGRAM_ADDRESS).unwrap(),
                 "message message 2".to_string(),
             );
 
@@ -562,5 +1857,36 @@ mod tests {
 
             Ok(())
         }
+
+        #[test]
+        fn chat_instruction_serializtion_dr() -> Result<(), ChatDeserializationError> {
+            let chat_inst = ChatInstruction::DeleteRange {
+                from_id: 3,
+                to_id: 7,
+            };
+
+            let mut data = vec![0; chat_inst.size()];
+            chat_inst.serialize(&mut data[..])?;
+
+            let chat_inst_new = ChatInstruction::deserialize(&data[..])?;
+
+            assert_eq!(chat_inst, chat_inst_new);
+
+            Ok(())
+        }
+
+        #[test]
+        fn chat_instruction_serializtion_da() -> Result<(), ChatDeserializationError> {
+            let chat_inst = ChatInstruction::DeleteAll;
+
+            let mut data = vec![0; chat_inst.size()];
+            chat_inst.serialize(&mut data[..])?;
+
+            let chat_inst_new = ChatInstruction::deserialize(&data[..])?;
+
+            assert_eq!(chat_inst, chat_inst_new);
+
+            Ok(())
+        }
     }
 }
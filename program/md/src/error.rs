@@ -0,0 +1,72 @@
+use std::fmt;
+
+use solana_program::program_error::ProgramError;
+
+use crate::data::MAX_MESSAGE_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChatError {
+    NameTooLong,
+    SenderBlocked,
+    BlockListFull,
+    RateLimited,
+    EmptyInstruction,
+    EmojiTooLong,
+    ReactionsFull,
+    MessageNotFound,
+    Unauthorized,
+    SeedTooLong,
+    InvalidLayout,
+    EditLengthMismatch,
+    DuplicateMessage,
+    MissingSender,
+    EmptyMessage,
+    MessageTooLong,
+    AccountAlreadyInitialized,
+    AccountFull,
+    NameRequired,
+    SenderMismatch,
+    IdTaken,
+}
+
+impl std::error::Error for ChatError {}
+
+impl fmt::Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChatError::NameTooLong => write!(f, "account name is too long"),
+            ChatError::SenderBlocked => write!(f, "sender is blocked by the account owner"),
+            ChatError::BlockListFull => write!(f, "block list is full"),
+            ChatError::RateLimited => write!(f, "sender is sending messages too fast"),
+            ChatError::EmptyInstruction => write!(f, "instruction data is empty"),
+            ChatError::EmojiTooLong => write!(f, "reaction emoji is empty or too long"),
+            ChatError::ReactionsFull => write!(f, "account has no room for more reactions"),
+            ChatError::MessageNotFound => write!(f, "message not found"),
+            ChatError::Unauthorized => write!(f, "signer doesn't own the chat account"),
+            ChatError::SeedTooLong => write!(f, "account seed is longer than 32 bytes"),
+            ChatError::InvalidLayout => write!(f, "messages would overlap the account metadata"),
+            ChatError::EditLengthMismatch => {
+                write!(f, "edited message can't be longer than the original")
+            }
+            ChatError::DuplicateMessage => {
+                write!(f, "a message with this content was already sent")
+            }
+            ChatError::MissingSender => write!(f, "message has no sender"),
+            ChatError::EmptyMessage => write!(f, "message body is empty"),
+            ChatError::AccountAlreadyInitialized => write!(f, "chat account is already open"),
+            ChatError::AccountFull => write!(f, "chat account has no room for these messages"),
+            ChatError::NameRequired => write!(f, "account name is empty"),
+            ChatError::SenderMismatch => write!(f, "message sender isn't the signer"),
+            ChatError::IdTaken => write!(f, "message id is already taken"),
+            ChatError::MessageTooLong => {
+                write!(f, "message body is longer than {} bytes", MAX_MESSAGE_LEN)
+            }
+        }
+    }
+}
+
+impl From<ChatError> for ProgramError {
+    fn from(e: ChatError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
@@ -1,14 +1,15 @@
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
-    entrypoint,
-    entrypoint::ProgramResult,
-    msg,
-    program_error::ProgramError,
-    pubkey::Pubkey,
+    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, msg, pubkey::Pubkey,
 };
 
 mod processor;
 
+// the instruction tags and limits the program enforces, for clients that link against it
+pub use md::data::{
+    ChatCommand, CHAT_ACCOUNT_SIZE, DEFAULT_SEED, MAX_ACCOUNT_NAME_LEN, MAX_BLOCKED_SENDERS,
+    MAX_EMOJI_LEN, MAX_REACTIONS,
+};
+
 // Declare and export the program's entrypoint
 entrypoint!(process_instruction);
 
@@ -20,12 +21,5 @@ pub fn process_instruction(
 ) -> ProgramResult {
     msg!("Chat program working entrypoint! ");
 
-    let acount_iterator = &mut accounts.iter();
-    let from_acc = next_account_info(acount_iterator)?;
-
-    if !from_acc.is_signer {
-        return Err(ProgramError::IllegalOwner);
-    }
-
     processor::process_instruction(program_id, accounts, instruction_data)
 }
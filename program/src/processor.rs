@@ -1,44 +1,463 @@
-use md::data::{
-    serialize_messages, AccountMetadata, ChatData, ChatDeserializationError, ChatInstruction,
-    Message,
+use md::{
+    data::{
+        deserialize_messages, serialize_messages, AccountMetadata, ChatData,
+        ChatDeserializationError, ChatInstruction, Message, Reaction, CHAT_ACCOUNT_SIZE,
+        MAX_ACCOUNT_NAME_LEN, MAX_BLOCKED_SENDERS, MAX_MESSAGE_LEN, MAX_REACTIONS,
+    },
+    error::ChatError,
 };
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
     entrypoint::ProgramResult,
     msg,
+    program::invoke_signed,
     program_error::ProgramError,
     pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
 };
+use std::collections::HashMap;
+
+const ASSIGNED_IDS_LOG: &str = "Assigned message ids";
+
+// minimum gap between two sends from the same sender, the account owner isn't limited
+const MIN_SEND_INTERVAL_SECS: i64 = 5;
+
+// the newest stored timestamp of every sender, read in one pass over the messages
+fn last_sent_times(
+    account_data: &[u8],
+    account_metadata: &AccountMetadata,
+) -> Result<HashMap<Pubkey, i64>, ProgramError> {
+    let messages_region =
+        &account_data[account_metadata.size()..account_metadata.next_free_index as usize];
+    let messages = deserialize_messages(messages_region)
+        .map_err(|_e| -> ProgramError { ProgramError::InvalidAccountData })?;
+    let mut last_sent = HashMap::new();
+    for message in messages {
+        let last = last_sent.entry(message.from).or_insert(message.timestamp);
+        *last = (*last).max(message.timestamp);
+    }
+    Ok(last_sent)
+}
+
+// anyone but the owner can only send as themselves, a blocked sender could otherwise write any
+// other pubkey into from; the owner stores others' messages when importing or restoring
+fn validate_senders(
+    signer: &Pubkey,
+    account_metadata: &AccountMetadata,
+    messages: &[Message],
+) -> ProgramResult {
+    if account_metadata.owner == *signer {
+        return Ok(());
+    }
+    if let Some(message) = messages.iter().find(|message| message.from != *signer) {
+        msg!("Message from {} wasn't signed by {}", message.from, signer);
+        return Err(ChatError::SenderMismatch.into());
+    }
+    Ok(())
+}
 
 fn receive_messages(
     account_data: &mut [u8],
     account_metadata: &mut AccountMetadata,
-    messages: &mut Vec<Message>,
-) -> Result<(), ChatDeserializationError> {
+    messages: &mut [Message],
+    now: i64,
+    rate_limited: bool,
+    content_ids: bool,
+) -> ProgramResult {
     if messages.is_empty() {
         return Ok(());
     }
 
-    let mut last_message_id = account_metadata.last_message_id;
-    for msg in messages.iter_mut() {
-        msg.id = last_message_id;
-        last_message_id += 1;
+    if let Some(message) = messages
+        .iter()
+        .find(|message| account_metadata.is_blocked(&message.from))
+    {
+        msg!("Sender: {} is blocked", message.from);
+        return Err(ChatError::SenderBlocked.into());
+    }
+
+    // one oversized message is reported on its own, so the client knows which one to drop
+    if let Some(index) = messages.iter().position(|message| {
+        message.msg_size as usize > MAX_MESSAGE_LEN || message.stored_size() > MAX_MESSAGE_LEN
+    }) {
+        msg!(
+            "Message {} of the batch is longer than {} bytes",
+            index,
+            MAX_MESSAGE_LEN
+        );
+        return Err(ChatError::MessageTooLong.into());
     }
 
     let messages_size: usize = messages.iter().map(|c| c.size()).sum();
     let start_index = account_metadata.next_free_index as usize;
+    if start_index + messages_size > account_data.len() {
+        msg!(
+            "Account has {} bytes free, the batch needs {}",
+            account_data.len().saturating_sub(start_index),
+            messages_size
+        );
+        return Err(ChatError::AccountFull.into());
+    }
+
+    if rate_limited {
+        // every message counts, a batch can't carry several from one sender past the limit
+        let mut last_sent = last_sent_times(account_data, account_metadata)?;
+        for message in messages.iter() {
+            if let Some(last) = last_sent.insert(message.from, now) {
+                if now - last < MIN_SEND_INTERVAL_SECS {
+                    msg!("Sender: {} is sending too fast", message.from);
+                    return Err(ChatError::RateLimited.into());
+                }
+            }
+        }
+    }
+
+    if content_ids {
+        // the same content sent twice, in one batch or across transactions, maps to one id
+        let mut ids = stored_message_ids(account_data, account_metadata)?;
+        for msg in messages.iter_mut() {
+            msg.id = msg.content_id();
+            if ids.contains(&msg.id) {
+                msg!("Message: {} was already sent", msg.id);
+                return Err(ChatError::DuplicateMessage.into());
+            }
+            ids.push(msg.id);
+            msg.timestamp = now;
+        }
+    } else {
+        // ids count up from 1 and are never reused, so they stay unique after deletes and
+        // the buffer order alone decides the display order. content ids live above
+        // CONTENT_ID_FLAG, but a buffer written before they did may still hold one in the way
+        let ids = stored_message_ids(account_data, account_metadata)?;
+        let first_id = account_metadata.last_message_id + 1;
+        for (id, msg) in (first_id..).zip(messages.iter_mut()) {
+            if id & Message::CONTENT_ID_FLAG != 0 || ids.contains(&id) {
+                msg!("Message id: {} is already taken", id);
+                return Err(ChatError::IdTaken.into());
+            }
+            msg.id = id;
+            msg.timestamp = now;
+        }
+    }
 
     serialize_messages(
         messages,
         &mut account_data[start_index..start_index + messages_size],
-    )?;
+    )
+    .map_err(|_e| -> ProgramError { ProgramError::InvalidInstructionData })?;
 
     account_metadata.next_free_index = (start_index + messages_size) as u32;
-    account_metadata.last_message_id = messages.last().unwrap().id;
-    account_metadata.serialize(&mut account_data[0..account_metadata.size()])
+    // content ids are scattered, the sequential counter only tracks SendMessages
+    if !content_ids {
+        account_metadata.last_message_id = messages.last().unwrap().id;
+    }
+    account_metadata.message_count += messages.len() as u32;
+    account_metadata
+        .serialize_counters(account_data)
+        .map_err(|_e| -> ProgramError { ProgramError::InvalidAccountData })
+}
+
+fn store_account_metadata(
+    account_data: &mut [u8],
+    account_metadata: &AccountMetadata,
+) -> ProgramResult {
+    account_metadata
+        .serialize(&mut account_data[0..account_metadata.size()])
+        .map_err(|_e| -> ProgramError { ProgramError::InvalidAccountData })
 }
 
-fn delete_messages(_id: u32) {}
+// the metadata grows and shrinks with its block list and reactions, the messages after it
+// are moved to the new end of the metadata before it is stored
+fn store_resized_metadata(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+    old_size: usize,
+) -> ProgramResult {
+    let new_size = account_metadata.size();
+    let end_index = account_metadata.next_free_index as usize;
+    if new_size > old_size {
+        let grow = new_size - old_size;
+        if end_index + grow > account_data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        account_data.copy_within(old_size..end_index, new_size);
+        account_metadata.next_free_index += grow as u32;
+    } else if new_size < old_size {
+        let shrink = old_size - new_size;
+        account_data.copy_within(old_size..end_index, new_size);
+        account_data[end_index - shrink..end_index].fill(0);
+        account_metadata.next_free_index -= shrink as u32;
+    }
+    store_account_metadata(account_data, account_metadata)
+}
+
+fn block_sender(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+    sender: &Pubkey,
+) -> ProgramResult {
+    if account_metadata.is_blocked(sender) {
+        return Ok(());
+    }
+    if account_metadata.blocked_senders.len() >= MAX_BLOCKED_SENDERS {
+        msg!("Block list already has {} senders", MAX_BLOCKED_SENDERS);
+        return Err(ChatError::BlockListFull.into());
+    }
+    let old_size = account_metadata.size();
+    account_metadata.blocked_senders.push(*sender);
+    account_metadata.blocked_count += 1;
+    store_resized_metadata(account_data, account_metadata, old_size)
+}
+
+fn unblock_sender(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+    sender: &Pubkey,
+) -> ProgramResult {
+    let position = match account_metadata
+        .blocked_senders
+        .iter()
+        .position(|blocked| blocked == sender)
+    {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+    let old_size = account_metadata.size();
+    account_metadata.blocked_senders.remove(position);
+    account_metadata.blocked_count -= 1;
+    store_resized_metadata(account_data, account_metadata, old_size)
+}
+
+fn stored_message_ids(
+    account_data: &[u8],
+    account_metadata: &AccountMetadata,
+) -> Result<Vec<u32>, ProgramError> {
+    let messages_region =
+        &account_data[account_metadata.size()..account_metadata.next_free_index as usize];
+    let messages = deserialize_messages(messages_region)
+        .map_err(|_e| -> ProgramError { ProgramError::InvalidAccountData })?;
+    Ok(messages.iter().map(|message| message.id).collect())
+}
+
+fn add_reaction(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+    reaction: Reaction,
+) -> ProgramResult {
+    if account_metadata.is_blocked(&reaction.from) {
+        msg!("Sender: {} is blocked", reaction.from);
+        return Err(ChatError::SenderBlocked.into());
+    }
+    if !stored_message_ids(account_data, account_metadata)?.contains(&reaction.target_id) {
+        msg!("Message: {} not found", reaction.target_id);
+        return Err(ChatError::MessageNotFound.into());
+    }
+    if account_metadata.reactions.len() >= MAX_REACTIONS {
+        msg!("Account already has {} reactions", MAX_REACTIONS);
+        return Err(ChatError::ReactionsFull.into());
+    }
+    let old_size = account_metadata.size();
+    account_metadata.reactions.push(reaction);
+    account_metadata.reaction_count += 1;
+    store_resized_metadata(account_data, account_metadata, old_size)
+}
+
+// reactions to deleted messages would only take up reaction slots
+fn drop_orphaned_reactions(account_data: &mut [u8]) -> ProgramResult {
+    let mut account_metadata = load_account_metadata(account_data)?;
+    let ids = stored_message_ids(account_data, &account_metadata)?;
+    let old_size = account_metadata.size();
+    account_metadata
+        .reactions
+        .retain(|reaction| ids.contains(&reaction.target_id));
+    if account_metadata.reactions.len() == account_metadata.reaction_count as usize {
+        return Ok(());
+    }
+    account_metadata.reaction_count = account_metadata.reactions.len() as u32;
+    store_resized_metadata(account_data, &mut account_metadata, old_size)
+}
+
+// only the stored owner may delete from the account or change its settings, that starts out as
+// the user the address is derived from but moves with TransferOwnership
+fn validate_owner(
+    owner: &AccountInfo,
+    chat_account: &AccountInfo,
+    account_metadata: &AccountMetadata,
+) -> ProgramResult {
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if account_metadata.owner != *owner.key {
+        msg!(
+            "Signer: {} doesn't own account: {}",
+            owner.key,
+            chat_account.key
+        );
+        return Err(ChatError::Unauthorized.into());
+    }
+    Ok(())
+}
+
+fn delete_messages(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+    id: u32,
+) -> Result<(), ChatDeserializationError> {
+    let end_index = account_metadata.next_free_index as usize;
+    let mut current_index = account_metadata.size();
+
+    while current_index < end_index {
+        let mut message = Message::default();
+        message.deserialize(&account_data[current_index..end_index])?;
+        let message_size = message.size();
+
+        if message.id == id {
+            // shift the rest of the messages over the deleted one
+            account_data.copy_within(current_index + message_size..end_index, current_index);
+            account_data[end_index - message_size..end_index].fill(0);
+
+            account_metadata.next_free_index -= message_size as u32;
+            account_metadata.message_count -= 1;
+            return account_metadata.serialize(&mut account_data[0..account_metadata.size()]);
+        }
+        current_index += message_size;
+    }
+
+    Err(ChatDeserializationError)
+}
+
+// rewrites the body where the message sits and pulls the rest of the buffer over the bytes
+// it no longer needs, a longer body would have to push every later message back
+fn edit_message(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+    editor: &Pubkey,
+    id: u32,
+    new_msg: &str,
+) -> ProgramResult {
+    let end_index = account_metadata.next_free_index as usize;
+    let mut current_index = account_metadata.size();
+
+    while current_index < end_index {
+        let mut message = Message::default();
+        message
+            .deserialize(&account_data[current_index..end_index])
+            .map_err(|_e| -> ProgramError { ProgramError::InvalidAccountData })?;
+        let old_size = message.size();
+        if message.id != id {
+            current_index += old_size;
+            continue;
+        }
+
+        if message.from != *editor {
+            msg!("Signer: {} didn't send message: {}", editor, id);
+            return Err(ChatError::Unauthorized.into());
+        }
+        message.msg = new_msg.to_string();
+        message.msg_size = new_msg.len() as u32;
+        message.compressed = 0;
+        message.compressed_msg = Vec::new();
+        let new_size = message.size();
+        if new_size > old_size {
+            msg!(
+                "Edit needs {} bytes but message: {} has {}",
+                new_size,
+                id,
+                old_size
+            );
+            return Err(ChatError::EditLengthMismatch.into());
+        }
+
+        message
+            .serialize(&mut account_data[current_index..current_index + new_size])
+            .map_err(|_e| -> ProgramError { ProgramError::InvalidAccountData })?;
+        let shrink = old_size - new_size;
+        account_data.copy_within(
+            current_index + old_size..end_index,
+            current_index + new_size,
+        );
+        account_data[end_index - shrink..end_index].fill(0);
+        account_metadata.next_free_index -= shrink as u32;
+        return account_metadata
+            .serialize_counters(account_data)
+            .map_err(|_e| -> ProgramError { ProgramError::InvalidAccountData });
+    }
+
+    msg!("Message: {} not found", id);
+    Err(ChatError::MessageNotFound.into())
+}
+
+fn delete_messages_range(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+    from_id: u32,
+    to_id: u32,
+) -> Result<(), ChatDeserializationError> {
+    delete_messages_where(account_data, account_metadata, |message| {
+        (from_id..=to_id).contains(&message.id)
+    })
+    .map(|_| ())
+}
+
+// expired messages are only hidden by readers until someone pays for this
+fn purge_expired_messages(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+    now: i64,
+) -> Result<u32, ChatDeserializationError> {
+    delete_messages_where(account_data, account_metadata, |message| {
+        message.is_expired(now)
+    })
+}
+
+// compacts the kept messages to the front in one pass, returns how many were dropped
+fn delete_messages_where(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+    delete: impl Fn(&Message) -> bool,
+) -> Result<u32, ChatDeserializationError> {
+    let end_index = account_metadata.next_free_index as usize;
+    let mut current_index = account_metadata.size();
+    let mut write_index = current_index;
+    let mut deleted = 0;
+
+    while current_index < end_index {
+        let mut message = Message::default();
+        message.deserialize(&account_data[current_index..end_index])?;
+        let message_size = message.size();
+
+        if delete(&message) {
+            deleted += 1;
+        } else {
+            // keep the message, moving it down over already deleted ones
+            account_data.copy_within(current_index..current_index + message_size, write_index);
+            write_index += message_size;
+        }
+        current_index += message_size;
+    }
+    account_data[write_index..end_index].fill(0);
+
+    account_metadata.next_free_index = write_index as u32;
+    account_metadata.message_count -= deleted;
+    account_metadata.serialize(&mut account_data[0..account_metadata.size()])?;
+    Ok(deleted)
+}
+
+fn delete_all_messages(
+    account_data: &mut [u8],
+    account_metadata: &mut AccountMetadata,
+) -> Result<(), ChatDeserializationError> {
+    let start_index = account_metadata.size();
+    account_data[start_index..account_metadata.next_free_index as usize].fill(0);
+
+    // last_message_id is kept so cleared ids aren't handed out again
+    account_metadata.next_free_index = start_index as u32;
+    account_metadata.message_count = 0;
+    account_metadata.serialize(&mut account_data[0..account_metadata.size()])
+}
 
 fn open_account(
     account_data: &mut [u8],
@@ -47,19 +466,97 @@ fn open_account(
     account_metadata.serialize(&mut account_data[0..account_metadata.size()])
 }
 
+// Chat accounts are PDAs derived from [seed, user, bump]. only the canonical bump
+// find_program_address settles on is accepted, any other valid bump would give the
+// same user and seed a second account
+fn is_chat_address(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    account_metadata: &AccountMetadata,
+    chat_account: &Pubkey,
+) -> bool {
+    let seeds: &[&[u8]] = &[account_metadata.seed.as_bytes(), user.as_ref()];
+    let (expected, bump) = Pubkey::find_program_address(seeds, program_id);
+    expected == *chat_account && bump == account_metadata.bump
+}
+
+fn validate_chat_address(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    account_metadata: &AccountMetadata,
+    chat_account: &Pubkey,
+) -> ProgramResult {
+    if !is_chat_address(program_id, user, account_metadata, chat_account) {
+        msg!(
+            "Account: {} is not a chat account of {}",
+            chat_account,
+            user
+        );
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(())
+}
+
+fn create_chat_account<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    chat_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    account_metadata: &AccountMetadata,
+) -> ProgramResult {
+    let lamports = Rent::get()?.minimum_balance(CHAT_ACCOUNT_SIZE);
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            chat_account.key,
+            lamports,
+            CHAT_ACCOUNT_SIZE as u64,
+            program_id,
+        ),
+        &[payer.clone(), chat_account.clone(), system_program.clone()],
+        &[&[
+            account_metadata.seed.as_bytes(),
+            payer.key.as_ref(),
+            &[account_metadata.bump],
+        ]],
+    )
+}
+
+// hands every lamport to the owner, an account left with none is dropped by the runtime
+fn close_account(owner: &AccountInfo, chat_account: &AccountInfo) -> ProgramResult {
+    chat_account.try_borrow_mut_data()?.fill(0);
+    let lamports = chat_account.lamports();
+    **owner.try_borrow_mut_lamports()? += lamports;
+    **chat_account.try_borrow_mut_lamports()? = 0;
+    Ok(())
+}
+
+fn load_account_metadata(account_data: &[u8]) -> Result<AccountMetadata, ProgramError> {
+    let mut acc_metadata = AccountMetadata::default();
+    if acc_metadata.deserialize(account_data).is_err() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(acc_metadata)
+}
+
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
     let acount_iterator = &mut accounts.iter();
-    let _from_user = next_account_info(acount_iterator)?;
+    let from_user = next_account_info(acount_iterator)?;
     let to_acc = next_account_info(acount_iterator)?;
 
-    let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
-    let mut acc_metadata = AccountMetadata::default();
-    if acc_metadata.deserialize(to_acc_data).is_err() {
-        return ProgramResult::Err(ProgramError::InvalidInstructionData);
+    // every instruction pays for or acts as the first account, OpenAccount included
+    if !from_user.is_signer {
+        msg!("Account: {} didn't sign the transaction", from_user.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if instruction_data.is_empty() {
+        msg!("Empty instruction data, expected a ChatInstruction tag");
+        return Err(ChatError::EmptyInstruction.into());
     }
 
     let chat_instruction = &mut ChatInstruction::deserialize(instruction_data)
@@ -68,27 +565,1341 @@ pub fn process_instruction(
     match chat_instruction {
         ChatInstruction::SendMessages { messages } => {
             msg!("SendMessages");
-            if receive_messages(to_acc_data, &mut acc_metadata, messages).is_err() {
-                return ProgramResult::Err(ProgramError::InvalidInstructionData);
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            validate_senders(from_user.key, &acc_metadata, messages)?;
+            let now = Clock::get()?.unix_timestamp;
+            let owner_sending = acc_metadata.owner == *from_user.key;
+            receive_messages(
+                to_acc_data,
+                &mut acc_metadata,
+                messages,
+                now,
+                !owner_sending,
+                false,
+            )?;
+            // clients read this back from the transaction logs to learn the ids they got
+            if let (Some(first), Some(last)) = (messages.first(), messages.last()) {
+                msg!("{} {}..={}", ASSIGNED_IDS_LOG, first.id, last.id);
             }
             ProgramResult::Ok(())
         }
+        ChatInstruction::SendUniqueMessages { messages } => {
+            msg!("SendUniqueMessages");
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            validate_senders(from_user.key, &acc_metadata, messages)?;
+            let now = Clock::get()?.unix_timestamp;
+            let owner_sending = acc_metadata.owner == *from_user.key;
+            receive_messages(
+                to_acc_data,
+                &mut acc_metadata,
+                messages,
+                now,
+                !owner_sending,
+                true,
+            )
+        }
         ChatInstruction::DeleteMessages { id } => {
             msg!("DeleteMessages");
-            delete_messages(*id);
-            ProgramResult::Ok(())
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            validate_owner(from_user, to_acc, &acc_metadata)?;
+            if delete_messages(to_acc_data, &mut acc_metadata, *id).is_err() {
+                msg!("Message: {} not found", id);
+                return ProgramResult::Err(ProgramError::InvalidInstructionData);
+            }
+            drop_orphaned_reactions(to_acc_data)
+        }
+        ChatInstruction::DeleteRange { from_id, to_id } => {
+            msg!("DeleteRange");
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            validate_owner(from_user, to_acc, &acc_metadata)?;
+            if delete_messages_range(to_acc_data, &mut acc_metadata, *from_id, *to_id).is_err() {
+                return ProgramResult::Err(ProgramError::InvalidInstructionData);
+            }
+            drop_orphaned_reactions(to_acc_data)
+        }
+        ChatInstruction::PurgeExpired => {
+            msg!("PurgeExpired");
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            validate_owner(from_user, to_acc, &acc_metadata)?;
+            let now = Clock::get()?.unix_timestamp;
+            match purge_expired_messages(to_acc_data, &mut acc_metadata, now) {
+                Ok(purged) => msg!("Purged {} expired messages", purged),
+                Err(_e) => return ProgramResult::Err(ProgramError::InvalidInstructionData),
+            }
+            drop_orphaned_reactions(to_acc_data)
+        }
+        ChatInstruction::DeleteAll => {
+            msg!("DeleteAll");
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            validate_owner(from_user, to_acc, &acc_metadata)?;
+            if delete_all_messages(to_acc_data, &mut acc_metadata).is_err() {
+                return ProgramResult::Err(ProgramError::InvalidInstructionData);
+            }
+            drop_orphaned_reactions(to_acc_data)
         }
         ChatInstruction::OpenAccount { account_metadata } => {
             msg!("OpenAccount");
-            if acc_metadata.initialized > 0 {
-                msg!("Account: {} already exist", account_metadata.account_name);
+            if account_metadata.account_name_len == 0 {
+                msg!("Account name is empty");
+                return ProgramResult::Err(ChatError::NameRequired.into());
+            }
+            if account_metadata.account_name_len as usize > MAX_ACCOUNT_NAME_LEN {
+                msg!("Account name is longer than {} bytes", MAX_ACCOUNT_NAME_LEN);
+                return ProgramResult::Err(ChatError::NameTooLong.into());
+            }
+            // a new account holds no messages, they start right after the metadata
+            if account_metadata.next_free_index as usize != account_metadata.size() {
+                msg!(
+                    "next_free_index: {} must point right past the {} byte metadata",
+                    account_metadata.next_free_index,
+                    account_metadata.size()
+                );
+                return ProgramResult::Err(ChatError::InvalidLayout.into());
+            }
+            // the flag is the program's to set, a client setting it is sending a stored layout
+            if account_metadata.initialized > 0 {
+                msg!("OpenAccount metadata must be sent with initialized = 0");
                 return ProgramResult::Err(ProgramError::InvalidInstructionData);
             }
+            // counters and lists would describe messages, blocks and reactions that aren't there
+            if account_metadata.last_message_id != 0
+                || account_metadata.message_count != 0
+                || account_metadata.blocked_count != 0
+                || !account_metadata.blocked_senders.is_empty()
+                || account_metadata.reaction_count != 0
+                || !account_metadata.reactions.is_empty()
+            {
+                msg!("OpenAccount metadata must be sent with empty counters and lists");
+                return ProgramResult::Err(ProgramError::InvalidInstructionData);
+            }
+            validate_chat_address(program_id, from_user.key, account_metadata, to_acc.key)?;
+            account_metadata.owner = *from_user.key;
+            account_metadata.initialized = 1;
+            account_metadata.created_at = Clock::get()?.unix_timestamp;
+            if to_acc.data_is_empty() {
+                let system_program = next_account_info(acount_iterator)?;
+                create_chat_account(
+                    program_id,
+                    from_user,
+                    to_acc,
+                    system_program,
+                    account_metadata,
+                )?;
+            }
+
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let acc_metadata = load_account_metadata(to_acc_data)?;
+            if acc_metadata.initialized > 0 {
+                msg!("Account: {} already exist", acc_metadata.account_name);
+                return ProgramResult::Err(ChatError::AccountAlreadyInitialized.into());
+            }
             msg!("Opening account: {}", account_metadata.account_name);
             if let Err(_e) = open_account(to_acc_data, account_metadata) {
                 return ProgramResult::Err(ProgramError::InvalidInstructionData);
             }
             Ok(())
         }
+        ChatInstruction::CloseAccount => {
+            msg!("CloseAccount");
+            if to_acc.owner != program_id {
+                return ProgramResult::Err(ProgramError::IncorrectProgramId);
+            }
+            let acc_metadata = load_account_metadata(&to_acc.try_borrow_data()?)?;
+            validate_owner(from_user, to_acc, &acc_metadata)?;
+            msg!("Closing account: {}", acc_metadata.account_name);
+            close_account(from_user, to_acc)
+        }
+        ChatInstruction::BlockSender { pubkey } => {
+            msg!("BlockSender");
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            validate_owner(from_user, to_acc, &acc_metadata)?;
+            block_sender(to_acc_data, &mut acc_metadata, pubkey)
+        }
+        ChatInstruction::UnblockSender { pubkey } => {
+            msg!("UnblockSender");
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            validate_owner(from_user, to_acc, &acc_metadata)?;
+            unblock_sender(to_acc_data, &mut acc_metadata, pubkey)
+        }
+        ChatInstruction::React { target_id, emoji } => {
+            msg!("React");
+            // the reaction is attributed to the signer, so it can't be made in someone's name
+            let reaction = Reaction::new(*target_id, *from_user.key, emoji)?;
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            add_reaction(to_acc_data, &mut acc_metadata, reaction)
+        }
+        ChatInstruction::EditMessage { id, new_msg } => {
+            msg!("EditMessage");
+            // only the sender may change what they wrote, edit_message checks it against the signer
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            edit_message(to_acc_data, &mut acc_metadata, from_user.key, *id, new_msg)
+        }
+        ChatInstruction::TransferOwnership { new_owner } => {
+            msg!("TransferOwnership");
+            let to_acc_data = &mut *to_acc.try_borrow_mut_data()?;
+            let mut acc_metadata = load_account_metadata(to_acc_data)?;
+            validate_owner(from_user, to_acc, &acc_metadata)?;
+            msg!("New owner: {}", new_owner);
+            acc_metadata.owner = *new_owner;
+            store_account_metadata(to_acc_data, &acc_metadata)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use md::{
+        data::{
+            deserialize_account_data, AccountMetadata, ChatData, ChatInstruction, Message,
+            Reaction, CHAT_ACCOUNT_SIZE, DEFAULT_SEED, MAX_ACCOUNT_NAME_LEN, MAX_MESSAGE_LEN,
+        },
+        error::ChatError,
+    };
+    use solana_program::{
+        account_info::AccountInfo, clock::Clock, entrypoint::SUCCESS, program_error::ProgramError,
+        program_stubs::SyscallStubs, pubkey::Pubkey,
+    };
+    use std::sync::Once;
+
+    use super::{
+        delete_all_messages, delete_messages, delete_messages_range, process_instruction,
+        receive_messages, MIN_SEND_INTERVAL_SECS,
+    };
+
+    // the clock every process_instruction call sees in these tests
+    const TEST_UNIX_TIMESTAMP: i64 = 1_650_000_000;
+
+    struct TestSyscalls;
+
+    impl SyscallStubs for TestSyscalls {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                unix_timestamp: TEST_UNIX_TIMESTAMP,
+                ..Clock::default()
+            };
+            unsafe { *(var_addr as *mut Clock) = clock };
+            SUCCESS
+        }
+    }
+
+    fn install_test_syscalls() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            solana_program::program_stubs::set_syscall_stubs(Box::new(TestSyscalls));
+        });
+    }
+
+    // metadata as OpenAccount leaves it for this user
+    fn owned_metadata(owner: &TestAccount, bump: u8) -> AccountMetadata {
+        let mut account_metadata = AccountMetadata::new("abc", bump).unwrap();
+        account_metadata.owner = owner.key;
+        account_metadata
+    }
+
+    fn opened_account_data(account_metadata: &AccountMetadata) -> Vec<u8> {
+        let mut data = vec![0; CHAT_ACCOUNT_SIZE];
+        account_metadata
+            .serialize(&mut data[0..account_metadata.size()])
+            .unwrap();
+        data
+    }
+
+    fn open_account_data(bump: u8) -> Vec<u8> {
+        open_account_data_with_metadata(AccountMetadata::new("abc", bump).unwrap())
+    }
+
+    // metadata as the client sends it, the program sets initialized itself
+    fn open_account_data_with_metadata(mut account_metadata: AccountMetadata) -> Vec<u8> {
+        account_metadata.initialized = 0;
+        let chat_inst = ChatInstruction::OpenAccount { account_metadata };
+        let mut data = vec![0; chat_inst.size()];
+        chat_inst.serialize(&mut data[..]).unwrap();
+        data
+    }
+
+    struct TestAccount {
+        key: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+        owner: Pubkey,
+    }
+
+    impl TestAccount {
+        fn new(key: Pubkey, data_len: usize, owner: Pubkey) -> Self {
+            TestAccount {
+                key,
+                lamports: 0,
+                data: vec![0; data_len],
+                owner,
+            }
+        }
+
+        fn info(&mut self, is_signer: bool) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                is_signer,
+                true,
+                &mut self.lamports,
+                &mut self.data,
+                &self.owner,
+                false,
+                0,
+            )
+        }
+    }
+
+    fn chat_accounts(program_id: &Pubkey) -> (TestAccount, TestAccount, u8) {
+        let user = Pubkey::new_unique();
+        let (chat_pk, bump) =
+            Pubkey::find_program_address(&[DEFAULT_SEED.as_bytes(), user.as_ref()], program_id);
+        (
+            TestAccount::new(user, 0, Pubkey::default()),
+            TestAccount::new(chat_pk, CHAT_ACCOUNT_SIZE, *program_id),
+            bump,
+        )
+    }
+
+    #[test]
+    fn open_account_with_canonical_bump() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let accounts = [user.info(true), chat.info(false)];
+
+        process_instruction(&program_id, &accounts, &open_account_data(bump)).unwrap();
+
+        let mut account_metadata = AccountMetadata::default();
+        account_metadata
+            .deserialize(&accounts[1].data.borrow())
+            .unwrap();
+        let mut expected = AccountMetadata::new("abc", bump).unwrap();
+        expected.owner = *accounts[0].key;
+        expected.created_at = TEST_UNIX_TIMESTAMP;
+        assert_eq!(account_metadata, expected);
+    }
+
+    #[test]
+    fn welcome_message_right_after_open() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let welcome = Message::new(0, user.key, "hello everyone".to_string());
+        let send = instruction_data(ChatInstruction::SendMessages {
+            messages: vec![welcome],
+        });
+        let accounts = [user.info(true), chat.info(false)];
+
+        // the instructions of one transaction run in order over the same accounts
+        process_instruction(&program_id, &accounts, &open_account_data(bump)).unwrap();
+        process_instruction(&program_id, &accounts, &send).unwrap();
+
+        let (_, messages) = deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        let messages = messages.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id, 1);
+        assert_eq!(messages[0].msg, "hello everyone");
+    }
+
+    #[test]
+    fn open_account_with_wrong_bump() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let accounts = [user.info(true), chat.info(false)];
+
+        let result = process_instruction(
+            &program_id,
+            &accounts,
+            &open_account_data(bump.wrapping_sub(1)),
+        );
+        assert_eq!(result, Err(ProgramError::InvalidSeeds));
+        assert!(accounts[1].data.borrow().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn open_account_with_non_canonical_bump() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        // the next bump down that still lands off the curve, valid but not canonical
+        let (other_pk, other_bump) = (0..bump)
+            .rev()
+            .find_map(|other_bump| {
+                let seeds: &[&[u8]] = &[DEFAULT_SEED.as_bytes(), user.key.as_ref(), &[other_bump]];
+                Pubkey::create_program_address(seeds, &program_id)
+                    .ok()
+                    .map(|pk| (pk, other_bump))
+            })
+            .unwrap();
+        chat.key = other_pk;
+        let accounts = [user.info(true), chat.info(false)];
+
+        let result = process_instruction(&program_id, &accounts, &open_account_data(other_bump));
+        assert_eq!(result, Err(ProgramError::InvalidSeeds));
+        assert!(accounts[1].data.borrow().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn open_account_twice() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let accounts = [user.info(true), chat.info(false)];
+        process_instruction(&program_id, &accounts, &open_account_data(bump)).unwrap();
+        let opened = accounts[1].data.borrow().to_vec();
+
+        let renamed = open_account_data_with_metadata(AccountMetadata::new("xyz", bump).unwrap());
+        let result = process_instruction(&program_id, &accounts, &renamed);
+        assert_eq!(result, Err(ChatError::AccountAlreadyInitialized.into()));
+        assert_eq!(*accounts[1].data.borrow(), opened);
+    }
+
+    #[test]
+    fn open_account_with_initialized_flag_set() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let accounts = [user.info(true), chat.info(false)];
+        let chat_inst = ChatInstruction::OpenAccount {
+            account_metadata: AccountMetadata::new("abc", bump).unwrap(),
+        };
+
+        let result = process_instruction(&program_id, &accounts, &instruction_data(chat_inst));
+        assert_eq!(result, Err(ProgramError::InvalidInstructionData));
+        assert!(accounts[1].data.borrow().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn open_account_with_custom_seed() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let mut user = TestAccount::new(Pubkey::new_unique(), 0, Pubkey::default());
+        let (work_pk, bump) =
+            Pubkey::find_program_address(&[b"work", user.key.as_ref()], &program_id);
+        let mut chat = TestAccount::new(work_pk, CHAT_ACCOUNT_SIZE, program_id);
+        let accounts = [user.info(true), chat.info(false)];
+
+        let default_seed = AccountMetadata::new("abc", bump).unwrap();
+        let result = process_instruction(
+            &program_id,
+            &accounts,
+            &open_account_data_with_metadata(default_seed),
+        );
+        assert_eq!(result, Err(ProgramError::InvalidSeeds));
+
+        let work = AccountMetadata::with_seed("abc", "work", bump).unwrap();
+        process_instruction(
+            &program_id,
+            &accounts,
+            &open_account_data_with_metadata(work),
+        )
+        .unwrap();
+        let (stored_metadata, _) = deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(stored_metadata.seed, "work");
+    }
+
+    #[test]
+    fn open_account_with_overlapping_layout() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let accounts = [user.info(true), chat.info(false)];
+
+        let mut account_metadata = AccountMetadata::new("abc", bump).unwrap();
+        account_metadata.next_free_index = account_metadata.size() as u32 - 1;
+        let result = process_instruction(
+            &program_id,
+            &accounts,
+            &open_account_data_with_metadata(account_metadata),
+        );
+        assert_eq!(result, Err(ChatError::InvalidLayout.into()));
+        assert!(accounts[1].data.borrow().iter().all(|b| *b == 0));
+
+        // pointing past the metadata would leave garbage to read as messages
+        let metadata_size = AccountMetadata::new("abc", bump).unwrap().size() as u32;
+        for next_free_index in [metadata_size + 1, CHAT_ACCOUNT_SIZE as u32 + 1] {
+            let mut account_metadata = AccountMetadata::new("abc", bump).unwrap();
+            account_metadata.next_free_index = next_free_index;
+            let result = process_instruction(
+                &program_id,
+                &accounts,
+                &open_account_data_with_metadata(account_metadata),
+            );
+            assert_eq!(result, Err(ChatError::InvalidLayout.into()));
+        }
+        assert!(accounts[1].data.borrow().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn open_account_starts_empty() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let accounts = [user.info(true), chat.info(false)];
+        let nonempty: [fn(&mut AccountMetadata); 4] = [
+            |md| md.last_message_id = 3,
+            |md| md.message_count = 1,
+            |md| {
+                md.blocked_count = 1;
+                md.blocked_senders = vec![Pubkey::new_unique()];
+            },
+            |md| {
+                md.reaction_count = 1;
+                md.reactions = vec![Reaction::new(1, Pubkey::new_unique(), "👍").unwrap()];
+            },
+        ];
+
+        for set in nonempty {
+            let mut account_metadata = AccountMetadata::new("abc", bump).unwrap();
+            set(&mut account_metadata);
+            account_metadata.next_free_index = account_metadata.size() as u32;
+            let result = process_instruction(
+                &program_id,
+                &accounts,
+                &open_account_data_with_metadata(account_metadata),
+            );
+            assert_eq!(result, Err(ProgramError::InvalidInstructionData));
+        }
+        assert!(accounts[1].data.borrow().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn open_account_name_length_limit() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let accounts = [user.info(true), chat.info(false)];
+
+        let name = "a".repeat(MAX_ACCOUNT_NAME_LEN + 1);
+        let account_metadata = AccountMetadata {
+            initialized: 1,
+            bump,
+            next_free_index: 0,
+            last_message_id: 0,
+            message_count: 0,
+            blocked_count: 0,
+            reaction_count: 0,
+            seed_len: DEFAULT_SEED.len() as u32,
+            account_name_len: name.len() as u32,
+            owner: Pubkey::default(),
+            created_at: 0,
+            account_name: name,
+            seed: DEFAULT_SEED.to_string(),
+            blocked_senders: vec![],
+            reactions: vec![],
+        };
+        let result = process_instruction(
+            &program_id,
+            &accounts,
+            &open_account_data_with_metadata(account_metadata),
+        );
+        assert_eq!(result, Err(ChatError::NameTooLong.into()));
+
+        // built by hand, AccountMetadata::new refuses an empty name
+        let mut account_metadata = AccountMetadata::new("a", bump).unwrap();
+        account_metadata.account_name = String::new();
+        account_metadata.account_name_len = 0;
+        account_metadata.next_free_index = account_metadata.size() as u32;
+        let result = process_instruction(
+            &program_id,
+            &accounts,
+            &open_account_data_with_metadata(account_metadata),
+        );
+        assert_eq!(result, Err(ChatError::NameRequired.into()));
+
+        let name = "a".repeat(MAX_ACCOUNT_NAME_LEN);
+        let account_metadata = AccountMetadata::new(&name, bump).unwrap();
+        process_instruction(
+            &program_id,
+            &accounts,
+            &open_account_data_with_metadata(account_metadata),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn message_count_after_sends_and_delete() {
+        let from = Pubkey::new_unique();
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let mut data = opened_account_data(&account_metadata);
+
+        let mut first = vec![
+            Message::new(0, from, "one".to_string()),
+            Message::new(0, from, "two".to_string()),
+        ];
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut first,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        let mut second = vec![Message::new(0, from, "three".to_string())];
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut second,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(account_metadata.message_count, 3);
+
+        delete_messages(&mut data, &mut account_metadata, 2).unwrap();
+
+        let (stored_metadata, messages) = deserialize_account_data(&data).unwrap();
+        let messages = messages.unwrap();
+        assert_eq!(stored_metadata.message_count, 2);
+        assert_eq!(stored_metadata.message_count as usize, messages.len());
+        assert_eq!(messages[0].msg, "one");
+        assert_eq!(messages[1].msg, "three");
+    }
+
+    #[test]
+    fn append_only_writes_counters() {
+        let from = Pubkey::new_unique();
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let mut data = opened_account_data(&account_metadata);
+        let metadata_size = account_metadata.size();
+        let name_start = AccountMetadata::ACCOUNT_METADATA_BASE_SIZE;
+        // a name that differs from the in-memory copy would be restored by a full serialize
+        data[name_start..name_start + 3].copy_from_slice(b"xyz");
+
+        let mut messages = vec![Message::new(0, from, "one".to_string())];
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut messages,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        let (stored_metadata, messages) = deserialize_account_data(&data).unwrap();
+        assert_eq!(stored_metadata.account_name, "xyz");
+        assert_eq!(stored_metadata.last_message_id, 1);
+        assert_eq!(stored_metadata.message_count, 1);
+        assert_eq!(
+            stored_metadata.next_free_index as usize,
+            metadata_size + messages.unwrap()[0].size()
+        );
+    }
+
+    #[test]
+    fn fresh_id_after_deleting_middle_message() {
+        let from = Pubkey::new_unique();
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let mut data = opened_account_data(&account_metadata);
+
+        let mut first = ["one", "two", "three"]
+            .iter()
+            .map(|m| Message::new(0, from, m.to_string()))
+            .collect::<Vec<_>>();
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut first,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        delete_messages(&mut data, &mut account_metadata, 2).unwrap();
+        let mut second = vec![Message::new(0, from, "four".to_string())];
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut second,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        let (_, messages) = deserialize_account_data(&data).unwrap();
+        let messages = messages.unwrap();
+        assert_eq!(
+            messages.iter().map(|m| m.id).collect::<Vec<_>>(),
+            vec![1, 3, 4]
+        );
+        assert_eq!(
+            messages.iter().map(|m| m.msg.as_str()).collect::<Vec<_>>(),
+            vec!["one", "three", "four"]
+        );
+    }
+
+    #[test]
+    fn delete_missing_message() {
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let mut data = opened_account_data(&account_metadata);
+
+        assert!(delete_messages(&mut data, &mut account_metadata, 7).is_err());
+        assert_eq!(account_metadata.message_count, 0);
+    }
+
+    #[test]
+    fn delete_inclusive_range() {
+        let from = Pubkey::new_unique();
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let mut data = opened_account_data(&account_metadata);
+
+        let mut messages = ["one", "two", "three", "four", "five"]
+            .iter()
+            .map(|m| Message::new(0, from, m.to_string()))
+            .collect::<Vec<_>>();
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut messages,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        delete_messages_range(&mut data, &mut account_metadata, 2, 4).unwrap();
+
+        let (stored_metadata, messages) = deserialize_account_data(&data).unwrap();
+        let messages = messages.unwrap();
+        assert_eq!(stored_metadata.message_count, 2);
+        assert_eq!(
+            messages.iter().map(|m| m.id).collect::<Vec<_>>(),
+            vec![1, 5]
+        );
+        assert_eq!(messages[1].msg, "five");
+        assert!(data[stored_metadata.next_free_index as usize..]
+            .iter()
+            .all(|b| *b == 0));
+    }
+
+    #[test]
+    fn delete_all() {
+        let from = Pubkey::new_unique();
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let mut data = opened_account_data(&account_metadata);
+
+        let mut messages = vec![
+            Message::new(0, from, "one".to_string()),
+            Message::new(0, from, "two".to_string()),
+        ];
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut messages,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        delete_all_messages(&mut data, &mut account_metadata).unwrap();
+
+        let (stored_metadata, messages) = deserialize_account_data(&data).unwrap();
+        assert_eq!(stored_metadata.message_count, 0);
+        assert_eq!(
+            stored_metadata.next_free_index as usize,
+            stored_metadata.size()
+        );
+        assert!(messages.is_none());
+        assert!(data[stored_metadata.size()..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn close_account_returns_lamports() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        user.lamports = 10;
+        chat.lamports = 1_000;
+        chat.data = opened_account_data(&owned_metadata(&user, bump));
+        let accounts = [user.info(true), chat.info(false)];
+
+        let chat_inst = ChatInstruction::CloseAccount;
+        let mut data = vec![0; chat_inst.size()];
+        chat_inst.serialize(&mut data[..]).unwrap();
+        process_instruction(&program_id, &accounts, &data).unwrap();
+
+        assert_eq!(accounts[0].lamports(), 1_010);
+        assert_eq!(accounts[1].lamports(), 0);
+        assert!(accounts[1].data.borrow().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn close_account_only_by_owner() {
+        let program_id = Pubkey::new_unique();
+        let (_, mut chat, bump) = chat_accounts(&program_id);
+        chat.lamports = 1_000;
+        chat.data = opened_account_data(&AccountMetadata::new("abc", bump).unwrap());
+        let mut stranger = TestAccount::new(Pubkey::new_unique(), 0, Pubkey::default());
+        let accounts = [stranger.info(true), chat.info(false)];
+
+        let chat_inst = ChatInstruction::CloseAccount;
+        let mut data = vec![0; chat_inst.size()];
+        chat_inst.serialize(&mut data[..]).unwrap();
+        let result = process_instruction(&program_id, &accounts, &data);
+
+        assert_eq!(result, Err(ChatError::Unauthorized.into()));
+        assert_eq!(accounts[1].lamports(), 1_000);
+        drop(accounts);
+
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        chat.data = opened_account_data(&AccountMetadata::new("abc", bump).unwrap());
+        let accounts = [user.info(false), chat.info(false)];
+        let result = process_instruction(&program_id, &accounts, &data);
+        assert_eq!(result, Err(ProgramError::MissingRequiredSignature));
+    }
+
+    fn instruction_data(chat_inst: ChatInstruction) -> Vec<u8> {
+        let mut data = vec![0; chat_inst.size()];
+        chat_inst.serialize(&mut data[..]).unwrap();
+        data
+    }
+
+    #[test]
+    fn blocked_sender_is_rejected() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let blocked = Pubkey::new_unique();
+        let friend = Pubkey::new_unique();
+        let mut account_metadata = owned_metadata(&user, bump);
+        chat.data = opened_account_data(&account_metadata);
+        let mut messages = vec![Message::new(0, friend, "before".to_string())];
+        receive_messages(
+            &mut chat.data,
+            &mut account_metadata,
+            &mut messages,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        let accounts = [user.info(true), chat.info(false)];
+
+        let block = instruction_data(ChatInstruction::BlockSender { pubkey: blocked });
+        process_instruction(&program_id, &accounts, &block).unwrap();
+
+        let send = |from: Pubkey| {
+            instruction_data(ChatInstruction::SendMessages {
+                messages: vec![Message::new(0, from, "hi".to_string())],
+            })
+        };
+        let result = process_instruction(&program_id, &accounts, &send(blocked));
+        assert_eq!(result, Err(ChatError::SenderBlocked.into()));
+        process_instruction(&program_id, &accounts, &send(friend)).unwrap();
+
+        let (stored_metadata, messages) =
+            deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(stored_metadata.blocked_senders, vec![blocked]);
+        let messages = messages.unwrap();
+        assert_eq!(
+            messages.iter().map(|m| m.msg.as_str()).collect::<Vec<_>>(),
+            vec!["before", "hi"]
+        );
+
+        let unblock = instruction_data(ChatInstruction::UnblockSender { pubkey: blocked });
+        process_instruction(&program_id, &accounts, &unblock).unwrap();
+        process_instruction(&program_id, &accounts, &send(blocked)).unwrap();
+
+        let (stored_metadata, messages) =
+            deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        assert!(stored_metadata.blocked_senders.is_empty());
+        assert_eq!(messages.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn blocked_signer_cant_spoof_the_sender() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (user, mut chat, bump) = chat_accounts(&program_id);
+        let mut blocked = TestAccount::new(Pubkey::new_unique(), 0, Pubkey::default());
+        let mut account_metadata = owned_metadata(&user, bump);
+        account_metadata.blocked_senders.push(blocked.key);
+        account_metadata.blocked_count = 1;
+        chat.data = opened_account_data(&account_metadata);
+        let stored = chat.data.clone();
+        let accounts = [blocked.info(true), chat.info(false)];
+
+        let send = |from: Pubkey| {
+            instruction_data(ChatInstruction::SendMessages {
+                messages: vec![Message::new(0, from, "hi".to_string())],
+            })
+        };
+        let result = process_instruction(&program_id, &accounts, &send(Pubkey::new_unique()));
+        assert_eq!(result, Err(ChatError::SenderMismatch.into()));
+        let unique = instruction_data(ChatInstruction::SendUniqueMessages {
+            messages: vec![Message::new(0, user.key, "hi".to_string())],
+        });
+        let result = process_instruction(&program_id, &accounts, &unique);
+        assert_eq!(result, Err(ChatError::SenderMismatch.into()));
+        let result = process_instruction(&program_id, &accounts, &send(*accounts[0].key));
+        assert_eq!(result, Err(ChatError::SenderBlocked.into()));
+        assert_eq!(*accounts[1].data.borrow(), &stored[..]);
+    }
+
+    #[test]
+    fn oversized_message_or_batch_is_rejected() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        chat.data = opened_account_data(&owned_metadata(&user, bump));
+        let accounts = [user.info(true), chat.info(false)];
+        let send = |bodies: &[usize]| {
+            instruction_data(ChatInstruction::SendMessages {
+                messages: bodies
+                    .iter()
+                    .map(|len| Message::new(0, *accounts[0].key, "a".repeat(*len)))
+                    .collect(),
+            })
+        };
+
+        // the long one is rejected on its own although the batch would fit
+        let result =
+            process_instruction(&program_id, &accounts, &send(&[2, MAX_MESSAGE_LEN + 1, 2]));
+        assert_eq!(result, Err(ChatError::MessageTooLong.into()));
+
+        // every message is within the limit, together they don't fit in the account
+        let result = process_instruction(&program_id, &accounts, &send(&[MAX_MESSAGE_LEN; 5]));
+        assert_eq!(result, Err(ChatError::AccountFull.into()));
+
+        let (_, messages) = deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        assert!(messages.is_none());
+        process_instruction(&program_id, &accounts, &send(&[MAX_MESSAGE_LEN; 4])).unwrap();
+    }
+
+    #[test]
+    fn block_sender_only_by_owner() {
+        let program_id = Pubkey::new_unique();
+        let (_, mut chat, bump) = chat_accounts(&program_id);
+        chat.data = opened_account_data(&AccountMetadata::new("abc", bump).unwrap());
+        let mut stranger = TestAccount::new(Pubkey::new_unique(), 0, Pubkey::default());
+        let accounts = [stranger.info(true), chat.info(false)];
+
+        let block = instruction_data(ChatInstruction::BlockSender {
+            pubkey: Pubkey::new_unique(),
+        });
+        let result = process_instruction(&program_id, &accounts, &block);
+        assert_eq!(result, Err(ChatError::Unauthorized.into()));
+    }
+
+    #[test]
+    fn sender_rate_limited() {
+        let from = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let mut account_metadata = AccountMetadata::new("abc", 255).unwrap();
+        let mut data = opened_account_data(&account_metadata);
+
+        let mut first = vec![Message::new(0, from, "one".to_string())];
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut first,
+            100,
+            true,
+            false,
+        )
+        .unwrap();
+
+        let mut too_soon = vec![Message::new(0, from, "two".to_string())];
+        let now = 100 + MIN_SEND_INTERVAL_SECS - 1;
+        let result = receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut too_soon,
+            now,
+            true,
+            false,
+        );
+        assert_eq!(result, Err(ChatError::RateLimited.into()));
+
+        // a second message from one sender in the same batch is as fast as they come
+        let mut batch = vec![
+            Message::new(0, other, "one".to_string()),
+            Message::new(0, other, "two".to_string()),
+        ];
+        let result = receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut batch,
+            now,
+            true,
+            false,
+        );
+        assert_eq!(result, Err(ChatError::RateLimited.into()));
+
+        // other senders and the owner aren't held back
+        let mut others = vec![Message::new(0, other, "hi".to_string())];
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut others,
+            now,
+            true,
+            false,
+        )
+        .unwrap();
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut too_soon,
+            now,
+            false,
+            false,
+        )
+        .unwrap();
+
+        let mut later = vec![Message::new(0, from, "three".to_string())];
+        let now = now + MIN_SEND_INTERVAL_SECS;
+        receive_messages(
+            &mut data,
+            &mut account_metadata,
+            &mut later,
+            now,
+            true,
+            false,
+        )
+        .unwrap();
+
+        let (_, messages) = deserialize_account_data(&data).unwrap();
+        let messages = messages.unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].timestamp, 100);
+        assert_eq!(messages[3].timestamp, now);
+    }
+
+    #[test]
+    fn delete_only_by_owner() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let mut account_metadata = owned_metadata(&user, bump);
+        chat.data = opened_account_data(&account_metadata);
+        let mut messages = vec![Message::new(0, Pubkey::new_unique(), "one".to_string())];
+        receive_messages(
+            &mut chat.data,
+            &mut account_metadata,
+            &mut messages,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        let mut stranger = TestAccount::new(Pubkey::new_unique(), 0, Pubkey::default());
+
+        let deletes = [
+            ChatInstruction::DeleteMessages { id: 1 },
+            ChatInstruction::DeleteRange {
+                from_id: 1,
+                to_id: 1,
+            },
+            ChatInstruction::DeleteAll,
+        ];
+        let accounts = [stranger.info(true), chat.info(false)];
+        for delete in deletes {
+            let result = process_instruction(&program_id, &accounts, &instruction_data(delete));
+            assert_eq!(result, Err(ChatError::Unauthorized.into()));
+        }
+        let (stored_metadata, _) = deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(stored_metadata.message_count, 1);
+
+        drop(accounts);
+        let accounts = [user.info(true), chat.info(false)];
+        let delete = instruction_data(ChatInstruction::DeleteMessages { id: 1 });
+        process_instruction(&program_id, &accounts, &delete).unwrap();
+    }
+
+    #[test]
+    fn purge_expired_drops_messages_past_the_clock() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let mut account_metadata = owned_metadata(&user, bump);
+        chat.data = opened_account_data(&account_metadata);
+        let from = Pubkey::new_unique();
+        let expiring = |msg: &str, expires_at: Option<i64>| Message {
+            expires_at,
+            ..Message::new(0, from, msg.to_string())
+        };
+        let mut messages = vec![
+            expiring("gone", Some(TEST_UNIX_TIMESTAMP - 1)),
+            expiring("kept", None),
+            expiring("now", Some(TEST_UNIX_TIMESTAMP)),
+            expiring("later", Some(TEST_UNIX_TIMESTAMP + 1)),
+        ];
+        receive_messages(
+            &mut chat.data,
+            &mut account_metadata,
+            &mut messages,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        let mut stranger = TestAccount::new(Pubkey::new_unique(), 0, Pubkey::default());
+        let purge = instruction_data(ChatInstruction::PurgeExpired);
+
+        let accounts = [stranger.info(true), chat.info(false)];
+        let result = process_instruction(&program_id, &accounts, &purge);
+        assert_eq!(result, Err(ChatError::Unauthorized.into()));
+        drop(accounts);
+
+        let accounts = [user.info(true), chat.info(false)];
+        process_instruction(&program_id, &accounts, &purge).unwrap();
+        let (stored_metadata, messages) =
+            deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        let messages = messages.unwrap();
+        let bodies: Vec<&str> = messages
+            .iter()
+            .map(|message| message.msg.as_str())
+            .collect();
+        assert_eq!(bodies, vec!["kept", "later"]);
+        assert_eq!(stored_metadata.message_count, 2);
+        assert_eq!(stored_metadata.last_message_id, 4);
+        assert!(
+            accounts[1].data.borrow()[stored_metadata.next_free_index as usize..]
+                .iter()
+                .all(|byte| *byte == 0)
+        );
+    }
+
+    #[test]
+    fn transfer_ownership_moves_owner_checks() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        chat.data = opened_account_data(&owned_metadata(&user, bump));
+        let mut new_owner = TestAccount::new(Pubkey::new_unique(), 0, Pubkey::default());
+        let transfer = instruction_data(ChatInstruction::TransferOwnership {
+            new_owner: new_owner.key,
+        });
+        let clear = instruction_data(ChatInstruction::DeleteAll);
+
+        let accounts = [new_owner.info(true), chat.info(false)];
+        let result = process_instruction(&program_id, &accounts, &transfer);
+        assert_eq!(result, Err(ChatError::Unauthorized.into()));
+        drop(accounts);
+
+        let accounts = [user.info(true), chat.info(false)];
+        process_instruction(&program_id, &accounts, &transfer).unwrap();
+        let (stored_metadata, _) = deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        assert_eq!(stored_metadata.owner, new_owner.key);
+        // the user the address is derived from lost control of it
+        let result = process_instruction(&program_id, &accounts, &clear);
+        assert_eq!(result, Err(ChatError::Unauthorized.into()));
+        drop(accounts);
+
+        let accounts = [new_owner.info(true), chat.info(false)];
+        process_instruction(&program_id, &accounts, &clear).unwrap();
+    }
+
+    #[test]
+    fn edit_in_place_or_reject_longer() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let mut sender = TestAccount::new(Pubkey::new_unique(), 0, Pubkey::default());
+        let mut account_metadata = owned_metadata(&user, bump);
+        chat.data = opened_account_data(&account_metadata);
+        let mut messages = vec![
+            Message::new(0, sender.key, "helo wrold".to_string()),
+            Message::new(0, sender.key, "after".to_string()),
+        ];
+        receive_messages(
+            &mut chat.data,
+            &mut account_metadata,
+            &mut messages,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        let edit = |id, new_msg: &str| {
+            instruction_data(ChatInstruction::EditMessage {
+                id,
+                new_msg: new_msg.to_string(),
+            })
+        };
+
+        let accounts = [sender.info(true), chat.info(false)];
+        process_instruction(&program_id, &accounts, &edit(1, "hello")).unwrap();
+        let (stored_metadata, stored_messages) =
+            deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        let stored_messages = stored_messages.unwrap();
+        assert_eq!(stored_messages[0].msg, "hello");
+        // the next message moved down over the freed bytes
+        assert_eq!(stored_messages[1].msg, "after");
+        assert_eq!(
+            stored_metadata.next_free_index as usize,
+            stored_metadata.size() + stored_messages[0].size() + stored_messages[1].size()
+        );
+
+        let result = process_instruction(&program_id, &accounts, &edit(1, "hello world"));
+        assert_eq!(result, Err(ChatError::EditLengthMismatch.into()));
+        let result = process_instruction(&program_id, &accounts, &edit(3, "x"));
+        assert_eq!(result, Err(ChatError::MessageNotFound.into()));
+        drop(accounts);
+
+        // not even the account owner may rewrite someone else's message
+        let accounts = [user.info(true), chat.info(false)];
+        let result = process_instruction(&program_id, &accounts, &edit(2, "x"));
+        assert_eq!(result, Err(ChatError::Unauthorized.into()));
+    }
+
+    #[test]
+    fn unique_send_rejects_repeated_content() {
+        install_test_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        chat.data = opened_account_data(&owned_metadata(&user, bump));
+        let owner = user.key;
+        let send = |timestamps: &[i64]| {
+            let messages = timestamps
+                .iter()
+                .map(|timestamp| {
+                    let mut message = Message::new(0, owner, "hi".to_string());
+                    message.timestamp = *timestamp;
+                    message
+                })
+                .collect();
+            instruction_data(ChatInstruction::SendUniqueMessages { messages })
+        };
+        let mut sent = Message::new(0, owner, "hi".to_string());
+        sent.timestamp = 42;
+
+        let accounts = [user.info(true), chat.info(false)];
+        process_instruction(&program_id, &accounts, &send(&[42])).unwrap();
+        let (stored_metadata, stored_messages) =
+            deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        let stored = &stored_messages.unwrap()[0];
+        assert_eq!(stored.id, sent.content_id());
+        assert!(stored.has_content_id());
+        assert_eq!(stored_metadata.last_message_id, 0);
+
+        let result = process_instruction(&program_id, &accounts, &send(&[42]));
+        assert_eq!(result, Err(ChatError::DuplicateMessage.into()));
+        let result = process_instruction(&program_id, &accounts, &send(&[43, 43]));
+        assert_eq!(result, Err(ChatError::DuplicateMessage.into()));
+        // a new timestamp makes the same text a different message
+        process_instruction(&program_id, &accounts, &send(&[43])).unwrap();
+    }
+
+    #[test]
+    fn sequential_id_rejects_taken_id() {
+        let program_id = Pubkey::new_unique();
+        let (user, mut chat, bump) = chat_accounts(&program_id);
+        let from = Pubkey::new_unique();
+        let mut account_metadata = owned_metadata(&user, bump);
+        chat.data = opened_account_data(&account_metadata);
+        let mut send = |account_metadata: &mut AccountMetadata| {
+            let mut messages = vec![Message::new(0, from, "hi".to_string())];
+            receive_messages(
+                &mut chat.data,
+                account_metadata,
+                &mut messages,
+                0,
+                false,
+                false,
+            )
+        };
+        send(&mut account_metadata).unwrap();
+        // id 1 now sits in the buffer the way a content id stored before CONTENT_ID_FLAG can
+        account_metadata.last_message_id = 0;
+
+        let result = send(&mut account_metadata);
+        assert_eq!(result, Err(ChatError::IdTaken.into()));
+        assert_eq!(account_metadata.message_count, 1);
+    }
+
+    #[test]
+    fn reaction_attaches_to_target() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let from = Pubkey::new_unique();
+        let mut account_metadata = owned_metadata(&user, bump);
+        chat.data = opened_account_data(&account_metadata);
+        let mut messages = vec![
+            Message::new(0, from, "one".to_string()),
+            Message::new(0, from, "two".to_string()),
+        ];
+        receive_messages(
+            &mut chat.data,
+            &mut account_metadata,
+            &mut messages,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        let mut reactor = TestAccount::new(Pubkey::new_unique(), 0, Pubkey::default());
+        let reactor_pk = reactor.key;
+        let accounts = [reactor.info(true), chat.info(false)];
+
+        let react = |target_id: u32| {
+            instruction_data(ChatInstruction::React {
+                target_id,
+                emoji: "👍".to_string(),
+            })
+        };
+        process_instruction(&program_id, &accounts, &react(2)).unwrap();
+        let result = process_instruction(&program_id, &accounts, &react(9));
+        assert_eq!(result, Err(ChatError::MessageNotFound.into()));
+
+        let (stored_metadata, messages) =
+            deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        let reactions = stored_metadata.reactions_to(2).collect::<Vec<_>>();
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].from, reactor_pk);
+        assert_eq!(reactions[0].emoji, "👍");
+        assert_eq!(stored_metadata.reactions_to(1).count(), 0);
+        assert_eq!(
+            messages
+                .unwrap()
+                .iter()
+                .map(|m| m.msg.as_str())
+                .collect::<Vec<_>>(),
+            vec!["one", "two"]
+        );
+
+        // deleting the target drops its reactions
+        drop(accounts);
+        let accounts = [user.info(true), chat.info(false)];
+        let delete = instruction_data(ChatInstruction::DeleteMessages { id: 2 });
+        process_instruction(&program_id, &accounts, &delete).unwrap();
+        let (stored_metadata, messages) =
+            deserialize_account_data(&accounts[1].data.borrow()).unwrap();
+        assert!(stored_metadata.reactions.is_empty());
+        assert_eq!(messages.unwrap()[0].msg, "one");
+    }
+
+    #[test]
+    fn empty_instruction_data() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, _) = chat_accounts(&program_id);
+        let accounts = [user.info(true), chat.info(false)];
+
+        let result = process_instruction(&program_id, &accounts, &[]);
+        assert_eq!(result, Err(ChatError::EmptyInstruction.into()));
+    }
+
+    #[test]
+    fn unsigned_instruction_is_rejected() {
+        let program_id = Pubkey::new_unique();
+        let (mut user, mut chat, bump) = chat_accounts(&program_id);
+        let accounts = [user.info(false), chat.info(false)];
+
+        let result = process_instruction(&program_id, &accounts, &open_account_data(bump));
+        assert_eq!(result, Err(ProgramError::MissingRequiredSignature));
+        assert!(accounts[1].data.borrow().iter().all(|b| *b == 0));
+
+        let send = instruction_data(ChatInstruction::SendMessages {
+            messages: vec![Message::new(0, *accounts[0].key, "hi".to_string())],
+        });
+        let result = process_instruction(&program_id, &accounts, &send);
+        assert_eq!(result, Err(ProgramError::MissingRequiredSignature));
     }
 }